@@ -0,0 +1,19 @@
+// ROMs shared by the benchmarks.
+
+// A tight loop of arithmetic, skips, a subroutine call and a BCD store: the
+// kind of code that spends its time in fetch and decode rather than drawing.
+pub const ARITHMETIC: [u8; 26] = [
+    0x60, 0x00, // 200: V0 = 0
+    0x61, 0x01, // 202: V1 = 1
+    0xA3, 0x00, // 204: I = 0x300
+    0x80, 0x14, // 206: V0 += V1
+    0x82, 0x00, // 208: V2 = V0
+    0x82, 0x26, // 20A: V2 >>= 1
+    0x30, 0x00, // 20C: skip if V0 == 0
+    0x22, 0x14, // 20E: call 0x214
+    0x72, 0x03, // 210: V2 += 3
+    0x12, 0x06, // 212: jump 0x206
+    0xF0, 0x33, // 214: BCD of V0 at I
+    0x33, 0x07, // 216: skip if V3 == 7
+    0x00, 0xEE, // 218: return
+];
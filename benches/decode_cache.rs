@@ -0,0 +1,91 @@
+// Instructions per second with and without the decoded instruction cache.
+// Run with `cargo bench --bench decode_cache`.
+
+use std::time::Instant;
+
+use chip8::bus::Bus;
+use chip8::cpu::{Cpu, Instruction};
+use chip8::mem::Memory;
+use chip8::system::System;
+
+mod common;
+
+use common::ARITHMETIC;
+
+const TICKS: u32 = 2_000_000;
+
+// Just memory, so the CPU's own fetch, decode and execute are measured.
+struct MemoryBus(Memory);
+
+impl Bus for MemoryBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0.read(addr)
+    }
+    fn write(&mut self, addr: u16, val: u8) {
+        self.0.set(addr as usize, val)
+    }
+    fn fetch(&mut self, addr: u16) -> u16 {
+        self.0.fetch(addr)
+    }
+    fn fetch_decoded(&mut self, addr: u16) -> Instruction {
+        self.0.fetch_decoded(addr)
+    }
+    fn clear_screen(&mut self) {}
+    fn draw_sprite(&mut self, _x: u8, _y: u8, _n: u16, _addr: u16) -> bool {
+        false
+    }
+    fn is_key_pressed(&self, _key: u8) -> bool {
+        false
+    }
+    fn delay_timer(&self) -> u8 {
+        0
+    }
+    fn set_delay_timer(&mut self, _val: u8) {}
+    fn set_sound_timer(&mut self, _val: u8) {}
+    fn random_byte(&mut self) -> u8 {
+        0
+    }
+}
+
+fn cpu_instructions_per_second(cache: bool) -> f64 {
+    let mut bus = MemoryBus(Memory::new());
+    bus.0.set_decode_cache(cache);
+    bus.0.load(ARITHMETIC.to_vec()).unwrap();
+    let mut cpu = Cpu::new();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        cpu.step(&mut bus);
+    }
+    TICKS as f64 / start.elapsed().as_secs_f64()
+}
+
+fn system_instructions_per_second(cache: bool) -> f64 {
+    let mut emu = System::new();
+    emu.set_trace(false);
+    emu.memory_mut().set_decode_cache(cache);
+    emu.load_rom(ARITHMETIC.to_vec()).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..TICKS {
+        emu.tick();
+    }
+    TICKS as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    // warm up
+    cpu_instructions_per_second(true);
+
+    for (name, run) in [
+        ("Cpu::step", cpu_instructions_per_second as fn(bool) -> f64),
+        ("System::tick", system_instructions_per_second),
+    ] {
+        let uncached = run(false);
+        let cached = run(true);
+        println!("{}", name);
+        println!("  without decode cache: {:>12.0} instructions/s", uncached);
+        println!("  with decode cache:    {:>12.0} instructions/s", cached);
+        println!("  speedup:              {:>12.2}x", cached / uncached);
+    }
+}
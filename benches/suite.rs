@@ -0,0 +1,152 @@
+// Headless benchmarks of the emulator core. Run with
+// `cargo bench --bench suite`, optionally followed by `-- <rom files>` to
+// include real ROMs.
+//
+// - instructions per second of `System::tick` for each ROM
+// - wall time of a frame that redraws the whole screen with `DXYN`
+// - cost of rendering the display to an offscreen SDL canvas
+
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+
+use chip8::cpu::Instruction;
+use chip8::system::System;
+use chip8::video::{PIXEL_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+mod common;
+
+use common::ARITHMETIC;
+
+const TICKS: u32 = 1_000_000;
+const FRAMES: u32 = 20_000;
+const RENDERS: u32 = 500;
+
+// The classic Maze: random diagonals drawn in 4x4 cells. Once the screen is
+// full it clears it at 0x218, where the benchmark resets it.
+const MAZE: [u8; 34] = [
+    0xA2, 0x1E, // 200: I = 0x21E
+    0xC2, 0x01, // 202: V2 = random & 1
+    0x32, 0x01, // 204: skip if V2 == 1
+    0xA2, 0x1A, // 206: I = 0x21A
+    0xD0, 0x14, // 208: draw 4 rows at (V0, V1)
+    0x70, 0x04, // 20A: V0 += 4
+    0x30, 0x40, // 20C: skip if V0 == 64
+    0x12, 0x00, // 20E: jump 0x200
+    0x60, 0x00, // 210: V0 = 0
+    0x71, 0x04, // 212: V1 += 4
+    0x31, 0x20, // 214: skip if V1 == 32
+    0x12, 0x00, // 216: jump 0x200
+    0x00, 0xE0, // 218: clear screen, restart
+    0x80, 0x40, 0x20, 0x10, // 21A: \
+    0x20, 0x40, 0x80, 0x10, // 21E: /
+];
+
+// Clears the screen and covers it with 16 sprites of 15 rows, forever.
+// Every clear starts a new frame.
+const SPRITES: [u8; 26] = [
+    0x00, 0xE0, // 200: clear screen
+    0x60, 0x00, // 202: V0 = 0
+    0x61, 0x00, // 204: V1 = 0
+    0xA0, 0x00, // 206: I = font
+    0xD0, 0x1F, // 208: draw 15 rows at (V0, V1)
+    0x70, 0x08, // 20A: V0 += 8
+    0x30, 0x40, // 20C: skip if V0 == 64
+    0x12, 0x08, // 20E: jump 0x208
+    0x60, 0x00, // 210: V0 = 0
+    0x71, 0x10, // 212: V1 += 16
+    0x31, 0x20, // 214: skip if V1 == 32
+    0x12, 0x08, // 216: jump 0x208
+    0x12, 0x00, // 218: jump 0x200
+];
+
+fn system(rom: &[u8]) -> System {
+    let mut emu = System::new();
+    emu.set_trace(false);
+    emu.load_rom(rom.to_vec()).unwrap();
+    emu
+}
+
+// Resets of Maze are left out of the measured time.
+fn instructions_per_second(rom: &[u8]) -> f64 {
+    let mut emu = system(rom);
+    let restarts = rom == MAZE;
+    let mut elapsed = Duration::ZERO;
+    let mut ticks = 0;
+    while ticks < TICKS {
+        let start = Instant::now();
+        let mut finished = false;
+        while ticks < TICKS && !finished {
+            finished = emu.tick() == Instruction::ClearScreen && restarts;
+            ticks += 1;
+        }
+        elapsed += start.elapsed();
+        if finished {
+            emu.reset();
+        }
+    }
+    TICKS as f64 / elapsed.as_secs_f64()
+}
+
+// Average wall time from one clear screen to the next.
+fn sprite_frame_time() -> Duration {
+    let mut emu = system(&SPRITES);
+    while emu.tick() != Instruction::ClearScreen {}
+    let start = Instant::now();
+    let mut frames = 0;
+    while frames < FRAMES {
+        if emu.tick() == Instruction::ClearScreen {
+            frames += 1;
+        }
+    }
+    start.elapsed() / FRAMES
+}
+
+// Average time of `System::draw` on a software canvas the size of the window.
+fn render_time() -> Result<Duration, String> {
+    let surface = Surface::new(
+        SCREEN_WIDTH as u32 * PIXEL_SIZE,
+        SCREEN_HEIGHT as u32 * PIXEL_SIZE,
+        PixelFormatEnum::RGB888,
+    )?;
+    let mut canvas = surface.into_canvas()?;
+    let mut emu = system(&SPRITES);
+    for _ in 0..20 {
+        emu.tick();
+    }
+    let start = Instant::now();
+    for _ in 0..RENDERS {
+        emu.draw(&mut canvas);
+    }
+    Ok(start.elapsed() / RENDERS)
+}
+
+fn main() {
+    let mut roms: Vec<(String, Vec<u8>)> = vec![
+        ("arithmetic".to_string(), ARITHMETIC.to_vec()),
+        ("maze".to_string(), MAZE.to_vec()),
+        ("sprites".to_string(), SPRITES.to_vec()),
+    ];
+    for path in env::args().skip(1).filter(|a| !a.starts_with("--")) {
+        match fs::read(&path) {
+            Ok(data) => roms.push((path, data)),
+            Err(e) => println!("Unable to read {}: {}", path, e),
+        }
+    }
+
+    println!("System::tick, {} ticks per ROM", TICKS);
+    for (name, rom) in &roms {
+        println!("  {:<24} {:>12.0} instructions/s", name, instructions_per_second(rom));
+    }
+
+    let frame = sprite_frame_time();
+    println!("Frame of 16 15-row sprites:  {:>9.2} us", frame.as_secs_f64() * 1e6);
+
+    match render_time() {
+        Ok(render) => println!("System::draw to a canvas:    {:>9.2} us", render.as_secs_f64() * 1e6),
+        Err(e) => println!("System::draw skipped, no SDL canvas: {}", e),
+    }
+}
@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use chip8::{cpu, mem};
+
+// Decodes every address of an arbitrary ROM, including odd ones and the
+// wraparound at the end of RAM.
+fuzz_target!(|data: &[u8]| {
+    let mut memory = mem::Memory::new();
+    if memory.load(data.to_vec()).is_err() {
+        return;
+    }
+    for addr in 0..mem::RAM_SIZE as u16 {
+        let _ = cpu::Cpu::decode(memory.get_instruction(addr)).to_string();
+    }
+});
@@ -0,0 +1,42 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use std::time::Duration;
+
+use chip8::system;
+
+// Enough to get through a few frames of any ROM while keeping each run fast.
+const MAX_TICKS: usize = 4096;
+const TICKS_PER_KEY_EVENT: usize = 16;
+
+// Input layout: [event count N][N key events][ROM bytes]. A key event is the
+// keypad index in the low nibble and pressed/released in the top bit, applied
+// every TICKS_PER_KEY_EVENT ticks. Nothing may panic, and each run is bounded.
+fuzz_target!(|data: &[u8]| {
+    let (&event_count, rest) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let (events, rom) = rest.split_at((event_count as usize).min(rest.len()));
+
+    let mut emulator = system::System::new();
+    if emulator.load_rom(rom.to_vec()).is_err() {
+        return;
+    }
+
+    for tick in 0..MAX_TICKS {
+        if tick % TICKS_PER_KEY_EVENT == 0 {
+            if let Some(event) = events.get(tick / TICKS_PER_KEY_EVENT) {
+                let time = Duration::from_millis(tick as u64);
+                if event >> 7 == 1 {
+                    emulator.keypad_mut().press(event & 0xF, time);
+                } else {
+                    emulator.keypad_mut().release(event & 0xF, time);
+                }
+            }
+        }
+        emulator.tick();
+        emulator.should_draw();
+        emulator.should_play_sound();
+    }
+});
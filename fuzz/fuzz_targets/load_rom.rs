@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use chip8::{mem, system};
+
+// Any ROM either loads or is rejected with an error, never a panic.
+fuzz_target!(|data: &[u8]| {
+    let mut emulator = system::System::new();
+    let result = emulator.load_rom(data.to_vec());
+    assert_eq!(result.is_ok(), data.len() <= mem::RAM_SIZE - mem::PROGRAM_START);
+});
@@ -0,0 +1,221 @@
+use chip8::keypad::KeyWait;
+use chip8::platform::Quirks;
+use chip8::system::System;
+use chip8::timing::{self, Timing};
+use chip8::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::time::Duration;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+// Stereo frames of audio per video frame.
+pub const AUDIO_FRAMES: usize = (SAMPLE_RATE / timing::FRAMES_PER_SECOND) as usize;
+const TONE: f32 = 440.0;
+const VOLUME: i16 = 4000;
+
+// Core options in the form the frontend takes them: a description, then the
+// values with the default first. Both end in a NUL so they can be handed
+// over as they are.
+pub struct CoreOption {
+    pub key: &'static str,
+    pub value: &'static str,
+}
+
+pub const OPTIONS: [CoreOption; 9] = [
+    CoreOption {
+        key: "chip8_clock\0",
+        value: "Clock (instructions per frame); fixed|vip|7|10|11|15|20|30|50|100|200|500|1000\0",
+    },
+    CoreOption { key: "chip8_shift\0", value: "Quirk: shift VX in place; enabled|disabled\0" },
+    CoreOption {
+        key: "chip8_memory_increment_by_x\0",
+        value: "Quirk: FX55/FX65 add X to I; disabled|enabled\0",
+    },
+    CoreOption {
+        key: "chip8_memory_leave_i_unchanged\0",
+        value: "Quirk: FX55/FX65 leave I unchanged; disabled|enabled\0",
+    },
+    CoreOption { key: "chip8_wrap\0", value: "Quirk: sprites wrap; enabled|disabled\0" },
+    CoreOption { key: "chip8_jump\0", value: "Quirk: BNNN jumps to XNN + VX; disabled|enabled\0" },
+    CoreOption { key: "chip8_vblank\0", value: "Quirk: DXYN waits for vblank; disabled|enabled\0" },
+    CoreOption { key: "chip8_logic\0", value: "Quirk: logic ops reset VF; disabled|enabled\0" },
+    CoreOption { key: "chip8_fx0a\0", value: "FX0A waits for; release|press\0" },
+];
+
+impl CoreOption {
+    pub fn name(&self) -> &'static str {
+        self.key.trim_end_matches('\0')
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub key_wait: KeyWait,
+}
+
+impl Options {
+    pub const DEFAULT: Options = Options {
+        quirks: Quirks::DEFAULT,
+        timing: Timing::Fixed,
+        key_wait: KeyWait::Release,
+    };
+
+    // Reads the options through `value`, which gives the current value of an
+    // option by name. Missing and unknown values keep the default.
+    pub fn read(value: impl Fn(&str) -> Option<String>) -> Options {
+        let mut options = Options::DEFAULT;
+        for option in OPTIONS.iter() {
+            let value = match value(option.name()) {
+                Some(value) => value,
+                None => continue,
+            };
+            let enabled = value == "enabled";
+            let quirks = &mut options.quirks;
+            match option.name() {
+                "chip8_clock" => {
+                    options.timing = match value.as_str() {
+                        "fixed" => Timing::Fixed,
+                        "vip" => Timing::Vip,
+                        tickrate => match tickrate.parse() {
+                            Ok(tickrate) if tickrate > 0 => Timing::Tickrate(tickrate),
+                            _ => continue,
+                        },
+                    }
+                }
+                "chip8_shift" => quirks.shift = enabled,
+                "chip8_memory_increment_by_x" => quirks.memory_increment_by_x = enabled,
+                "chip8_memory_leave_i_unchanged" => quirks.memory_leave_i_unchanged = enabled,
+                "chip8_wrap" => quirks.wrap = enabled,
+                "chip8_jump" => quirks.jump = enabled,
+                "chip8_vblank" => quirks.vblank = enabled,
+                "chip8_logic" => quirks.logic = enabled,
+                "chip8_fx0a" => options.key_wait = if value == "press" { KeyWait::Press } else { KeyWait::Release },
+                _ => {}
+            }
+        }
+        options
+    }
+}
+
+// The emulator as the frontend drives it: keys in, then a frame of video and
+// audio out.
+pub struct Core {
+    emu: System,
+    keys: [bool; 16],
+    frames: u64,
+    // Position in the current period of the buzzer's square wave, 0 to 1.
+    phase: f32,
+    video: Vec<u32>,
+    audio: Vec<i16>,
+}
+
+impl Core {
+    pub fn load(rom: Vec<u8>, options: Options) -> Result<Core, String> {
+        let mut emu = System::new();
+        emu.set_trace(false);
+        emu.load_rom(rom)?;
+        let mut core = Core {
+            emu,
+            keys: [false; 16],
+            frames: 0,
+            phase: 0.0,
+            video: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            audio: vec![0; AUDIO_FRAMES * 2],
+        };
+        core.set_options(options);
+        core.render();
+        Ok(core)
+    }
+
+    pub fn set_options(&mut self, options: Options) {
+        self.emu.set_quirks(options.quirks);
+        self.emu.set_timing(options.timing);
+        self.emu.set_key_wait(options.key_wait);
+    }
+
+    pub fn system(&self) -> &System {
+        &self.emu
+    }
+    pub fn system_mut(&mut self) -> &mut System {
+        &mut self.emu
+    }
+
+    // The keys held as of the start of the next frame. Changes are passed on
+    // as key events, so taps of a single frame are not lost.
+    pub fn set_keys(&mut self, keys: [bool; 16]) {
+        let time = self.time();
+        for (key, (&down, was_down)) in keys.iter().zip(self.keys.iter_mut()).enumerate() {
+            if down != *was_down {
+                if down {
+                    self.emu.keypad_mut().press(key as u8, time);
+                } else {
+                    self.emu.keypad_mut().release(key as u8, time);
+                }
+                *was_down = down;
+            }
+        }
+    }
+
+    pub fn run_frame(&mut self) {
+        self.emu.run_frame();
+        self.frames += 1;
+        self.render();
+        self.emu.mark_drawn();
+        self.fill_audio();
+    }
+
+    pub fn reset(&mut self) {
+        self.emu.reset();
+        self.render();
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.emu.save_state()
+    }
+    pub fn unserialize(&mut self, state: &[u8]) -> Result<(), String> {
+        self.emu.load_state(state)?;
+        self.render();
+        Ok(())
+    }
+
+    // The display as XRGB8888, SCREEN_WIDTH pixels per row.
+    pub fn video(&self) -> &[u32] {
+        &self.video
+    }
+    // AUDIO_FRAMES interleaved stereo frames.
+    pub fn audio(&self) -> &[i16] {
+        &self.audio
+    }
+
+    fn time(&self) -> Duration {
+        Duration::from_micros(self.frames * 1_000_000 / timing::FRAMES_PER_SECOND as u64)
+    }
+
+    fn render(&mut self) {
+        let (off, on) = self.emu.video().palette();
+        let xrgb = |r: u8, g: u8, b: u8| (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        let (off, on) = (xrgb(off.r, off.g, off.b), xrgb(on.r, on.g, on.b));
+        for (out, &pixel) in self.video.iter_mut().zip(self.emu.video().pixels()) {
+            *out = if pixel == 0 { off } else { on };
+        }
+    }
+
+    // A square wave while the sound timer runs, silence otherwise.
+    fn fill_audio(&mut self) {
+        let (_, sound) = self.emu.timers();
+        if sound == 0 {
+            self.audio.iter_mut().for_each(|sample| *sample = 0);
+            self.phase = 0.0;
+            return;
+        }
+        for frame in self.audio.chunks_mut(2) {
+            let sample = if self.phase < 0.5 { VOLUME } else { -VOLUME };
+            frame[0] = sample;
+            frame[1] = sample;
+            self.phase += TONE / SAMPLE_RATE as f32;
+            if self.phase >= 1.0 {
+                self.phase -= 1.0;
+            }
+        }
+    }
+}
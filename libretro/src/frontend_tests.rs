@@ -0,0 +1,272 @@
+#[cfg(test)]
+mod tests {
+    use crate::api::*;
+    use crate::emulator::{Options, AUDIO_FRAMES};
+    use crate::*;
+    use chip8::keypad::KeyWait;
+    use chip8::timing::Timing;
+    use std::collections::{HashMap, HashSet};
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_uint, c_void};
+    use std::sync::{Mutex, MutexGuard};
+
+    // A minimal frontend: it keeps what the core hands it and answers its
+    // questions from here. The callbacks are global, so tests take turns.
+    struct Host {
+        pixel_format: Option<c_uint>,
+        // Declared options, as (key, description and values).
+        declared: Vec<(String, String)>,
+        variables: HashMap<String, CString>,
+        variables_updated: bool,
+        held: HashSet<(c_uint, c_uint)>,
+        polls: u32,
+        frame: Vec<u32>,
+        frame_size: (c_uint, c_uint, usize),
+        audio: Vec<i16>,
+    }
+
+    static TURN: Mutex<()> = Mutex::new(());
+    static HOST: Mutex<Option<Host>> = Mutex::new(None);
+
+    fn host<T>(f: impl FnOnce(&mut Host) -> T) -> T {
+        f(HOST.lock().unwrap().as_mut().unwrap())
+    }
+
+    unsafe extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+        host(|host| match cmd {
+            ENVIRONMENT_SET_PIXEL_FORMAT => {
+                host.pixel_format = Some(*(data as *const c_uint));
+                true
+            }
+            ENVIRONMENT_SET_VARIABLES => {
+                let mut variable = data as *const Variable;
+                while !(*variable).key.is_null() {
+                    let key = CStr::from_ptr((*variable).key).to_string_lossy().into_owned();
+                    let value = CStr::from_ptr((*variable).value).to_string_lossy().into_owned();
+                    host.declared.push((key, value));
+                    variable = variable.add(1);
+                }
+                true
+            }
+            ENVIRONMENT_GET_VARIABLE => {
+                let variable = &mut *(data as *mut Variable);
+                let key = CStr::from_ptr(variable.key).to_string_lossy();
+                match host.variables.get(key.as_ref()) {
+                    Some(value) => {
+                        variable.value = value.as_ptr();
+                        true
+                    }
+                    None => false,
+                }
+            }
+            ENVIRONMENT_GET_VARIABLE_UPDATE => {
+                *(data as *mut bool) = host.variables_updated;
+                host.variables_updated = false;
+                true
+            }
+            _ => false,
+        })
+    }
+
+    unsafe extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+        let pixels = std::slice::from_raw_parts(data as *const u32, pitch / 4 * height as usize);
+        host(|host| {
+            host.frame = pixels.to_vec();
+            host.frame_size = (width, height, pitch);
+        });
+    }
+
+    unsafe extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+    unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+        let samples = std::slice::from_raw_parts(data, frames * 2);
+        host(|host| host.audio = samples.to_vec());
+        frames
+    }
+
+    unsafe extern "C" fn input_poll() {
+        host(|host| host.polls += 1);
+    }
+
+    unsafe extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+        host(|host| (port == 0 && host.held.contains(&(device, id))) as i16)
+    }
+
+    // Sets up the core the way a frontend does before loading a game.
+    fn start() -> MutexGuard<'static, ()> {
+        let turn = TURN.lock().unwrap_or_else(|e| e.into_inner());
+        *HOST.lock().unwrap() = Some(Host {
+            pixel_format: None,
+            declared: Vec::new(),
+            variables: HashMap::new(),
+            variables_updated: false,
+            held: HashSet::new(),
+            polls: 0,
+            frame: Vec::new(),
+            frame_size: (0, 0, 0),
+            audio: Vec::new(),
+        });
+        unsafe {
+            retro_set_environment(environment);
+            retro_set_video_refresh(video_refresh);
+            retro_set_audio_sample(audio_sample);
+            retro_set_audio_sample_batch(audio_sample_batch);
+            retro_set_input_poll(input_poll);
+            retro_set_input_state(input_state);
+        }
+        retro_init();
+        turn
+    }
+
+    fn load(rom: &[u8]) -> bool {
+        let game = GameInfo {
+            path: std::ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: std::ptr::null(),
+        };
+        unsafe { retro_load_game(&game) }
+    }
+
+    fn run(frames: u32) {
+        for _ in 0..frames {
+            unsafe { retro_run() };
+        }
+    }
+
+    fn set_variable(key: &str, value: &str) {
+        host(|host| {
+            host.variables.insert(key.to_string(), CString::new(value).unwrap());
+            host.variables_updated = true;
+        });
+    }
+
+    fn v(x: usize) -> u8 {
+        with_core(|core| core.as_ref().unwrap().system().cpu().get_v(x))
+    }
+
+    #[test]
+    fn the_core_describes_itself() {
+        let _turn = start();
+        assert_eq!(retro_api_version(), 1);
+        let declared = host(|host| host.declared.clone());
+        assert_eq!(declared.len(), OPTIONS.len());
+        assert_eq!(declared[0].0, "chip8_clock");
+        assert!(declared[0].1.ends_with("; fixed|vip|7|10|11|15|20|30|50|100|200|500|1000"));
+
+        unsafe {
+            let mut info = std::mem::zeroed::<SystemInfo>();
+            retro_get_system_info(&mut info);
+            assert_eq!(CStr::from_ptr(info.library_name).to_str(), Ok("chip8"));
+            assert!(!info.need_fullpath);
+            let mut av = std::mem::zeroed::<SystemAvInfo>();
+            retro_get_system_av_info(&mut av);
+            assert_eq!((av.geometry.base_width, av.geometry.base_height), (64, 32));
+            assert_eq!(av.timing.fps, 60.0);
+        }
+
+        assert!(!load(&[0; 0x1000]));
+        assert!(load(&[0x12, 0x00]));
+        assert_eq!(host(|host| host.pixel_format), Some(PIXEL_FORMAT_XRGB8888));
+        retro_unload_game();
+        assert_eq!(retro_serialize_size(), 0);
+    }
+
+    #[test]
+    fn frames_show_the_display_and_play_the_buzzer() {
+        let _turn = start();
+        // draw the 0 glyph at 0,0, sound timer = 12, spin
+        assert!(load(&[0xA0, 0x00, 0xD0, 0x05, 0x61, 0x0C, 0xF1, 0x18, 0x12, 0x08]));
+        run(1);
+        let (frame, size, audio) = host(|host| (host.frame.clone(), host.frame_size, host.audio.clone()));
+        assert_eq!(size, (64, 32, 256));
+        let on = frame[0];
+        let off = frame[4];
+        assert_ne!(on, off);
+        // F0 90 90 90 F0
+        assert_eq!(&frame[0..5], &[on, on, on, on, off]);
+        assert_eq!(&frame[64..69], &[on, off, off, on, off]);
+        assert_eq!(audio.len(), AUDIO_FRAMES * 2);
+        assert!(audio.iter().any(|&sample| sample > 0) && audio.iter().any(|&sample| sample < 0));
+
+        run(5);
+        assert!(host(|host| host.audio.iter().all(|&sample| sample == 0)));
+        assert_eq!(host(|host| host.polls), 6);
+        retro_unload_game();
+    }
+
+    #[test]
+    fn joypad_and_keyboard_press_keypad_keys() {
+        let _turn = start();
+        // V1 = 1 once key 5 is down
+        let rom = [0x60, 0x05, 0xE0, 0x9E, 0x12, 0x02, 0x61, 0x01, 0x12, 0x08];
+        assert!(load(&rom));
+        run(3);
+        assert_eq!(v(1), 0);
+        host(|host| host.held.insert((DEVICE_JOYPAD, DEVICE_ID_JOYPAD_UP)));
+        run(1);
+        assert_eq!(v(1), 1);
+
+        host(|host| host.held.clear());
+        retro_reset();
+        run(3);
+        assert_eq!(v(1), 0);
+        host(|host| host.held.insert((DEVICE_KEYBOARD, 'w' as c_uint)));
+        run(1);
+        assert_eq!(v(1), 1);
+        retro_unload_game();
+    }
+
+    #[test]
+    fn states_round_trip() {
+        let _turn = start();
+        // V0 counts up
+        assert!(load(&[0x70, 0x01, 0x12, 0x00]));
+        run(2);
+        let size = retro_serialize_size();
+        assert!(size > 0);
+        let mut state = vec![0u8; size];
+        unsafe {
+            assert!(!retro_serialize(state.as_mut_ptr() as *mut c_void, size - 1));
+            assert!(retro_serialize(state.as_mut_ptr() as *mut c_void, size));
+        }
+        let saved = v(0);
+        run(2);
+        assert_ne!(v(0), saved);
+        unsafe {
+            assert!(retro_unserialize(state.as_ptr() as *const c_void, size));
+            assert_eq!(v(0), saved);
+            assert!(!retro_unserialize(state.as_ptr() as *const c_void, size - 1));
+        }
+        retro_unload_game();
+    }
+
+    #[test]
+    fn options_set_quirks_and_clock() {
+        let _turn = start();
+        set_variable("chip8_clock", "vip");
+        set_variable("chip8_jump", "enabled");
+        assert!(load(&[0x12, 0x00]));
+        with_core(|core| {
+            let emu = core.as_ref().unwrap().system();
+            assert_eq!(emu.timing(), Timing::Vip);
+            assert!(emu.quirks().jump);
+        });
+
+        // changes made while running apply from the next frame
+        set_variable("chip8_clock", "20");
+        set_variable("chip8_shift", "disabled");
+        set_variable("chip8_fx0a", "press");
+        run(1);
+        with_core(|core| {
+            let emu = core.as_ref().unwrap().system();
+            assert_eq!(emu.timing(), Timing::Tickrate(20));
+            assert!(!emu.quirks().shift && emu.quirks().jump);
+            assert_eq!(emu.keypad().wait(), KeyWait::Press);
+        });
+        retro_unload_game();
+
+        let options = Options::read(|name| if name == "chip8_clock" { Some("fast".to_string()) } else { None });
+        assert_eq!(options, Options::DEFAULT);
+    }
+}
@@ -0,0 +1,328 @@
+// The safety contract of the retro_* functions is the one in libretro.h.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use chip8::keypad::KEY_LAYOUT;
+use chip8::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub mod api;
+pub mod emulator;
+
+mod frontend_tests;
+
+use crate::api::*;
+use crate::emulator::{Core, Options, AUDIO_FRAMES, OPTIONS, SAMPLE_RATE};
+
+// A libretro core, so the emulator runs in RetroArch and other libretro
+// frontends. The frontend calls the retro_* functions below from one thread.
+
+// Joypad buttons for the keypad, laid out for the many programs that move
+// with 5/7/8/9 (W/A/S/D) and act with 4 and 6.
+const JOYPAD_MAP: [(c_uint, u8); 10] = [
+    (DEVICE_ID_JOYPAD_UP, 0x5),
+    (DEVICE_ID_JOYPAD_LEFT, 0x7),
+    (DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (DEVICE_ID_JOYPAD_RIGHT, 0x9),
+    (DEVICE_ID_JOYPAD_B, 0x4),
+    (DEVICE_ID_JOYPAD_A, 0x6),
+    (DEVICE_ID_JOYPAD_Y, 0x1),
+    (DEVICE_ID_JOYPAD_X, 0x2),
+    (DEVICE_ID_JOYPAD_L, 0x3),
+    (DEVICE_ID_JOYPAD_R, 0xC),
+];
+const JOYPAD_SELECT_KEY: u8 = 0x0;
+const JOYPAD_START_KEY: u8 = 0xF;
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+// The emulator keeps boxed callbacks that are not Send, and frontends only
+// call in from one thread anyway.
+thread_local! {
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+fn with_core<T>(f: impl FnOnce(&mut Option<Core>) -> T) -> T {
+    CORE.with(|core| f(&mut core.borrow_mut()))
+}
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+unsafe fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match callbacks().environment {
+        Some(environment) => environment(cmd, data),
+        None => false,
+    }
+}
+
+unsafe fn read_options() -> Options {
+    Options::read(|name| {
+        let option = OPTIONS.iter().find(|option| option.name() == name)?;
+        let mut variable = Variable { key: option.key.as_ptr() as *const c_char, value: ptr::null() };
+        if !environment(ENVIRONMENT_GET_VARIABLE, &mut variable as *mut Variable as *mut c_void) || variable.value.is_null() {
+            return None;
+        }
+        Some(CStr::from_ptr(variable.value).to_string_lossy().into_owned())
+    })
+}
+
+unsafe fn poll_keys() -> [bool; 16] {
+    let mut keys = [false; 16];
+    let callbacks = callbacks();
+    if let Some(poll) = callbacks.input_poll {
+        poll();
+    }
+    let state = match callbacks.input_state {
+        Some(state) => state,
+        None => return keys,
+    };
+    for &(id, key) in JOYPAD_MAP.iter() {
+        keys[key as usize] |= state(0, DEVICE_JOYPAD, 0, id) != 0;
+    }
+    keys[JOYPAD_SELECT_KEY as usize] |= state(0, DEVICE_JOYPAD, 0, DEVICE_ID_JOYPAD_SELECT) != 0;
+    keys[JOYPAD_START_KEY as usize] |= state(0, DEVICE_JOYPAD, 0, DEVICE_ID_JOYPAD_START) != 0;
+    // Keyboard ids are the lower case ASCII codes.
+    for (key, &c) in KEY_LAYOUT.iter().enumerate() {
+        keys[key] |= state(0, DEVICE_KEYBOARD, 0, c as c_uint) != 0;
+    }
+    keys
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(callback);
+    let mut variables: Vec<Variable> = OPTIONS
+        .iter()
+        .map(|option| Variable {
+            key: option.key.as_ptr() as *const c_char,
+            value: option.value.as_ptr() as *const c_char,
+        })
+        .collect();
+    variables.push(Variable { key: ptr::null(), value: ptr::null() });
+    callback(ENVIRONMENT_SET_VARIABLES, variables.as_mut_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+// Audio goes out a frame at a time through the batch callback.
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    with_core(|core| *core = None);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: b"chip8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|rom\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: SCREEN_WIDTH as c_uint,
+            base_height: SCREEN_HEIGHT as c_uint,
+            max_width: SCREEN_WIDTH as c_uint,
+            max_height: SCREEN_HEIGHT as c_uint,
+            aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: chip8::timing::FRAMES_PER_SECOND as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core(|core| {
+        if let Some(core) = core.as_mut() {
+            core.reset();
+        }
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    let mut updated = false;
+    if environment(ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void) && updated {
+        let options = read_options();
+        with_core(|core| {
+            if let Some(core) = core.as_mut() {
+                core.set_options(options);
+            }
+        });
+    }
+    let keys = poll_keys();
+
+    with_core(|core| {
+        let core = match core.as_mut() {
+            Some(core) => core,
+            None => return,
+        };
+        core.set_keys(keys);
+        core.run_frame();
+
+        let callbacks = callbacks();
+        if let Some(video_refresh) = callbacks.video_refresh {
+            video_refresh(
+                core.video().as_ptr() as *const c_void,
+                SCREEN_WIDTH as c_uint,
+                SCREEN_HEIGHT as c_uint,
+                SCREEN_WIDTH * 4,
+            );
+        }
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            audio_sample_batch(core.audio().as_ptr(), AUDIO_FRAMES);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_core(|core| core.as_ref().map_or(0, |core| core.serialize().len()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match with_core(|core| core.as_ref().map(Core::serialize)) {
+        Some(state) => state,
+        None => return false,
+    };
+    if data.is_null() || size < state.len() {
+        return false;
+    }
+    ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let state = slice::from_raw_parts(data as *const u8, size);
+    with_core(|core| match core.as_mut() {
+        Some(core) => match core.unserialize(state) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("Cannot load state: {}", e);
+                false
+            }
+        },
+        None => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+        println!("The frontend does not support XRGB8888");
+        return false;
+    }
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    match Core::load(rom, read_options()) {
+        Ok(core) => {
+            with_core(|slot| *slot = Some(core));
+            true
+        }
+        Err(e) => {
+            println!("Cannot load ROM: {}", e);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    with_core(|core| *core = None);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+// RAM is only changed through the emulator, not exposed to the frontend.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
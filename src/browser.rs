@@ -0,0 +1,221 @@
+use sdl2::pixels::Color;
+use sdl2::render::{Canvas, RenderTarget};
+use std::fs;
+use std::path::Path;
+
+use crate::mem::MAX_ROM_SIZE;
+use crate::osd::{self, GLYPH_WIDTH, LINE_HEIGHT, MARGIN, SCALE, WINDOW_HEIGHT, WINDOW_WIDTH};
+use crate::romdb::{RomDb, RomInfo};
+
+pub const MAX_RECENT: usize = 10;
+// Entries shown at once, and how far Page Up/Down move.
+pub const LIST_ROWS: usize = 10;
+const DETAIL_COLOR: Color = Color::RGB(160, 160, 160);
+
+// Recently played ROMs, most recent first, stored one path per line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recent {
+    paths: Vec<String>,
+}
+
+impl Recent {
+    pub fn new() -> Recent {
+        Recent { paths: Vec::new() }
+    }
+    // A missing or unreadable file is an empty list.
+    pub fn load(path: &str) -> Recent {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        Recent {
+            paths: text
+                .lines()
+                .filter(|line| !line.is_empty())
+                .take(MAX_RECENT)
+                .map(|line| line.to_string())
+                .collect(),
+        }
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text: String = self.paths.iter().map(|path| format!("{}\n", path)).collect();
+        fs::write(path, text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn add(&mut self, rom_path: &str) {
+        self.paths.retain(|path| path != rom_path);
+        self.paths.insert(0, rom_path.to_string());
+        self.paths.truncate(MAX_RECENT);
+    }
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+}
+
+// A ROM file that can be picked, with what the database knows about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub path: String,
+    pub recent: bool,
+    pub info: Option<RomInfo>,
+}
+
+impl Entry {
+    fn new(path: &str, recent: bool, rom_db: &RomDb) -> Entry {
+        let info = fs::read(path).ok().and_then(|rom| rom_db.lookup(&rom));
+        Entry {
+            path: path.to_string(),
+            recent,
+            info,
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
+    }
+    // The database title, or the file name of unknown ROMs.
+    pub fn title(&self) -> &str {
+        match self.info.as_ref().and_then(|info| info.title.as_deref()) {
+            Some(title) => title,
+            None => self.file_name(),
+        }
+    }
+
+    // Case insensitive match against the file name, title and authors.
+    fn matches(&self, filter: &str) -> bool {
+        let filter = filter.to_lowercase();
+        let matches = |text: &str| text.to_lowercase().contains(&filter);
+        matches(self.file_name())
+            || matches(self.title())
+            || self.info.iter().flat_map(|info| &info.authors).any(|author| matches(author))
+    }
+}
+
+// The ROM selection screen: recent ROMs followed by the files in a
+// directory, narrowed down by typing.
+pub struct Browser {
+    dir: String,
+    entries: Vec<Entry>,
+    filter: String,
+    // Index into `visible()`.
+    selected: usize,
+}
+
+impl Browser {
+    // Lists the files in `dir` small enough to be ROMs, by name. Recent ROMs
+    // that still exist come first, wherever they are.
+    pub fn open(dir: &str, recent: &Recent, rom_db: &RomDb) -> Browser {
+        let mut entries: Vec<Entry> = recent
+            .paths()
+            .iter()
+            .filter(|path| Path::new(path).is_file())
+            .map(|path| Entry::new(path, true, rom_db))
+            .collect();
+
+        // Absolute paths, so they compare equal to the recent ones
+        let full_dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.into());
+        let mut paths: Vec<String> = match fs::read_dir(full_dir) {
+            Ok(dir) => dir
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry
+                        .metadata()
+                        .is_ok_and(|meta| meta.is_file() && meta.len() as usize <= MAX_ROM_SIZE)
+                })
+                .map(|entry| entry.path().to_string_lossy().into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+        for path in paths {
+            if !recent.paths().contains(&path) {
+                entries.push(Entry::new(&path, false, rom_db));
+            }
+        }
+
+        Browser {
+            dir: dir.to_string(),
+            entries,
+            filter: String::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn dir(&self) -> &str {
+        &self.dir
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+    // Typing narrows the list down and starts again from the top.
+    pub fn push_filter(&mut self, text: &str) {
+        self.filter.push_str(text);
+        self.selected = 0;
+    }
+    pub fn pop_filter(&mut self) {
+        self.filter.pop();
+        self.selected = 0;
+    }
+    pub fn clear_filter(&mut self) {
+        self.filter.clear();
+        self.selected = 0;
+    }
+
+    // Entries matching the filter, in list order.
+    pub fn visible(&self) -> Vec<&Entry> {
+        self.entries.iter().filter(|entry| entry.matches(&self.filter)).collect()
+    }
+    pub fn selected(&self) -> Option<&Entry> {
+        self.visible().get(self.selected).copied()
+    }
+    // Moves the selection by `rows`, stopping at either end.
+    pub fn move_selection(&mut self, rows: i32) {
+        let last = self.visible().len().saturating_sub(1) as i32;
+        self.selected = (self.selected as i32 + rows).max(0).min(last) as usize;
+    }
+
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
+        let columns = ((WINDOW_WIDTH - 2 * MARGIN) / ((GLYPH_WIDTH as i32 + 1) * SCALE)) as usize;
+        let line = |text: &str| text.chars().take(columns).collect::<String>();
+        let y = |row: usize| MARGIN + row as i32 * LINE_HEIGHT;
+
+        osd::draw_background(canvas, 0, 0, WINDOW_WIDTH, WINDOW_HEIGHT);
+        osd::draw_text(canvas, &line(&format!("ROMs in {}", self.dir)), MARGIN, y(0), osd::TEXT_COLOR);
+        let filter = format!("Filter: {}_", self.filter);
+        osd::draw_text(canvas, &line(&filter), MARGIN, y(1), osd::TEXT_COLOR);
+
+        let visible = self.visible();
+        if visible.is_empty() {
+            let text = if self.is_empty() { "No ROMs found" } else { "No matches" };
+            osd::draw_text(canvas, text, MARGIN, y(3), DETAIL_COLOR);
+            return;
+        }
+        let first = (self.selected + 1).saturating_sub(LIST_ROWS);
+        for (row, (i, entry)) in visible.iter().enumerate().skip(first).take(LIST_ROWS).enumerate() {
+            let marker = if i == self.selected { '>' } else { ' ' };
+            let recent = if entry.recent { '*' } else { ' ' };
+            let text = line(&format!("{}{} {}", marker, recent, entry.title()));
+            let color = if i == self.selected { osd::SELECTED_COLOR } else { osd::TEXT_COLOR };
+            osd::draw_text(canvas, &text, MARGIN, y(row + 3), color);
+        }
+
+        // Details of the selected ROM below the list
+        let entry = visible[self.selected];
+        let mut details = vec![entry.file_name().to_string()];
+        match &entry.info {
+            Some(info) => {
+                if !info.authors.is_empty() {
+                    details.push(format!("By {}", info.authors.join(", ")));
+                }
+                details.push(info.platform.map_or("Unknown platform", |platform| platform.name).to_string());
+            }
+            None => details.push("Not in the ROM database".to_string()),
+        }
+        for (row, text) in details.iter().enumerate() {
+            osd::draw_text(canvas, &line(text), MARGIN, y(LIST_ROWS + 4 + row), DETAIL_COLOR);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+#[cfg(test)]
+mod tests {
+    use crate::browser::{Browser, Recent, MAX_RECENT};
+    use crate::mem::MAX_ROM_SIZE;
+    use crate::romdb::{sha1_hex, RomDb};
+    use std::fs;
+    use std::path::PathBuf;
+
+    const PONG: [u8; 2] = [0x12, 0x00];
+
+    // A fresh directory with a few ROM files in it.
+    fn rom_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chip8_browser_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pong.ch8"), PONG).unwrap();
+        fs::write(dir.join("maze.ch8"), [0x12, 0x02]).unwrap();
+        fs::write(dir.join("zz_test.ch8"), [0x12, 0x04]).unwrap();
+        fs::write(dir.join("huge.bin"), vec![0; MAX_ROM_SIZE + 1]).unwrap();
+        fs::create_dir(dir.join("subdir")).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    fn db() -> RomDb {
+        let hashes = format!(r#"{{ "{}": 0 }}"#, sha1_hex(&PONG));
+        let programs = format!(
+            r#"[{{ "title": "Paddles", "authors": ["Paul Vervalin"],
+                   "roms": {{ "{}": {{ "platforms": ["originalChip8"] }} }} }}]"#,
+            sha1_hex(&PONG)
+        );
+        RomDb::from_json(&hashes, &programs).unwrap()
+    }
+
+    #[test]
+    fn recent_keeps_the_latest_first_without_duplicates() {
+        let mut recent = Recent::new();
+        recent.add("a.ch8");
+        recent.add("b.ch8");
+        recent.add("a.ch8");
+        assert_eq!(recent.paths(), ["a.ch8", "b.ch8"]);
+        for i in 0..MAX_RECENT {
+            recent.add(&format!("{}.ch8", i));
+        }
+        assert_eq!(recent.paths().len(), MAX_RECENT);
+        assert_eq!(recent.paths()[0], format!("{}.ch8", MAX_RECENT - 1));
+
+        let dir = rom_dir("recent");
+        let path = dir.join("recent.txt").to_string_lossy().into_owned();
+        assert_eq!(Recent::load(&path), Recent::new());
+        recent.save(&path).unwrap();
+        assert_eq!(Recent::load(&path), recent);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lists_recent_roms_then_the_directory() {
+        let dir = rom_dir("list");
+        let zz = dir.join("zz_test.ch8").to_string_lossy().into_owned();
+        let mut recent = Recent::new();
+        recent.add(&zz);
+        recent.add("/no/such/rom.ch8");
+
+        let browser = Browser::open(&dir.to_string_lossy(), &recent, &db());
+        let titles: Vec<&str> = browser.visible().iter().map(|entry| entry.title()).collect();
+        // too big to be a ROM, directories and missing recent ROMs are left out
+        assert_eq!(titles, ["zz_test.ch8", "maze.ch8", "Paddles"]);
+        assert!(browser.visible()[0].recent);
+        assert!(!browser.visible()[1].recent);
+
+        let pong = browser.visible()[2];
+        assert_eq!(pong.file_name(), "pong.ch8");
+        let info = pong.info.as_ref().unwrap();
+        assert_eq!(info.authors, ["Paul Vervalin"]);
+        assert_eq!(info.platform.map(|platform| platform.id), Some("originalChip8"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn filter_matches_names_titles_and_authors() {
+        let dir = rom_dir("filter");
+        let mut browser = Browser::open(&dir.to_string_lossy(), &Recent::new(), &db());
+        browser.move_selection(2);
+        browser.push_filter("M");
+        assert_eq!(browser.selected().map(|entry| entry.file_name()), Some("maze.ch8"));
+        browser.push_filter("a");
+        assert_eq!(browser.visible().len(), 1);
+        browser.clear_filter();
+
+        for filter in ["pong", "PADDLES", "vervalin"] {
+            browser.push_filter(filter);
+            assert_eq!(browser.selected().map(|entry| entry.title()), Some("Paddles"));
+            browser.clear_filter();
+        }
+        browser.push_filter("tetris");
+        assert!(browser.visible().is_empty());
+        assert!(browser.selected().is_none());
+        browser.pop_filter();
+        assert_eq!(browser.filter(), "tetri");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn selection_stops_at_either_end() {
+        let dir = rom_dir("select");
+        let mut browser = Browser::open(&dir.to_string_lossy(), &Recent::new(), &RomDb::new());
+        browser.move_selection(-1);
+        assert_eq!(browser.selected().unwrap().file_name(), "maze.ch8");
+        browser.move_selection(10);
+        assert_eq!(browser.selected().unwrap().file_name(), "zz_test.ch8");
+        browser.move_selection(-1);
+        assert_eq!(browser.selected().unwrap().file_name(), "pong.ch8");
+        assert!(browser.selected().unwrap().info.is_none());
+
+        let browser = Browser::open("/no/such/dir", &Recent::new(), &RomDb::new());
+        assert!(browser.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
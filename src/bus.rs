@@ -0,0 +1,45 @@
+use crate::cpu::{Cpu, Instruction};
+
+// Everything the CPU reaches outside its own registers. `System` connects
+// this to memory, video, keypad and timers; tests can plug in a mock.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+    // Instruction fetches are separate from data reads so a bus can tell
+    // code from data.
+    fn fetch(&mut self, addr: u16) -> u16 {
+        (self.read(addr) as u16) << 8 | self.read(addr.wrapping_add(1)) as u16
+    }
+    // A bus may cache decoded instructions, as long as it notices when the
+    // code underneath changes.
+    fn fetch_decoded(&mut self, addr: u16) -> Instruction {
+        Cpu::decode(self.fetch(addr))
+    }
+
+    fn clear_screen(&mut self);
+    // Draws `n` rows of sprite data starting at `addr` with the top left
+    // corner at (x, y), returning whether any lit pixel was turned off.
+    fn draw_sprite(&mut self, x: u8, y: u8, n: u16, addr: u16) -> bool;
+
+    // Where the glyph of a hex digit is, for FX29, and in the large font for
+    // FX30 if there is one. By default the small font is at 0.
+    fn font_address(&self, digit: u8) -> u16 {
+        (digit & 0xF) as u16 * 5
+    }
+    fn large_font_address(&self, _digit: u8) -> Option<u16> {
+        None
+    }
+
+    fn is_key_pressed(&self, key: u8) -> bool;
+    // Called each time FX0A runs until it returns the key to store. By
+    // default it finishes as soon as a key is down, the highest one winning.
+    fn wait_for_key(&mut self) -> Option<u8> {
+        (0..16).rev().find(|&key| self.is_key_pressed(key))
+    }
+
+    fn delay_timer(&self) -> u8;
+    fn set_delay_timer(&mut self, val: u8);
+    fn set_sound_timer(&mut self, val: u8);
+
+    fn random_byte(&mut self) -> u8;
+}
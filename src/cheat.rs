@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::Path;
+
+use crate::mem::{Memory, RAM_SIZE};
+use crate::romdb::sha1_hex;
+
+// Search for more than this many matches and pinning them is refused, as it
+// would freeze half of memory.
+pub const MAX_PINS: usize = 16;
+
+// How a byte compares with its value in the last snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Comparison {
+    fn matches(self, old: u8, new: u8) -> bool {
+        match self {
+            Comparison::Equal(val) => new == val,
+            Comparison::Changed => new != old,
+            Comparison::Unchanged => new == old,
+            Comparison::Increased => new > old,
+            Comparison::Decreased => new < old,
+        }
+    }
+}
+
+// An iterative search for a game variable: start with every address, then
+// keep narrowing down to those that behaved like the variable since the last
+// step, e.g. "decreased" after losing a life.
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl Search {
+    pub fn new(mem: &Memory) -> Search {
+        Search {
+            snapshot: mem.ram().to_vec(),
+            candidates: (0..mem.size() as u16).collect(),
+        }
+    }
+
+    // Keeps the addresses that match and takes a new snapshot.
+    pub fn refine(&mut self, mem: &Memory, comparison: Comparison) {
+        let snapshot = &self.snapshot;
+        self.candidates
+            .retain(|&addr| comparison.matches(snapshot[addr as usize], mem.get_byte(addr)));
+        self.snapshot = mem.ram().to_vec();
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+    // The value at `addr` when the last snapshot was taken.
+    pub fn previous(&self, addr: u16) -> u8 {
+        self.snapshot[addr as usize % RAM_SIZE]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    // Written every frame, so the game cannot change it.
+    Pin,
+    // Written once when the ROM is loaded or reset, e.g. to patch out code.
+    Patch,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub kind: CheatKind,
+    pub addr: u16,
+    pub value: u8,
+    pub name: String,
+}
+
+// The cheats for one ROM. They are kept in a text file named after the ROM's
+// SHA-1, one per line:
+//
+//     # comment
+//     pin 0x2F0 0x03 lives
+//     patch 0x23A 0x00 no damage
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+pub fn cheat_path(dir: &str, rom: &[u8]) -> String {
+    Path::new(dir)
+        .join(format!("{}.cht", sha1_hex(rom)))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats { cheats: Vec::new() }
+    }
+
+    // A missing file has no cheats.
+    pub fn load(path: &str) -> Result<Cheats, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Cheats::parse(&text).map_err(|e| format!("{}: {}", path, e)),
+            Err(_) => Ok(Cheats::new()),
+        }
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(path, self.to_text()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(4, char::is_whitespace);
+            let kind = match fields.next() {
+                Some("pin") => CheatKind::Pin,
+                Some("patch") => CheatKind::Patch,
+                _ => return Err(format!("line {}: expected pin or patch", i + 1)),
+            };
+            let addr = fields.next().and_then(parse_number).filter(|&addr| (addr as usize) < RAM_SIZE);
+            let value = fields.next().and_then(parse_number).filter(|&value| value <= 0xFF);
+            match (addr, value) {
+                (Some(addr), Some(value)) => cheats.add(Cheat {
+                    kind,
+                    addr,
+                    value: value as u8,
+                    name: fields.next().unwrap_or("").trim().to_string(),
+                }),
+                _ => return Err(format!("line {}: expected an address and a byte", i + 1)),
+            }
+        }
+        Ok(cheats)
+    }
+    pub fn to_text(&self) -> String {
+        self.cheats
+            .iter()
+            .map(|cheat| {
+                let kind = match cheat.kind {
+                    CheatKind::Pin => "pin",
+                    CheatKind::Patch => "patch",
+                };
+                format!("{} 0x{:03X} 0x{:02X} {}", kind, cheat.addr, cheat.value, cheat.name)
+                    .trim_end()
+                    .to_string()
+                    + "\n"
+            })
+            .collect()
+    }
+
+    // A cheat for an address replaces the one of the same kind already there.
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats
+            .retain(|old| old.addr != cheat.addr || old.kind != cheat.kind);
+        self.cheats.push(cheat);
+    }
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // The bytes to write whenever the ROM is loaded.
+    pub fn patches(&self) -> Vec<(u16, u8)> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.kind == CheatKind::Patch)
+            .map(|cheat| (cheat.addr, cheat.value))
+            .collect()
+    }
+    // Called once per frame to hold the pinned values.
+    pub fn apply_pins(&self, mem: &mut Memory) {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.kind == CheatKind::Pin) {
+            mem.poke(cheat.addr, cheat.value);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+    use crate::cheat::{cheat_path, Cheat, CheatKind, Cheats, Comparison, Search};
+    use crate::mem::{Memory, RAM_SIZE};
+    use crate::romdb::sha1_hex;
+    use crate::system::System;
+    use crate::timing::{Timing, FIXED_TICKS_PER_FRAME};
+    use std::fs;
+
+    #[test]
+    fn search_narrows_down_to_the_variable() {
+        let mut mem = Memory::new();
+        mem.poke(0xF00, 0x33);
+        mem.poke(0xF01, 0x33);
+        mem.poke(0xF02, 0x37);
+        let mut search = Search::new(&mem);
+        assert_eq!(search.candidates().len(), RAM_SIZE);
+
+        search.refine(&mem, Comparison::Equal(0x33));
+        assert_eq!(search.candidates(), [0xF00, 0xF01]);
+
+        // lose a life
+        mem.poke(0xF00, 0x32);
+        mem.poke(0xF02, 0x36);
+        search.refine(&mem, Comparison::Unchanged);
+        assert_eq!(search.candidates(), [0xF01]);
+
+        let mut search = Search::new(&mem);
+        mem.poke(0xF00, 1);
+        mem.poke(0xF01, 4);
+        search.refine(&mem, Comparison::Changed);
+        assert_eq!(search.candidates(), [0xF00, 0xF01]);
+        assert_eq!(search.previous(0xF00), 1);
+        mem.poke(0xF00, 0);
+        mem.poke(0xF01, 5);
+        search.refine(&mem, Comparison::Increased);
+        assert_eq!(search.candidates(), [0xF01]);
+        search.refine(&mem, Comparison::Decreased);
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn cheat_files_round_trip() {
+        let text = "# Pong\npin 0x2F0 0x03 lives\n\npatch 570 255\npin 0xEA0 0 \n";
+        let cheats = Cheats::parse(text).unwrap();
+        assert_eq!(
+            cheats.cheats()[0],
+            Cheat {
+                kind: CheatKind::Pin,
+                addr: 0x2F0,
+                value: 3,
+                name: "lives".to_string(),
+            }
+        );
+        assert_eq!(cheats.patches(), [(0x23A, 0xFF)]);
+        assert_eq!(
+            cheats.to_text(),
+            "pin 0x2F0 0x03 lives\npatch 0x23A 0xFF\npin 0xEA0 0x00\n"
+        );
+        assert_eq!(Cheats::parse(&cheats.to_text()).unwrap(), cheats);
+
+        assert!(Cheats::parse("freeze 0x200 1").is_err());
+        assert!(Cheats::parse("pin 0x1000 1").is_err());
+        assert!(Cheats::parse("pin 0x200 0x100").is_err());
+        assert!(Cheats::parse("pin 0x200").is_err());
+
+        let dir = std::env::temp_dir().join(format!("chip8_cheats_{}", std::process::id()));
+        let path = cheat_path(&dir.to_string_lossy(), &[0x12, 0x00]);
+        assert!(path.ends_with(&format!("{}.cht", sha1_hex(&[0x12, 0x00]))));
+        assert_eq!(Cheats::load(&path).unwrap(), Cheats::new());
+        cheats.save(&path).unwrap();
+        assert_eq!(Cheats::load(&path).unwrap(), cheats);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pins_hold_values_and_patches_survive_resets() {
+        // loop: I = 0xF00; V0 = [I]; V0 += 1; I = 0xF00; [I] = V0
+        let rom = vec![0xAF, 0x00, 0xF0, 0x65, 0x70, 0x01, 0xAF, 0x00, 0xF0, 0x55, 0x12, 0x00];
+        let mut emu = System::new();
+        emu.set_trace(false);
+        emu.load_rom(rom).unwrap();
+
+        let mut cheats = Cheats::new();
+        cheats.add(Cheat {
+            kind: CheatKind::Pin,
+            addr: 0xF00,
+            value: 9,
+            name: String::new(),
+        });
+        // ADD V0, 1 becomes ADD V0, 0
+        cheats.add(Cheat {
+            kind: CheatKind::Patch,
+            addr: 0x205,
+            value: 0,
+            name: String::new(),
+        });
+        emu.set_patches(cheats.patches());
+        assert_eq!(emu.memory().get_byte(0x205), 0);
+        emu.reset();
+        assert_eq!(emu.memory().get_byte(0x205), 0);
+
+        emu.set_patches(Vec::new());
+        emu.reset();
+        // each loop adds one to the pinned value, which is put back every frame
+        for _ in 0..3 {
+            cheats.apply_pins(emu.memory_mut());
+            for _ in 0..6 {
+                emu.tick();
+            }
+            assert_eq!(emu.memory().get_byte(0xF00), 10);
+        }
+
+        // pins are written every 60 Hz frame, also by a ROM that never draws
+        emu.set_timing(Timing::Fixed);
+        let mut frames = 0;
+        let mut restored = 0;
+        for _ in 0..60 {
+            emu.tick();
+            if emu.frame_ended() {
+                if emu.memory().get_byte(0xF00) != 9 {
+                    restored += 1;
+                }
+                cheats.apply_pins(emu.memory_mut());
+                assert_eq!(emu.memory().get_byte(0xF00), 9);
+                frames += 1;
+            }
+        }
+        assert_eq!(frames, 60 / FIXED_TICKS_PER_FRAME);
+        assert!(restored > 0);
+
+        // the same address and kind replaces the cheat
+        cheats.add(Cheat {
+            kind: CheatKind::Pin,
+            addr: 0xF00,
+            value: 1,
+            name: String::new(),
+        });
+        assert_eq!(cheats.cheats().len(), 2);
+        cheats.clear();
+        assert!(cheats.is_empty());
+    }
+}
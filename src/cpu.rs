@@ -1,8 +1,32 @@
-use std::usize;
+use crate::bus::Bus;
+use crate::platform::Quirks;
 
-use crate::mem;
+// What this interpreter has always allowed, and the size of `Cpu::stack`.
+pub const DEFAULT_STACK_DEPTH: usize = 64;
+// The VIP interpreter keeps return addresses at 0xEA0-0xECF, growing down
+// from 0xECF, two bytes each with the high byte first.
+pub const VIP_STACK_TOP: u16 = 0xED0;
+pub const MAX_MEMORY_STACK_DEPTH: usize = 24;
 
-#[derive(Debug)]
+// A CALL with the stack full or a RET with it empty. Either is skipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackFault {
+    Overflow(u16),
+    Underflow(u16),
+}
+
+impl std::fmt::Display for StackFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StackFault::Overflow(pc) => write!(f, "Stack overflow at 0x{:03X}", pc),
+            StackFault::Underflow(pc) => write!(f, "Stack underflow at 0x{:03X}", pc),
+        }
+    }
+}
+
+// 8XY6/8XYE shift VY into VX, or VX in place with the shift quirk.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
     Invalid(u16),
     ClearScreen,
@@ -27,7 +51,7 @@ pub enum Instruction {
     SetIndexRegister(u16),   // ANNN
     V0Jump(u16),             // BNNN
     VxRand(u16, u16),        // CXNN
-    Draw(u8, u8, u16),       // DXYN
+    Draw(u16, u16, u16),     // DXYN
     KeyVxSkip(u16),          // EX9E
     KeyNotVxSkip(u16),       // EXA1
     SetTimerVx(u16),         // FX07
@@ -36,11 +60,55 @@ pub enum Instruction {
     SetSoundTimerVx(u16),    // FX18
     AddIVx(u16),             // FX1E
     SetISprite(u16),         // FX29
+    SetILargeSprite(u16),    // FX30
     BCDVX(u16),              // FX33
     RegDump(u16),            // FX55
     RegLoad(u16),            // FX65
 }
 
+impl Instruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Invalid(..) => "Invalid",
+            Instruction::ClearScreen => "ClearScreen",
+            Instruction::Return => "Return",
+            Instruction::Jump(..) => "Jump",
+            Instruction::Call(..) => "Call",
+            Instruction::VxNNSkip(..) => "VxNNSkip",
+            Instruction::VxNNNotSkip(..) => "VxNNNotSkip",
+            Instruction::VxVySkip(..) => "VxVySkip",
+            Instruction::SetRegister(..) => "SetRegister",
+            Instruction::AddRegister(..) => "AddRegister",
+            Instruction::VxSetVy(..) => "VxSetVy",
+            Instruction::VxBitOrVy(..) => "VxBitOrVy",
+            Instruction::VxBitAndVy(..) => "VxBitAndVy",
+            Instruction::VxBitXOrVy(..) => "VxBitXOrVy",
+            Instruction::VxBitAddVy(..) => "VxBitAddVy",
+            Instruction::VxSubVy(..) => "VxSubVy",
+            Instruction::VxBitShiftRVy(..) => "VxBitShiftRVy",
+            Instruction::VxMinusVy(..) => "VxMinusVy",
+            Instruction::VxBitShiftLVy(..) => "VxBitShiftLVy",
+            Instruction::VxNotVySkip(..) => "VxNotVySkip",
+            Instruction::SetIndexRegister(..) => "SetIndexRegister",
+            Instruction::V0Jump(..) => "V0Jump",
+            Instruction::VxRand(..) => "VxRand",
+            Instruction::Draw(..) => "Draw",
+            Instruction::KeyVxSkip(..) => "KeyVxSkip",
+            Instruction::KeyNotVxSkip(..) => "KeyNotVxSkip",
+            Instruction::SetTimerVx(..) => "SetTimerVx",
+            Instruction::GetKeyVx(..) => "GetKeyVx",
+            Instruction::GetTimerVx(..) => "GetTimerVx",
+            Instruction::SetSoundTimerVx(..) => "SetSoundTimerVx",
+            Instruction::AddIVx(..) => "AddIVx",
+            Instruction::SetISprite(..) => "SetISprite",
+            Instruction::SetILargeSprite(..) => "SetILargeSprite",
+            Instruction::BCDVX(..) => "BCDVX",
+            Instruction::RegDump(..) => "RegDump",
+            Instruction::RegLoad(..) => "RegLoad",
+        }
+    }
+}
+
 impl std::fmt::Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -49,38 +117,7 @@ impl std::fmt::Display for Instruction {
             Instruction::Return => write!(f, "returning"),
             Instruction::Jump(x) => write!(f, "jumping to 0x{:x}", x),
             Instruction::Call(x) => write!(f, "calling 0x{:x}", x),
-            _ => write!(f,"{:?}",self)
-            /*
-            Instruction::VxNNSkip(x, nn) => todo!(),
-            Instruction::VxNNNotSkip(_, _) => todo!(),
-            Instruction::VxVySkip(_, _) => todo!(),
-            Instruction::SetRegister(_, _) => todo!(),
-            Instruction::AddRegister(_, _) => todo!(),
-            Instruction::VxSetVy(_, _) => todo!(),
-            Instruction::VxBitOrVy(_, _) => todo!(),
-            Instruction::VxBitAndVy(_, _) => todo!(),
-            Instruction::VxBitXOrVy(_, _) => todo!(),
-            Instruction::VxBitAddVy(_, _) => todo!(),
-            Instruction::VxSubVy(_, _) => todo!(),
-            Instruction::VxBitShiftRVy(_, _) => todo!(),
-            Instruction::VxMinusVy(_, _) => todo!(),
-            Instruction::VxBitShiftLVy(_, _) => todo!(),
-            Instruction::VxNotVySkip(_, _) => todo!(),
-            Instruction::SetIndexRegister(_) => todo!(),
-            Instruction::V0Jump(_) => todo!(),
-            Instruction::VxRand(_, _) => todo!(),
-            Instruction::Draw(_, _, _) => todo!(),
-            Instruction::KeyVxSkip(_) => todo!(),
-            Instruction::KeyNotVxSkip(_) => todo!(),
-            Instruction::SetTimerVx(_) => todo!(),
-            Instruction::GetKeyVx(_) => todo!(),
-            Instruction::GetTimerVx(_) => todo!(),
-            Instruction::SetSoundTimerVx(_) => todo!(),
-            Instruction::AddIVx(_) => todo!(),
-            Instruction::SetISprite(_) => todo!(),
-            Instruction::BCDVX(_) => todo!(),
-            Instruction::RegDump(_) => todo!(),
-            Instruction::RegLoad(_) => todo!(), */
+            _ => write!(f, "{:?}", self),
         }
     }
 }
@@ -88,9 +125,15 @@ impl std::fmt::Display for Instruction {
 pub struct Cpu {
     pc: u16,
     sp: usize,
-    stack: [u16; 64],
+    // Addresses of the CALLs, unless the stack is kept in memory.
+    stack: [u16; DEFAULT_STACK_DEPTH],
+    stack_depth: usize,
+    // Return addresses go to RAM at VIP_STACK_TOP instead of `stack`.
+    stack_in_memory: bool,
+    stack_fault: Option<StackFault>,
     index: u16,
     v: [u8; 16],
+    quirks: Quirks,
 }
 
 impl Cpu {
@@ -99,11 +142,18 @@ impl Cpu {
             pc: 0x200,
             index: 0,
             sp: 0,
-            stack: [0; 64],
+            stack: [0; DEFAULT_STACK_DEPTH],
+            stack_depth: DEFAULT_STACK_DEPTH,
+            stack_in_memory: false,
+            stack_fault: None,
             v: [0; 16],
+            quirks: Quirks::DEFAULT,
         }
     }
 
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
     pub fn index(&self) -> u16 {
         self.index
     }
@@ -121,13 +171,61 @@ impl Cpu {
         (n3, n)
     }
 
-    pub fn inc_pc(&mut self) {
-        self.pc += 2;
+    fn inc_pc(&mut self) {
+        self.pc = self.pc.wrapping_add(2) & 0x0FFF;
     }
-    pub fn return_to_sp(&mut self) {
+    fn return_to_sp(&mut self, bus: &mut impl Bus) {
+        if self.sp == 0 {
+            self.fault(StackFault::Underflow(self.pc));
+            return;
+        }
         self.sp -= 1;
-        self.pc = self.stack[self.sp];
-        self.pc += 2;
+        if self.stack_in_memory {
+            let addr = Cpu::memory_stack_entry(self.sp);
+            self.pc = ((bus.read(addr) as u16) << 8 | bus.read(addr + 1) as u16) & 0x0FFF;
+        } else {
+            self.pc = self.stack[self.sp];
+            self.inc_pc();
+        }
+    }
+    fn fault(&mut self, fault: StackFault) {
+        self.stack_fault = Some(fault);
+        self.inc_pc();
+    }
+
+    // Where entry `i` of a stack in memory is kept.
+    pub fn memory_stack_entry(i: usize) -> u16 {
+        VIP_STACK_TOP - 2 * (i as u16 + 1)
+    }
+    pub fn stack_depth(&self) -> usize {
+        self.stack_depth
+    }
+    // Calls nested deeper than `depth` overflow. In memory at most
+    // MAX_MEMORY_STACK_DEPTH fit.
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack_depth = depth.clamp(1, DEFAULT_STACK_DEPTH);
+    }
+    pub fn stack_in_memory(&self) -> bool {
+        self.stack_in_memory
+    }
+    pub fn set_stack_in_memory(&mut self, in_memory: bool) {
+        self.stack_in_memory = in_memory;
+    }
+    fn max_depth(&self) -> usize {
+        match self.stack_in_memory {
+            true => self.stack_depth.min(MAX_MEMORY_STACK_DEPTH),
+            false => self.stack_depth,
+        }
+    }
+    pub fn take_stack_fault(&mut self) -> Option<StackFault> {
+        self.stack_fault.take()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
 
     pub fn set_index(&mut self, addr: u16) {
@@ -144,15 +242,269 @@ impl Cpu {
     pub fn jump(&mut self, addr: u16) {
         self.pc = addr
     }
-    pub fn jump_store(&mut self, addr: u16) {
-        self.stack[self.sp] = self.pc;
+    fn jump_store(&mut self, addr: u16, bus: &mut impl Bus) {
+        if self.sp >= self.max_depth() {
+            self.fault(StackFault::Overflow(self.pc));
+            return;
+        }
+        if self.stack_in_memory {
+            let entry = Cpu::memory_stack_entry(self.sp);
+            let ret = self.pc.wrapping_add(2) & 0x0FFF;
+            bus.write(entry, (ret >> 8) as u8);
+            bus.write(entry + 1, ret as u8);
+        } else {
+            self.stack[self.sp] = self.pc;
+        }
         self.sp += 1;
         self.pc = addr & 0x0FFF;
     }
 
-    pub fn fetch_decode(&self, mem: &mem::Memory) -> Instruction {
-        let opcode = mem.get_instruction(self.pc);
-        //println!("0x{:x}",opcode);
+    // Fetches, decodes and executes one instruction, returning it.
+    pub fn step(&mut self, bus: &mut impl Bus) -> Instruction {
+        let instr = self.fetch_decode(bus);
+
+        match instr {
+            Instruction::Invalid(_) => self.inc_pc(),
+            Instruction::ClearScreen => {
+                self.inc_pc();
+                bus.clear_screen();
+            }
+            Instruction::Return => self.return_to_sp(bus),
+            Instruction::Jump(addr) => self.jump(addr),
+            Instruction::Call(addr) => self.jump_store(addr, bus),
+            Instruction::Draw(x, y, n) => {
+                self.inc_pc();
+                let (x, y) = (self.get_v(x as usize), self.get_v(y as usize));
+                match bus.draw_sprite(x, y, n, self.index()) {
+                    true => self.set_v(1, 0xF),
+                    false => self.set_v(0, 0xF),
+                }
+            }
+            Instruction::VxNNSkip(x, nn) => {
+                self.inc_pc();
+                if self.get_v(x as usize) == nn as u8 {
+                    self.inc_pc();
+                }
+            }
+            Instruction::VxNNNotSkip(x, nn) => {
+                self.inc_pc();
+                if self.get_v(x as usize) != nn as u8 {
+                    self.inc_pc();
+                }
+            }
+            Instruction::VxVySkip(x, y) => {
+                self.inc_pc();
+                if self.get_v(x as usize) == self.get_v(y as usize) {
+                    self.inc_pc();
+                }
+            }
+            Instruction::SetRegister(x, nn) => {
+                self.inc_pc();
+                //println!("Setting register: 0x{:x} to value 0x{:x}", x, nn);
+                self.set_v(nn as u8, x as usize);
+            }
+            Instruction::AddRegister(x, nn) => {
+                self.inc_pc();
+                let x = x as usize;
+                let new_val = self.get_v(x).wrapping_add(nn as u8);
+                self.set_v(new_val, x);
+            }
+            Instruction::VxSetVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let y = y as usize;
+                let y_val = self.get_v(y);
+                self.set_v(y_val, x);
+            }
+            Instruction::VxBitOrVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(x);
+                let val_y = self.get_v(y as usize);
+                self.set_v(val_x | val_y, x);
+                if self.quirks.logic {
+                    self.set_v(0, 0xF);
+                }
+            }
+            Instruction::VxBitAndVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(x);
+                let val_y = self.get_v(y as usize);
+                self.set_v(val_x & val_y, x);
+                if self.quirks.logic {
+                    self.set_v(0, 0xF);
+                }
+            }
+            Instruction::VxBitXOrVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(x);
+                let val_y = self.get_v(y as usize);
+                self.set_v(val_x ^ val_y, x);
+                if self.quirks.logic {
+                    self.set_v(0, 0xF);
+                }
+            }
+            Instruction::VxBitAddVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(x);
+                let val_y = self.get_v(y as usize);
+                let (result, carry) = val_x.overflowing_add(val_y);
+                self.set_v(result, x);
+                self.set_v(carry as u8, 0xF);
+            }
+            Instruction::VxSubVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(x);
+                let val_y = self.get_v(y as usize);
+                let (result, borrow) = val_x.overflowing_sub(val_y);
+                self.set_v(result, x);
+                self.set_v(!borrow as u8, 0xF);
+            }
+            Instruction::VxBitShiftRVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(if self.quirks.shift { x } else { y as usize });
+                self.set_v(val_x >> 1, x);
+                self.set_v(val_x & 0x01, 0xF);
+            }
+            Instruction::VxBitShiftLVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(if self.quirks.shift { x } else { y as usize });
+                self.set_v(val_x << 1, x);
+                self.set_v((val_x & 0x80) >> 7, 0xF);
+            }
+            Instruction::VxMinusVy(x, y) => {
+                self.inc_pc();
+                let x = x as usize;
+                let val_x = self.get_v(x);
+                let val_y = self.get_v(y as usize);
+                let (result, borrow) = val_y.overflowing_sub(val_x);
+                self.set_v(result, x);
+                self.set_v(!borrow as u8, 0xF);
+            }
+            Instruction::VxNotVySkip(x, y) => {
+                self.inc_pc();
+                let val_x = self.get_v(x as usize);
+                let val_y = self.get_v(y as usize);
+                if val_x != val_y {
+                    self.inc_pc()
+                }
+            }
+            Instruction::SetIndexRegister(nnn) => {
+                self.inc_pc();
+                self.set_index(nnn);
+            }
+            Instruction::V0Jump(addr) => {
+                let offset = if self.quirks.jump { self.get_v((addr >> 8) as usize) } else { self.get_v(0) };
+                self.jump((addr + offset as u16) & 0x0FFF)
+            }
+            Instruction::VxRand(x, nn) => {
+                self.inc_pc();
+                self.set_v(bus.random_byte() & nn as u8, x as usize);
+            }
+            Instruction::KeyVxSkip(x) => {
+                self.inc_pc();
+                let vx_key = self.get_v(x as usize);
+                if vx_key <= 0xF && bus.is_key_pressed(vx_key) {
+                    self.inc_pc();
+                }
+            }
+            Instruction::KeyNotVxSkip(x) => {
+                self.inc_pc();
+                let vx_key = self.get_v(x as usize);
+                if vx_key <= 0xF && !bus.is_key_pressed(vx_key) {
+                    self.inc_pc();
+                }
+            }
+            Instruction::GetKeyVx(x) => {
+                if let Some(key) = bus.wait_for_key() {
+                    self.set_v(key, x as usize);
+                    self.inc_pc();
+                }
+            }
+            Instruction::SetTimerVx(x) => {
+                self.set_v(bus.delay_timer(), x as usize);
+                self.inc_pc()
+            }
+            Instruction::GetTimerVx(x) => {
+                bus.set_delay_timer(self.get_v(x as usize));
+                self.inc_pc()
+            }
+            Instruction::SetSoundTimerVx(x) => {
+                bus.set_sound_timer(self.get_v(x as usize));
+                self.inc_pc()
+            }
+            Instruction::AddIVx(x) => {
+                let vx = self.get_v(x as usize) as u16;
+                let new_value = vx.wrapping_add(self.index());
+                if new_value > 0xFFF {
+                    self.set_v(1, 0xF)
+                } else {
+                    self.set_v(0, 0xF)
+                }
+                self.set_index(new_value);
+                self.inc_pc();
+            }
+            Instruction::SetISprite(x) => {
+                let addr = bus.font_address(self.get_v(x as usize));
+                self.set_index(addr);
+                self.inc_pc()
+            }
+            // Fonts without large glyphs leave I alone.
+            Instruction::SetILargeSprite(x) => {
+                if let Some(addr) = bus.large_font_address(self.get_v(x as usize)) {
+                    self.set_index(addr);
+                }
+                self.inc_pc()
+            }
+            Instruction::BCDVX(x) => {
+                let i = self.index();
+                let vx = self.get_v(x as usize);
+                bus.write(i, vx / 100);
+                bus.write(i.wrapping_add(1), (vx / 10) % 10);
+                bus.write(i.wrapping_add(2), (vx % 100) % 10);
+                //println!("vx: {} i: {} i+1: {} i+2: {}",vx,vx / 100,(vx / 10) % 10, (vx % 100) % 10);
+                self.inc_pc();
+            }
+            Instruction::RegDump(x) => {
+                for vx in 0..x + 1 {
+                    let i = self.index().wrapping_add(vx);
+                    bus.write(i, self.get_v(vx as usize));
+                }
+                self.increment_index_after_transfer(x);
+                self.inc_pc()
+            }
+
+            Instruction::RegLoad(x) => {
+                for vx in 0..x + 1 {
+                    let i = self.index().wrapping_add(vx);
+                    self.set_v(bus.read(i), vx as usize);
+                }
+                self.increment_index_after_transfer(x);
+                self.inc_pc()
+            }
+        }
+        instr
+    }
+
+    fn increment_index_after_transfer(&mut self, x: u16) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
+        let increment = if self.quirks.memory_increment_by_x { x } else { x + 1 };
+        self.set_index(self.index().wrapping_add(increment));
+    }
+
+    pub fn fetch_decode(&self, bus: &mut impl Bus) -> Instruction {
+        bus.fetch_decoded(self.pc)
+    }
+
+    pub fn decode(opcode: u16) -> Instruction {
         match opcode & 0xF000 {
             0x0000 => {
                 match opcode {
@@ -211,22 +563,21 @@ impl Cpu {
                 }
             }
             0xA000 => Instruction::SetIndexRegister(opcode & 0x0FFF),
-            0xB000 => Instruction::V0Jump((self.v[0] as u16) + opcode & 0x0FFF),
+            0xB000 => Instruction::V0Jump(opcode & 0x0FFF),
             0xC000 => {
                 let (x, nn) = Self::unpack2(opcode);
-                let random_factor: u16 = (rand::random::<f32>() * 256f32) as u16;
-                Instruction::VxRand(x, nn & random_factor)
+                Instruction::VxRand(x, nn)
             }
             0xD000 => {
                 let (x, y, n) = Self::unpack3(opcode);
-                Instruction::Draw(self.v[x as usize], self.v[y as usize], n)
+                Instruction::Draw(x, y, n)
             }
             0xE000 => {
                 let x = (opcode & 0x0F00) >> 8;
                 match opcode & 0x00FF {
                     0x009E => Instruction::KeyVxSkip(x),
                     0x00A1 => Instruction::KeyNotVxSkip(x),
-                    _ => (Instruction::Invalid(opcode)),
+                    _ => Instruction::Invalid(opcode),
                 }
             }
             0xF000 => {
@@ -238,6 +589,7 @@ impl Cpu {
                     0x0018 => Instruction::SetSoundTimerVx(x),
                     0x001E => Instruction::AddIVx(x),
                     0x0029 => Instruction::SetISprite(x),
+                    0x0030 => Instruction::SetILargeSprite(x),
                     0x0033 => Instruction::BCDVX(x),
                     0x0055 => Instruction::RegDump(x),
                     0x0065 => Instruction::RegLoad(x),
@@ -247,4 +599,28 @@ impl Cpu {
             _ => Instruction::Invalid(opcode), // machine code
         }
     }
+
+    pub fn v(&self) -> [u8; 16] {
+        self.v
+    }
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+    pub fn stack(&self) -> [u16; DEFAULT_STACK_DEPTH] {
+        self.stack
+    }
+    pub fn set_stack(&mut self, stack: [u16; DEFAULT_STACK_DEPTH], sp: usize) {
+        self.stack = stack;
+        self.sp = sp;
+    }
+    // Moves the stack pointer within the current depth, e.g. from a
+    // debugger. The entries are left as they are; with the stack in memory
+    // they are whatever is in RAM at `memory_stack_entry`.
+    pub fn set_sp(&mut self, sp: usize) -> Result<(), String> {
+        if sp > self.max_depth() {
+            return Err(format!("the stack is {} deep", self.max_depth()));
+        }
+        self.sp = sp;
+        Ok(())
+    }
 }
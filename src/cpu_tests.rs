@@ -1,7 +1,11 @@
 #[cfg(test)]
 mod tests {
+    use crate::bus::Bus;
+    use crate::cpu::{Cpu, Instruction, StackFault, MAX_MEMORY_STACK_DEPTH};
+    use crate::keypad::KeyWait;
+    use crate::platform::Quirks;
     use crate::system;
-    use std::collections::HashMap;
+    use crate::test_machine::Machine;
 
     // SETUP
     fn vx_test(instruction: u16, val: u8) -> system::System {
@@ -13,14 +17,10 @@ mod tests {
         emu.load_test_v(vec![
             0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xE, 0xF,
         ]);
-        emu.tick(&keys_setup());
+        emu.tick();
         emu.test_eq_v(0x0, val);
         emu
     }
-    fn keys_setup() -> HashMap<sdl2::keyboard::Keycode, i32> {
-        let keys: HashMap<sdl2::keyboard::Keycode, i32> = HashMap::new();
-        keys
-    }
     // VX TESTS
 
     #[test]
@@ -65,4 +65,724 @@ mod tests {
     fn vx_bit_shift_l_vy(){
         vx_test(0x802E, 0b0010);
     }
+
+    // INSTRUCTION TESTS
+
+    #[test]
+    fn invalid_opcode_is_skipped() {
+        Machine::new().program(&[0x0123]).run(1).check();
+    }
+
+    #[test]
+    fn clear_screen() {
+        Machine::new()
+            .program(&[0x00E0])
+            .pixel(0, 0)
+            .pixel(63, 31)
+            .run(1)
+            .cleared_screen()
+            .check();
+    }
+
+    #[test]
+    fn call() {
+        Machine::new()
+            .program(&[0x2ABC])
+            .run(1)
+            .pc(0xABC)
+            .stack(&[0x200])
+            .check();
+    }
+
+    #[test]
+    fn call_and_return() {
+        Machine::new()
+            .program(&[0x2300])
+            .mem(0x300, &[0x00, 0xEE])
+            .run(2)
+            .pc(0x202)
+            .stack(&[0x200])
+            .sp(0)
+            .check();
+    }
+
+    #[test]
+    fn nested_return() {
+        Machine::new()
+            .pc(0x400)
+            .program(&[0x00EE])
+            .stack(&[0x200, 0x3FE])
+            .run(1)
+            .pc(0x400)
+            .sp(1)
+            .check();
+    }
+
+    #[test]
+    fn jump() {
+        Machine::new().program(&[0x1234]).run(1).pc(0x234).check();
+    }
+
+    #[test]
+    fn vx_nn_skip() {
+        Machine::new().program(&[0x3542]).v(5, 0x42).run(1).skipped().check();
+        Machine::new().program(&[0x3542]).v(5, 0x41).run(1).check();
+    }
+
+    #[test]
+    fn vx_nn_not_skip() {
+        Machine::new().program(&[0x4542]).v(5, 0x41).run(1).skipped().check();
+        Machine::new().program(&[0x4542]).v(5, 0x42).run(1).check();
+    }
+
+    #[test]
+    fn vx_vy_skip() {
+        Machine::new().program(&[0x5120]).v(1, 7).v(2, 7).run(1).skipped().check();
+        Machine::new().program(&[0x5120]).v(1, 7).v(2, 8).run(1).check();
+    }
+
+    #[test]
+    fn vx_not_vy_skip() {
+        Machine::new().program(&[0x9120]).v(1, 7).v(2, 8).run(1).skipped().check();
+        Machine::new().program(&[0x9120]).v(1, 7).v(2, 7).run(1).check();
+    }
+
+    #[test]
+    fn set_register() {
+        Machine::new().program(&[0x6A5C]).run(1).v(0xA, 0x5C).check();
+    }
+
+    #[test]
+    fn add_register_wraps_without_carry() {
+        Machine::new()
+            .program(&[0x7102])
+            .v(1, 0xFF)
+            .run(1)
+            .v(1, 0x01)
+            .check();
+    }
+
+    #[test]
+    fn vx_bit_add_vy_carry() {
+        Machine::new()
+            .program(&[0x8014])
+            .v(0, 0xFF)
+            .v(1, 0x02)
+            .run(1)
+            .v(0, 0x01)
+            .vf(1)
+            .check();
+    }
+
+    #[test]
+    fn vx_bit_add_vy_flag_wins_over_result() {
+        Machine::new()
+            .program(&[0x8F14])
+            .v(0xF, 0x10)
+            .v(1, 0x02)
+            .run(1)
+            .vf(0)
+            .check();
+    }
+
+    #[test]
+    fn vx_sub_vy_borrow() {
+        Machine::new()
+            .program(&[0x8015])
+            .v(0, 0x01)
+            .v(1, 0x02)
+            .run(1)
+            .v(0, 0xFF)
+            .vf(0)
+            .check();
+    }
+
+    #[test]
+    fn vx_sub_vy_equal_has_no_borrow() {
+        Machine::new()
+            .program(&[0x8015])
+            .v(0, 0x33)
+            .v(1, 0x33)
+            .run(1)
+            .v(0, 0x00)
+            .vf(1)
+            .check();
+    }
+
+    #[test]
+    fn vx_bit_shift_r_vy_flag() {
+        Machine::new()
+            .program(&[0x8016])
+            .v(0, 0x03)
+            .run(1)
+            .v(0, 0x01)
+            .vf(1)
+            .check();
+    }
+
+    #[test]
+    fn vx_minus_vy_borrow() {
+        Machine::new()
+            .program(&[0x8017])
+            .v(0, 0x03)
+            .v(1, 0x01)
+            .run(1)
+            .v(0, 0xFE)
+            .vf(0)
+            .check();
+    }
+
+    #[test]
+    fn vx_bit_shift_l_vy_carry() {
+        Machine::new()
+            .program(&[0x801E])
+            .v(0, 0x81)
+            .run(1)
+            .v(0, 0x02)
+            .vf(1)
+            .check();
+        Machine::new()
+            .program(&[0x801E])
+            .v(0, 0x41)
+            .run(1)
+            .v(0, 0x82)
+            .vf(0)
+            .check();
+    }
+
+    #[test]
+    fn set_index_register() {
+        Machine::new().program(&[0xA123]).run(1).index(0x123).check();
+    }
+
+    #[test]
+    fn v0_jump() {
+        Machine::new().program(&[0xB300]).v(0, 0x10).run(1).pc(0x310).check();
+    }
+
+    #[test]
+    fn v0_jump_wraps_around_memory() {
+        Machine::new().program(&[0xBFFF]).v(0, 0xFF).run(1).pc(0x0FE).check();
+    }
+
+    #[test]
+    fn vx_rand_masks_with_nn() {
+        Machine::new().program(&[0xC000]).v(0, 0xAA).run(1).v(0, 0).check();
+
+        let outcome = Machine::new().program(&[0xC10F]).run(1);
+        let v1 = outcome.actual().v[1];
+        assert_eq!(v1 & 0xF0, 0);
+        outcome.v(1, v1).check();
+    }
+
+    #[test]
+    fn draw() {
+        // Font glyph "0" at address 0: F0 90 90 90 F0
+        let mut outcome = Machine::new()
+            .program(&[0xD015])
+            .v(0, 2)
+            .v(1, 3)
+            .index(0)
+            .run(1)
+            .vf(0);
+        for x in 0..4 {
+            outcome = outcome.pixel(2 + x, 3, true).pixel(2 + x, 7, true);
+        }
+        for y in 4..7 {
+            outcome = outcome.pixel(2, y, true).pixel(5, y, true);
+        }
+        outcome.check();
+    }
+
+    #[test]
+    fn draw_collision() {
+        Machine::new()
+            .program(&[0xD011])
+            .mem(0x300, &[0x80])
+            .index(0x300)
+            .pixel(0, 0)
+            .run(1)
+            .pixel(0, 0, false)
+            .vf(1)
+            .check();
+    }
+
+    #[test]
+    fn draw_wraps_around_screen() {
+        Machine::new()
+            .program(&[0xD011])
+            .mem(0x300, &[0xF0])
+            .index(0x300)
+            .v(0, 62)
+            .v(1, 31)
+            .run(1)
+            .pixel(62, 31, true)
+            .pixel(63, 31, true)
+            .pixel(0, 31, true)
+            .pixel(1, 31, true)
+            .check();
+    }
+
+    #[test]
+    fn key_vx_skip() {
+        Machine::new().program(&[0xE09E]).v(0, 5).key(5).run(1).skipped().check();
+        Machine::new().program(&[0xE09E]).v(0, 5).key(4).run(1).check();
+    }
+
+    #[test]
+    fn key_not_vx_skip() {
+        Machine::new().program(&[0xE0A1]).v(0, 5).key(4).run(1).skipped().check();
+        Machine::new().program(&[0xE0A1]).v(0, 5).key(5).run(1).check();
+    }
+
+    #[test]
+    fn set_timer_vx() {
+        Machine::new()
+            .program(&[0xF307])
+            .delay_timer(10)
+            .run(1)
+            .v(3, 10)
+            .check();
+    }
+
+    #[test]
+    fn get_key_vx_waits_for_key() {
+        Machine::new().program(&[0xF20A]).run(3).pc(0x200).check();
+    }
+
+    #[test]
+    fn get_key_vx() {
+        Machine::new().program(&[0xF20A]).key_wait(KeyWait::Press).key(7).run(1).v(2, 7).check();
+    }
+
+    #[test]
+    fn get_key_vx_waits_for_release() {
+        Machine::new().program(&[0xF20A]).key(7).run(3).pc(0x200).check();
+    }
+
+    #[test]
+    fn get_timer_vx() {
+        Machine::new()
+            .program(&[0xF415])
+            .v(4, 20)
+            .run(1)
+            .delay_timer(19)
+            .check();
+    }
+
+    #[test]
+    fn set_sound_timer_vx() {
+        Machine::new()
+            .program(&[0xF418])
+            .v(4, 20)
+            .run(1)
+            .sound_timer(19)
+            .check();
+    }
+
+    #[test]
+    fn timers_count_down_to_zero() {
+        Machine::new()
+            .program(&[0x6000, 0x6000, 0x6000])
+            .delay_timer(2)
+            .sound_timer(1)
+            .run(3)
+            .delay_timer(0)
+            .sound_timer(0)
+            .check();
+    }
+
+    #[test]
+    fn add_i_vx() {
+        Machine::new()
+            .program(&[0xF11E])
+            .index(0x100)
+            .v(1, 0x10)
+            .run(1)
+            .index(0x110)
+            .vf(0)
+            .check();
+    }
+
+    #[test]
+    fn add_i_vx_overflow_sets_vf() {
+        Machine::new()
+            .program(&[0xF11E])
+            .index(0xFFF)
+            .v(1, 0x01)
+            .run(1)
+            .index(0x1000)
+            .vf(1)
+            .check();
+    }
+
+    #[test]
+    fn set_i_sprite() {
+        Machine::new().program(&[0xF029]).v(0, 0xA).run(1).index(0x32).check();
+        Machine::new().program(&[0xF029]).v(0, 0x3A).run(1).index(0x32).check();
+    }
+
+    #[test]
+    fn bcd_vx() {
+        Machine::new()
+            .program(&[0xF033])
+            .v(0, 254)
+            .index(0x300)
+            .run(1)
+            .mem(0x300, &[2, 5, 4])
+            .check();
+    }
+
+    #[test]
+    fn reg_dump() {
+        Machine::new()
+            .program(&[0xF255])
+            .v(0, 1)
+            .v(1, 2)
+            .v(2, 3)
+            .v(3, 4)
+            .index(0x300)
+            .run(1)
+            .mem(0x300, &[1, 2, 3])
+            .index(0x303)
+            .check();
+    }
+
+    #[test]
+    fn reg_load() {
+        Machine::new()
+            .program(&[0xF265])
+            .mem(0x300, &[4, 5, 6, 7])
+            .index(0x300)
+            .run(1)
+            .v(0, 4)
+            .v(1, 5)
+            .v(2, 6)
+            .index(0x303)
+            .check();
+    }
+
+    // ROBUSTNESS
+
+    #[test]
+    fn return_with_empty_stack_is_skipped() {
+        Machine::new().program(&[0x00EE]).run(1).check();
+    }
+
+    #[test]
+    fn call_with_full_stack_is_skipped() {
+        Machine::new()
+            .program(&[0x2300])
+            .stack(&[0x200; 64])
+            .run(1)
+            .check();
+    }
+
+    #[test]
+    fn memory_access_wraps_around() {
+        Machine::new()
+            .program(&[0xF233])
+            .v(2, 123)
+            .index(0xFFF)
+            .run(1)
+            .mem(0xFFF, &[1])
+            .mem(0x000, &[2, 3])
+            .check();
+    }
+
+    #[test]
+    fn oversized_rom_is_rejected() {
+        let mut emu = system::System::new();
+        assert!(emu.load_rom(vec![0; 0xE00]).is_ok());
+        assert!(emu.load_rom(vec![0; 0xE01]).is_err());
+    }
+
+    // STACK
+
+    #[test]
+    fn call_past_the_stack_depth_overflows() {
+        let mut emu = Machine::new()
+            .stack_depth(12)
+            .program(&[0x2300])
+            .stack(&[0x200; 12])
+            .run(1)
+            .check();
+        assert_eq!(emu.take_stack_fault(), Some(StackFault::Overflow(0x200)));
+        assert_eq!(emu.take_stack_fault(), None);
+
+        let mut emu = Machine::new().program(&[0x00EE]).run(1).check();
+        assert_eq!(emu.take_stack_fault(), Some(StackFault::Underflow(0x200)));
+    }
+
+    #[test]
+    fn stack_in_memory_holds_return_addresses_at_the_vip_location() {
+        let mut emu = Machine::new()
+            .stack_in_memory()
+            .program(&[0x2300])
+            .mem(0x300, &[0x23, 0x10])
+            .run(2)
+            .pc(0x310)
+            .sp(2)
+            .mem(0xECE, &[0x02, 0x02])
+            .mem(0xECC, &[0x03, 0x02])
+            .check();
+        assert_eq!(emu.call_stack(), [0x200, 0x300]);
+
+        // a program rewriting its return address returns there
+        emu.memory_mut().poke(0xECC, 0x04);
+        emu.memory_mut().poke(0x310, 0x00);
+        emu.memory_mut().poke(0x311, 0xEE);
+        emu.tick();
+        assert_eq!(emu.pc(), 0x402);
+        assert_eq!(emu.call_stack(), [0x200]);
+
+        // at most 24 levels fit below 0xED0
+        let state = Machine::new()
+            .stack_in_memory()
+            .program(&[0x2200])
+            .run(25)
+            .actual();
+        assert_eq!(state.sp, MAX_MEMORY_STACK_DEPTH);
+        assert_eq!(state.ram[0xEA0..0xEA2], [0x02, 0x02]);
+    }
+
+    #[test]
+    fn stack_settings_survive_resets() {
+        let mut emu = system::System::new();
+        emu.set_stack_depth(1);
+        emu.set_stack_in_memory(true);
+        emu.load_rom(vec![0x22, 0x02, 0x22, 0x04]).unwrap();
+        emu.reset();
+        emu.tick();
+        emu.tick();
+        assert_eq!(emu.take_stack_fault(), Some(StackFault::Overflow(0x202)));
+        assert_eq!(emu.memory().get_instruction(0xECE), 0x202);
+    }
+
+    // QUIRKS
+
+    const VIP: Quirks = Quirks {
+        shift: false,
+        memory_increment_by_x: false,
+        memory_leave_i_unchanged: false,
+        wrap: false,
+        jump: false,
+        vblank: true,
+        logic: true,
+    };
+
+    #[test]
+    fn shift_quirk_off_shifts_vy_into_vx() {
+        Machine::new()
+            .quirks(VIP)
+            .program(&[0x8126, 0x834E])
+            .v(2, 0b0000_0101)
+            .v(4, 0b1000_0001)
+            .run(2)
+            .v(1, 0b0000_0010)
+            .v(3, 0b0000_0010)
+            .vf(1)
+            .check();
+    }
+
+    #[test]
+    fn logic_quirk_resets_vf() {
+        for opcode in [0x8121, 0x8122, 0x8123] {
+            Machine::new()
+                .quirks(VIP)
+                .program(&[opcode])
+                .v(1, 0b1100)
+                .v(2, 0b1010)
+                .v(0xF, 7)
+                .run(1)
+                .v(
+                    1,
+                    match opcode & 0xF {
+                        1 => 0b1110,
+                        2 => 0b1000,
+                        _ => 0b0110,
+                    },
+                )
+                .vf(0)
+                .check();
+        }
+    }
+
+    #[test]
+    fn jump_quirk_adds_vx() {
+        Machine::new()
+            .quirks(Quirks { jump: true, ..Quirks::DEFAULT })
+            .program(&[0xB320])
+            .v(0, 0x10)
+            .v(3, 0x04)
+            .run(1)
+            .pc(0x324)
+            .check();
+    }
+
+    #[test]
+    fn memory_quirks_change_how_i_moves() {
+        let increment_by_x = Quirks {
+            memory_increment_by_x: true,
+            ..Quirks::DEFAULT
+        };
+        let leave_i = Quirks {
+            memory_leave_i_unchanged: true,
+            ..Quirks::DEFAULT
+        };
+        for (quirks, index) in [(increment_by_x, 0x302), (leave_i, 0x300)] {
+            Machine::new()
+                .quirks(quirks)
+                .program(&[0xF255])
+                .index(0x300)
+                .v(0, 1)
+                .v(1, 2)
+                .v(2, 3)
+                .run(1)
+                .mem(0x300, &[1, 2, 3])
+                .index(index)
+                .check();
+        }
+    }
+
+    #[test]
+    fn sprites_clip_without_wrap_quirk() {
+        // the "0" glyph drawn at (62, 30) only wraps onto the opposite edges
+        // with the wrap quirk
+        Machine::new()
+            .quirks(VIP)
+            .program(&[0xD125])
+            .v(1, 62)
+            .v(2, 30)
+            .run(1)
+            .pixel(62, 30, true)
+            .pixel(63, 30, true)
+            .pixel(62, 31, true)
+            .vf(0)
+            .check();
+        Machine::new()
+            .program(&[0xD125])
+            .v(1, 62)
+            .v(2, 30)
+            .run(1)
+            .pixel(62, 30, true)
+            .pixel(63, 30, true)
+            .pixel(0, 30, true)
+            .pixel(1, 30, true)
+            .pixel(62, 31, true)
+            .pixel(1, 31, true)
+            .pixel(62, 0, true)
+            .pixel(1, 0, true)
+            .pixel(62, 1, true)
+            .pixel(1, 1, true)
+            .pixel(62, 2, true)
+            .pixel(63, 2, true)
+            .pixel(0, 2, true)
+            .pixel(1, 2, true)
+            .check();
+    }
+
+    // MOCK BUS TESTS
+
+    struct MockBus {
+        ram: [u8; 4096],
+        sprites: Vec<(u8, u8, u16, u16)>,
+        collision: bool,
+        keys: [bool; 16],
+        delay_timer: u8,
+        sound_timer: u8,
+        random: u8,
+    }
+
+    impl MockBus {
+        fn new(program: &[u16]) -> MockBus {
+            let mut ram = [0; 4096];
+            for (i, opcode) in program.iter().enumerate() {
+                ram[0x200 + i * 2] = (opcode >> 8) as u8;
+                ram[0x200 + i * 2 + 1] = (opcode & 0xFF) as u8;
+            }
+            MockBus {
+                ram,
+                sprites: Vec::new(),
+                collision: false,
+                keys: [false; 16],
+                delay_timer: 0,
+                sound_timer: 0,
+                random: 0,
+            }
+        }
+    }
+
+    impl Bus for MockBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.ram[addr as usize]
+        }
+        fn write(&mut self, addr: u16, val: u8) {
+            self.ram[addr as usize] = val
+        }
+        fn clear_screen(&mut self) {
+            self.sprites.clear()
+        }
+        fn draw_sprite(&mut self, x: u8, y: u8, n: u16, addr: u16) -> bool {
+            self.sprites.push((x, y, n, addr));
+            self.collision
+        }
+        fn is_key_pressed(&self, key: u8) -> bool {
+            self.keys[key as usize]
+        }
+        fn delay_timer(&self) -> u8 {
+            self.delay_timer
+        }
+        fn set_delay_timer(&mut self, val: u8) {
+            self.delay_timer = val
+        }
+        fn set_sound_timer(&mut self, val: u8) {
+            self.sound_timer = val
+        }
+        fn random_byte(&mut self) -> u8 {
+            self.random
+        }
+    }
+
+    #[test]
+    fn step_returns_executed_instruction() {
+        let mut cpu = Cpu::new();
+        let mut bus = MockBus::new(&[0x6A5C]);
+        assert_eq!(cpu.step(&mut bus), Instruction::SetRegister(0xA, 0x5C));
+        assert_eq!(cpu.get_v(0xA), 0x5C);
+    }
+
+    #[test]
+    fn draw_goes_through_bus() {
+        let mut cpu = Cpu::new();
+        let mut bus = MockBus::new(&[0xA300, 0xD125]);
+        bus.collision = true;
+        cpu.set_v(10, 1);
+        cpu.set_v(20, 2);
+        cpu.step(&mut bus);
+        cpu.step(&mut bus);
+        assert_eq!(bus.sprites, vec![(10, 20, 5, 0x300)]);
+        assert_eq!(cpu.get_v(0xF), 1);
+    }
+
+    #[test]
+    fn rand_uses_bus_random_byte() {
+        let mut cpu = Cpu::new();
+        let mut bus = MockBus::new(&[0xC10F]);
+        bus.random = 0xAB;
+        cpu.step(&mut bus);
+        assert_eq!(cpu.get_v(1), 0x0B);
+    }
+
+    #[test]
+    fn timers_and_keys_go_through_bus() {
+        let mut cpu = Cpu::new();
+        let mut bus = MockBus::new(&[0x6007, 0xF015, 0xF018, 0xF30A]);
+        bus.keys[0xC] = true;
+        for _ in 0..4 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!((bus.delay_timer, bus.sound_timer), (7, 7));
+        assert_eq!(cpu.get_v(3), 0xC);
+    }
 }
@@ -0,0 +1,264 @@
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use std::collections::BTreeSet;
+
+use crate::cpu::{Cpu, Instruction};
+use crate::disasm;
+use crate::mem::RAM_SIZE;
+use crate::osd::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::system::System;
+
+// The panel sits to the right of the game display, in text rows and columns
+// of the OSD font at half size.
+pub const PANEL_WIDTH: u32 = 512;
+const TEXT_SCALE: i32 = 2;
+const CHAR_WIDTH: i32 = (GLYPH_WIDTH as i32 + 1) * TEXT_SCALE;
+const ROW_HEIGHT: i32 = (GLYPH_HEIGHT as i32 + 2) * TEXT_SCALE;
+const PADDING: i32 = 8;
+
+const BUTTON_ROW: usize = 0;
+const PAUSE_BUTTON: (usize, usize) = (0, 10);
+const STEP_BUTTON: (usize, usize) = (12, 22);
+const REGISTER_ROW: usize = 2;
+// Where the PC is in the source, when symbols are loaded.
+const SOURCE_ROW: usize = 5;
+const CODE_ROW: usize = 7;
+pub const CODE_LINES: usize = 15;
+// Instructions listed before the PC.
+const CODE_BEFORE: u16 = 4;
+const STACK_COLUMN: usize = 40;
+const PANEL_COLUMNS: usize = (PANEL_WIDTH as usize - 2 * PADDING as usize) / CHAR_WIDTH as usize;
+const MEMORY_ROW: usize = 24;
+pub const MEMORY_LINES: usize = 11;
+pub const BYTES_PER_LINE: usize = 16;
+
+const BACKGROUND: Color = Color::RGB(24, 24, 32);
+const TEXT_COLOR: Color = Color::RGB(200, 200, 200);
+const BREAKPOINT_COLOR: Color = Color::RGB(255, 96, 96);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugAction {
+    TogglePause,
+    // Run one instruction and stay paused.
+    Step,
+}
+
+pub fn is_debug_key(keycode: Keycode) -> bool {
+    matches!(
+        keycode,
+        Keycode::F5 | Keycode::F9 | Keycode::F10 | Keycode::PageUp | Keycode::PageDown
+    )
+}
+
+// The debug view: registers, call stack, disassembly and memory of the
+// running `System`, with breakpoints and single stepping. Pausing itself is
+// left to the main loop's clock.
+pub struct Debugger {
+    // Left edge of the panel in the window.
+    x: i32,
+    breakpoints: BTreeSet<u16>,
+    // First address in the memory view.
+    memory_top: u16,
+    step: bool,
+    stepped: bool,
+    // Execution resumes here without stopping at its breakpoint.
+    resume_from: Option<u16>,
+    hit: Option<u16>,
+}
+
+impl Debugger {
+    pub fn new(x: i32) -> Debugger {
+        Debugger {
+            x,
+            breakpoints: BTreeSet::new(),
+            memory_top: 0x200,
+            step: false,
+            stepped: false,
+            resume_from: None,
+            hit: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+    pub fn toggle_breakpoint(&mut self, addr: u16) {
+        let addr = addr & 0x0FFF;
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+        }
+    }
+
+    pub fn request_step(&mut self) {
+        self.step = true;
+    }
+    // Called before each instruction with whether the clock would run it.
+    // A requested step always runs; otherwise a breakpoint at `pc` stops
+    // execution, which `take_hit` reports.
+    pub fn should_run(&mut self, pc: u16, clock_runs: bool) -> bool {
+        if self.step {
+            self.step = false;
+            self.stepped = true;
+            return true;
+        }
+        if !clock_runs {
+            return false;
+        }
+        if self.breakpoints.contains(&pc) && self.resume_from != Some(pc) {
+            self.hit = Some(pc);
+            self.resume_from = Some(pc);
+            return false;
+        }
+        true
+    }
+    // Called after each instruction with the new PC.
+    pub fn ran(&mut self, pc: u16) {
+        self.resume_from = if self.stepped { Some(pc) } else { None };
+        self.stepped = false;
+    }
+    pub fn take_hit(&mut self) -> Option<u16> {
+        self.hit.take()
+    }
+
+    pub fn memory_top(&self) -> u16 {
+        self.memory_top
+    }
+    pub fn scroll_memory(&mut self, lines: i32) {
+        let last = (RAM_SIZE - MEMORY_LINES * BYTES_PER_LINE) as i32;
+        let top = self.memory_top as i32 + lines * BYTES_PER_LINE as i32;
+        self.memory_top = top.max(0).min(last) as u16;
+    }
+
+    // First address of the disassembly for a given PC.
+    pub fn code_start(pc: u16) -> u16 {
+        pc.wrapping_sub(CODE_BEFORE * 2) & 0x0FFF
+    }
+
+    pub fn key(&mut self, keycode: Keycode, pc: u16) -> Option<DebugAction> {
+        match keycode {
+            Keycode::F5 => return Some(DebugAction::TogglePause),
+            Keycode::F10 => return Some(DebugAction::Step),
+            Keycode::F9 => self.toggle_breakpoint(pc),
+            Keycode::PageUp => self.scroll_memory(-(MEMORY_LINES as i32)),
+            Keycode::PageDown => self.scroll_memory(MEMORY_LINES as i32),
+            _ => {}
+        }
+        None
+    }
+
+    // A left click at window coordinates. Clicking a line of the disassembly
+    // toggles its breakpoint.
+    pub fn click(&mut self, x: i32, y: i32, pc: u16) -> Option<DebugAction> {
+        if x < self.x + PADDING || y < PADDING {
+            return None;
+        }
+        let col = ((x - self.x - PADDING) / CHAR_WIDTH) as usize;
+        let row = ((y - PADDING) / ROW_HEIGHT) as usize;
+        let within = |(start, end): (usize, usize)| col >= start && col < end;
+        match row {
+            BUTTON_ROW if within(PAUSE_BUTTON) => Some(DebugAction::TogglePause),
+            BUTTON_ROW if within(STEP_BUTTON) => Some(DebugAction::Step),
+            _ if (CODE_ROW..CODE_ROW + CODE_LINES).contains(&row) && col < STACK_COLUMN => {
+                let line = (row - CODE_ROW) as u16;
+                self.toggle_breakpoint(Debugger::code_start(pc).wrapping_add(line * 2));
+                None
+            }
+            _ => None,
+        }
+    }
+
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>, emu: &System, paused: bool) {
+        let (_, height) = canvas.output_size().unwrap_or((0, 0));
+        canvas.set_draw_color(BACKGROUND);
+        canvas
+            .fill_rect(Rect::new(self.x, 0, PANEL_WIDTH, height.max(1)))
+            .expect("Unable to draw rect!");
+        let text = |canvas: &mut Canvas<T>, col: usize, row: usize, text: &str, color: Color| {
+            let x = self.x + PADDING + col as i32 * CHAR_WIDTH;
+            let y = PADDING + row as i32 * ROW_HEIGHT;
+            osd::draw_text_scaled(canvas, text, x, y, TEXT_SCALE, color);
+        };
+
+        let pause = if paused { "[F5 RUN]" } else { "[F5 PAUSE]" };
+        text(canvas, PAUSE_BUTTON.0, BUTTON_ROW, pause, osd::SELECTED_COLOR);
+        text(canvas, STEP_BUTTON.0, BUTTON_ROW, "[F10 STEP]", osd::SELECTED_COLOR);
+        text(canvas, 26, BUTTON_ROW, if paused { "PAUSED" } else { "RUNNING" }, osd::TEXT_COLOR);
+
+        let cpu = emu.cpu();
+        let (delay, sound) = emu.timers();
+        let registers = format!(
+            "PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}",
+            cpu.pc(),
+            cpu.index(),
+            cpu.sp(),
+            delay,
+            sound
+        );
+        text(canvas, 0, REGISTER_ROW, &registers, TEXT_COLOR);
+        for half in 0..2 {
+            let line: Vec<String> = (half * 8..half * 8 + 8)
+                .map(|i| format!("V{:X} {:02X}", i, cpu.get_v(i)))
+                .collect();
+            text(canvas, 0, REGISTER_ROW + 1 + half, &line.join("  "), TEXT_COLOR);
+        }
+
+        let symbols = emu.symbols();
+        if let Some(source) = symbols.source_line(cpu.pc()) {
+            let source: String = source.chars().take(PANEL_COLUMNS).collect();
+            text(canvas, 0, SOURCE_ROW, &source, osd::SELECTED_COLOR);
+        }
+
+        text(canvas, 0, CODE_ROW - 1, "CODE", osd::TEXT_COLOR);
+        let start = Debugger::code_start(cpu.pc());
+        for (i, line) in disasm::listing(emu.memory(), start, CODE_LINES, symbols).iter().enumerate() {
+            let breakpoint = self.breakpoints.contains(&line.addr);
+            let current = line.addr == cpu.pc();
+            let marker = format!(
+                "{}{}",
+                if breakpoint { '*' } else { ' ' },
+                if current { '>' } else { ' ' }
+            );
+            let color = match (current, breakpoint) {
+                (true, _) => osd::SELECTED_COLOR,
+                (false, true) => BREAKPOINT_COLOR,
+                _ => TEXT_COLOR,
+            };
+            let label = symbols.label(line.addr).map(|label| format!("{}: ", label)).unwrap_or_default();
+            let listing = format!("{}{:03X} {:04X} {}{}", marker, line.addr, line.opcode, label, line.text);
+            let listing: String = listing.chars().take(STACK_COLUMN - 1).collect();
+            text(canvas, 0, CODE_ROW + i, &listing, color);
+        }
+
+        // Innermost call first, each shown as the called address and where
+        // the call was made
+        text(canvas, STACK_COLUMN, CODE_ROW - 1, "CALL STACK", osd::TEXT_COLOR);
+        for (i, addr) in emu.call_stack().iter().rev().take(CODE_LINES).enumerate() {
+            let call = match Cpu::decode(emu.memory().get_instruction(*addr)) {
+                Instruction::Call(target) => symbols.format_addr(target),
+                _ => "?".to_string(),
+            };
+            let frame = format!("{} from {}", call, symbols.format_addr(*addr));
+            let frame: String = frame.chars().take(PANEL_COLUMNS - STACK_COLUMN).collect();
+            text(canvas, STACK_COLUMN, CODE_ROW + i, &frame, TEXT_COLOR);
+        }
+
+        let end = self.memory_top as usize + MEMORY_LINES * BYTES_PER_LINE - 1;
+        let header = format!("MEMORY {:03X}-{:03X}  PGUP/PGDN", self.memory_top, end);
+        text(canvas, 0, MEMORY_ROW - 1, &header, osd::TEXT_COLOR);
+        for line in 0..MEMORY_LINES {
+            let addr = self.memory_top as usize + line * BYTES_PER_LINE;
+            let bytes: Vec<String> = (addr..addr + BYTES_PER_LINE)
+                .map(|addr| format!("{:02X}", emu.memory().get_byte(addr as u16)))
+                .collect();
+            text(canvas, 0, MEMORY_ROW + line, &format!("{:03X} {}", addr, bytes.join(" ")), TEXT_COLOR);
+            // The byte I points at stands out
+            let index = cpu.index() as usize;
+            if (addr..addr + BYTES_PER_LINE).contains(&index) {
+                let col = 4 + (index - addr) * 3;
+                text(canvas, col, MEMORY_ROW + line, &bytes[index - addr], osd::SELECTED_COLOR);
+            }
+        }
+    }
+}
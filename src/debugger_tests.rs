@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests {
+    use crate::debugger::{DebugAction, Debugger, BYTES_PER_LINE, MEMORY_LINES};
+    use crate::mem::RAM_SIZE;
+    use sdl2::keyboard::Keycode;
+
+    const PANEL_X: i32 = 1024;
+
+    #[test]
+    fn breakpoints_stop_once_until_resumed() {
+        let mut debugger = Debugger::new(PANEL_X);
+        debugger.toggle_breakpoint(0x204);
+        assert!(debugger.should_run(0x200, true));
+        debugger.ran(0x202);
+        assert!(!debugger.should_run(0x202, false));
+
+        assert!(!debugger.should_run(0x204, true));
+        assert_eq!(debugger.take_hit(), Some(0x204));
+        assert_eq!(debugger.take_hit(), None);
+        // resuming runs the instruction under the breakpoint
+        assert!(debugger.should_run(0x204, true));
+        debugger.ran(0x206);
+        assert!(!debugger.should_run(0x204, true));
+
+        debugger.toggle_breakpoint(0x204);
+        assert!(debugger.breakpoints().is_empty());
+        assert!(debugger.should_run(0x204, true));
+    }
+
+    #[test]
+    fn steps_run_while_paused() {
+        let mut debugger = Debugger::new(PANEL_X);
+        debugger.toggle_breakpoint(0x202);
+        debugger.request_step();
+        assert!(debugger.should_run(0x200, false));
+        debugger.ran(0x202);
+        assert!(!debugger.should_run(0x202, false));
+        // continuing from where a step stopped does not stop again there
+        assert!(debugger.should_run(0x202, true));
+    }
+
+    #[test]
+    fn memory_view_scrolls_within_ram() {
+        let mut debugger = Debugger::new(PANEL_X);
+        assert_eq!(debugger.memory_top(), 0x200);
+        debugger.scroll_memory(-1);
+        assert_eq!(debugger.memory_top(), 0x200 - BYTES_PER_LINE as u16);
+        debugger.scroll_memory(-1000);
+        assert_eq!(debugger.memory_top(), 0);
+        debugger.key(Keycode::PageDown, 0x200);
+        assert_eq!(debugger.memory_top() as usize, MEMORY_LINES * BYTES_PER_LINE);
+        debugger.scroll_memory(1000);
+        assert_eq!(debugger.memory_top() as usize, RAM_SIZE - MEMORY_LINES * BYTES_PER_LINE);
+    }
+
+    #[test]
+    fn mouse_and_keys_control_execution() {
+        let mut debugger = Debugger::new(PANEL_X);
+        assert_eq!(debugger.key(Keycode::F5, 0x200), Some(DebugAction::TogglePause));
+        assert_eq!(debugger.key(Keycode::F10, 0x200), Some(DebugAction::Step));
+        assert_eq!(debugger.key(Keycode::F9, 0x200), None);
+        assert!(debugger.breakpoints().contains(&0x200));
+
+        // buttons on the first row, then the disassembly from row 7 on
+        assert_eq!(debugger.click(PANEL_X + 10, 10, 0x200), Some(DebugAction::TogglePause));
+        assert_eq!(debugger.click(PANEL_X + 8 + 13 * 8, 10, 0x200), Some(DebugAction::Step));
+        assert_eq!(debugger.click(100, 10, 0x200), None);
+        let code_line = |line: i32| 8 + (7 + line) * 14 + 2;
+        assert_eq!(debugger.click(PANEL_X + 20, code_line(4), 0x210), None);
+        assert!(debugger.breakpoints().contains(&0x210));
+        debugger.click(PANEL_X + 20, code_line(0), 0x210);
+        assert!(debugger.breakpoints().contains(&Debugger::code_start(0x210)));
+        assert_eq!(Debugger::code_start(0x210), 0x208);
+        assert_eq!(Debugger::code_start(0x002), 0xFFA);
+    }
+}
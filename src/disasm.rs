@@ -0,0 +1,76 @@
+use crate::cpu::{Cpu, Instruction};
+use crate::mem::Memory;
+use crate::symbols::Symbols;
+
+// Cowgod's mnemonics, which most CHIP-8 documentation uses. Anything that does
+// not decode is shown as a data word.
+pub fn disassemble(instr: &Instruction) -> String {
+    disassemble_with(instr, &Symbols::new())
+}
+
+// The same with addresses shown relative to labels.
+pub fn disassemble_with(instr: &Instruction, symbols: &Symbols) -> String {
+    let addr = |addr: u16| symbols.format_addr(addr);
+    match *instr {
+        Instruction::Invalid(opcode) => format!("DW 0x{:04X}", opcode),
+        Instruction::ClearScreen => "CLS".to_string(),
+        Instruction::Return => "RET".to_string(),
+        Instruction::Jump(a) => format!("JP {}", addr(a)),
+        Instruction::Call(a) => format!("CALL {}", addr(a)),
+        Instruction::VxNNSkip(x, nn) => format!("SE V{:X}, 0x{:02X}", x, nn),
+        Instruction::VxNNNotSkip(x, nn) => format!("SNE V{:X}, 0x{:02X}", x, nn),
+        Instruction::VxVySkip(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::SetRegister(x, nn) => format!("LD V{:X}, 0x{:02X}", x, nn),
+        Instruction::AddRegister(x, nn) => format!("ADD V{:X}, 0x{:02X}", x, nn),
+        Instruction::VxSetVy(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::VxBitOrVy(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::VxBitAndVy(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::VxBitXOrVy(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::VxBitAddVy(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::VxSubVy(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::VxBitShiftRVy(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::VxMinusVy(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::VxBitShiftLVy(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::VxNotVySkip(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::SetIndexRegister(a) => format!("LD I, {}", addr(a)),
+        Instruction::V0Jump(a) => format!("JP V0, {}", addr(a)),
+        Instruction::VxRand(x, nn) => format!("RND V{:X}, 0x{:02X}", x, nn),
+        Instruction::Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::KeyVxSkip(x) => format!("SKP V{:X}", x),
+        Instruction::KeyNotVxSkip(x) => format!("SKNP V{:X}", x),
+        Instruction::SetTimerVx(x) => format!("LD V{:X}, DT", x),
+        Instruction::GetKeyVx(x) => format!("LD V{:X}, K", x),
+        Instruction::GetTimerVx(x) => format!("LD DT, V{:X}", x),
+        Instruction::SetSoundTimerVx(x) => format!("LD ST, V{:X}", x),
+        Instruction::AddIVx(x) => format!("ADD I, V{:X}", x),
+        Instruction::SetISprite(x) => format!("LD F, V{:X}", x),
+        Instruction::SetILargeSprite(x) => format!("LD HF, V{:X}", x),
+        Instruction::BCDVX(x) => format!("LD B, V{:X}", x),
+        Instruction::RegDump(x) => format!("LD [I], V{:X}", x),
+        Instruction::RegLoad(x) => format!("LD V{:X}, [I]", x),
+    }
+}
+
+// One line of a listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub addr: u16,
+    pub opcode: u16,
+    pub text: String,
+}
+
+// `count` instructions from `start`, two bytes apart, without going through
+// the memory hooks.
+pub fn listing(mem: &Memory, start: u16, count: usize, symbols: &Symbols) -> Vec<Line> {
+    (0..count as u16)
+        .map(|i| {
+            let addr = start.wrapping_add(i * 2) & 0x0FFF;
+            let opcode = mem.get_instruction(addr);
+            Line {
+                addr,
+                opcode,
+                text: disassemble_with(&Cpu::decode(opcode), symbols),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+    use crate::disasm::{disassemble, listing};
+    use crate::mem::Memory;
+    use crate::symbols::Symbols;
+
+    fn text(opcode: u16) -> String {
+        disassemble(&Cpu::decode(opcode))
+    }
+
+    #[test]
+    fn instructions_use_cowgod_mnemonics() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x00EE, "RET"),
+            (0x0123, "DW 0x0123"),
+            (0x1ABC, "JP 0xABC"),
+            (0x2208, "CALL 0x208"),
+            (0x3A05, "SE VA, 0x05"),
+            (0x4105, "SNE V1, 0x05"),
+            (0x5120, "SE V1, V2"),
+            (0x6FFF, "LD VF, 0xFF"),
+            (0x7001, "ADD V0, 0x01"),
+            (0x8120, "LD V1, V2"),
+            (0x8124, "ADD V1, V2"),
+            (0x8127, "SUBN V1, V2"),
+            (0x812E, "SHL V1, V2"),
+            (0x9120, "SNE V1, V2"),
+            (0xA123, "LD I, 0x123"),
+            (0xB300, "JP V0, 0x300"),
+            (0xC10F, "RND V1, 0x0F"),
+            (0xD125, "DRW V1, V2, 5"),
+            (0xE19E, "SKP V1"),
+            (0xE1A1, "SKNP V1"),
+            (0xF107, "LD V1, DT"),
+            (0xF10A, "LD V1, K"),
+            (0xF115, "LD DT, V1"),
+            (0xF118, "LD ST, V1"),
+            (0xF11E, "ADD I, V1"),
+            (0xF129, "LD F, V1"),
+            (0xF133, "LD B, V1"),
+            (0xF155, "LD [I], V1"),
+            (0xF165, "LD V1, [I]"),
+        ];
+        for (opcode, expected) in cases {
+            assert_eq!(text(opcode), expected, "0x{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn listing_steps_two_bytes_and_wraps() {
+        let mut mem = Memory::new();
+        mem.load(vec![0x60, 0x05, 0x12, 0x00]).unwrap();
+        let lines = listing(&mem, 0x200, 2, &Symbols::new());
+        assert_eq!((lines[0].addr, lines[0].opcode, lines[0].text.as_str()), (0x200, 0x6005, "LD V0, 0x05"));
+        assert_eq!((lines[1].addr, lines[1].text.as_str()), (0x202, "JP 0x200"));
+
+        let lines = listing(&mem, 0xFFE, 2, &Symbols::new());
+        assert_eq!(lines[1].addr, 0x000);
+        assert_eq!(lines[1].opcode, 0xF090);
+    }
+}
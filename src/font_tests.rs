@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use crate::font::{self, FontSet, FONT_SETS, SMALL_FONT_SIZE};
+    use crate::mem::Memory;
+    use crate::system::System;
+
+    #[test]
+    fn font_sets_are_installed_at_the_base() {
+        for font in FONT_SETS.iter() {
+            assert_eq!(FontSet::by_id(font.id), Some(*font));
+            assert!(matches!(font.large.map(|large| large.len()), None | Some(100) | Some(160)));
+        }
+        assert_eq!(FontSet::by_id("fish"), None);
+
+        let mut mem = Memory::new();
+        assert_eq!(&mem.ram()[0..SMALL_FONT_SIZE], &FontSet::DEFAULT.small[..]);
+        assert_eq!(mem.large_font_address(2), Some(80 + 20));
+
+        mem.set_font(font::VIP, 0x050).unwrap();
+        assert!(mem.ram()[0..0x050].iter().all(|&byte| byte == 0));
+        assert_eq!(&mem.ram()[0x050..0x0A0], &font::VIP.small[..]);
+        assert_eq!(mem.font_address(0xA), 0x050 + 50);
+        assert_eq!(mem.large_font_address(0xA), None);
+        // the large font of the old set is gone too
+        assert!(mem.ram()[0x0A0..0x0F0].iter().all(|&byte| byte == 0));
+        mem.reset();
+        assert_eq!(&mem.ram()[0x050..0x0A0], &font::VIP.small[..]);
+
+        assert!(mem.set_font(font::OCTO, 0x150).is_err());
+        assert_eq!(mem.font(), font::VIP);
+    }
+
+    #[test]
+    fn fx29_and_fx30_point_at_the_glyphs() {
+        // V0 = 7, I = small 7, I = large 7
+        let rom = vec![0x60, 0x07, 0xF0, 0x29, 0xF0, 0x30];
+        let mut emu = System::new();
+        emu.set_trace(false);
+        emu.load_rom(rom).unwrap();
+        emu.set_font(font::SCHIP).unwrap();
+        emu.set_font_base(0x050).unwrap();
+        for _ in 0..2 {
+            emu.tick();
+        }
+        assert_eq!(emu.cpu().index(), 0x050 + 35);
+        emu.tick();
+        assert_eq!(emu.cpu().index(), 0x050 + 80 + 70);
+        assert_eq!(emu.memory().get_byte(0x050 + 80 + 70), 0xFF);
+
+        // without a large font FX30 does nothing
+        emu.set_font(font::DREAM6800).unwrap();
+        emu.reset();
+        for _ in 0..3 {
+            emu.tick();
+        }
+        assert_eq!(emu.cpu().index(), 0x050 + 35);
+    }
+}
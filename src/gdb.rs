@@ -0,0 +1,544 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::mem::RAM_SIZE;
+use crate::system::System;
+
+// Register numbers, in the order of `g` packets and the target description.
+// V0-VF are 0-15. Sixteen bit registers are sent big-endian, like CHIP-8
+// memory.
+pub const REG_I: usize = 16;
+pub const REG_PC: usize = 17;
+pub const REG_SP: usize = 18;
+pub const REG_DT: usize = 19;
+pub const REG_ST: usize = 20;
+const REG_COUNT: usize = 21;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+fn reg_size(reg: usize) -> usize {
+    match reg {
+        REG_I | REG_PC => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(emu: &System, reg: usize) -> u16 {
+    let (delay, sound) = emu.timers();
+    match reg {
+        0..=15 => emu.cpu().get_v(reg) as u16,
+        REG_I => emu.cpu().index(),
+        REG_PC => emu.pc(),
+        REG_SP => emu.cpu().sp() as u16,
+        REG_DT => delay as u16,
+        _ => sound as u16,
+    }
+}
+
+fn write_register(emu: &mut System, reg: usize, val: u16) -> Result<(), ()> {
+    let (delay, sound) = emu.timers();
+    match reg {
+        0..=15 => emu.cpu_mut().set_v(val as u8, reg),
+        REG_I => emu.cpu_mut().set_index(val),
+        REG_PC => emu.cpu_mut().jump(val & 0x0FFF),
+        REG_SP => emu.cpu_mut().set_sp(val as usize).map_err(|_| ())?,
+        REG_DT => emu.set_timers(val as u8, sound),
+        REG_ST => emu.set_timers(delay, val as u8),
+        _ => return Err(()),
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// None for anything but pairs of hex digits.
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+// "addr,len" as in m, M, Z and qXfer packets.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    // No debugger, the emulator runs as usual.
+    Detached,
+    Halted,
+    // `skip` lets the first instruction run even at a breakpoint, so that
+    // continuing from one does not stop straight away.
+    Running { skip: bool },
+    Stepping,
+}
+
+// The debugger's view of a `System`: answers packets and decides when the
+// emulator may run. It knows nothing about the connection, which is
+// `GdbServer`'s job.
+pub struct GdbStub {
+    state: State,
+    breakpoints: BTreeSet<u16>,
+    // Stop replies waiting to be sent.
+    outbox: Vec<String>,
+}
+
+impl GdbStub {
+    pub fn new() -> GdbStub {
+        GdbStub {
+            state: State::Detached,
+            breakpoints: BTreeSet::new(),
+            outbox: Vec::new(),
+        }
+    }
+
+    // A debugger connected, so the emulator halts until it says otherwise.
+    pub fn attach(&mut self) {
+        self.state = State::Halted;
+        self.breakpoints.clear();
+        self.outbox.clear();
+    }
+    pub fn detach(&mut self) {
+        self.state = State::Detached;
+        self.breakpoints.clear();
+        self.outbox.clear();
+    }
+    pub fn is_attached(&self) -> bool {
+        self.state != State::Detached
+    }
+    pub fn is_halted(&self) -> bool {
+        self.state == State::Halted
+    }
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+    pub fn take_outbox(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    // Called before each instruction: whether the one at `pc` may run.
+    // Hitting a breakpoint halts and queues the stop reply.
+    pub fn should_run(&mut self, pc: u16) -> bool {
+        match self.state {
+            State::Detached | State::Stepping => true,
+            State::Halted => false,
+            State::Running { skip } => {
+                if !skip && self.breakpoints.contains(&pc) {
+                    self.stop("T05swbreak:;");
+                    return false;
+                }
+                self.state = State::Running { skip: false };
+                true
+            }
+        }
+    }
+    // Called after each instruction, to finish a single step.
+    pub fn ran(&mut self) {
+        if self.state == State::Stepping {
+            self.stop("T05");
+        }
+    }
+    // The debugger sent ^C.
+    pub fn interrupt(&mut self) {
+        if let State::Running { .. } | State::Stepping = self.state {
+            self.stop("T02");
+        }
+    }
+    fn stop(&mut self, reply: &str) {
+        self.state = State::Halted;
+        self.outbox.push(reply.to_string());
+    }
+
+    // Answers one packet, given without framing. None means no reply for
+    // now: `c` and `s` are answered by a stop reply once the target halts.
+    pub fn handle(&mut self, packet: &str, emu: &mut System) -> Option<String> {
+        let (command, args) = match packet.char_indices().nth(1) {
+            Some((i, _)) => packet.split_at(i),
+            None => (packet, ""),
+        };
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => {
+                let mut bytes = Vec::new();
+                for reg in 0..REG_COUNT {
+                    let val = read_register(emu, reg);
+                    bytes.extend_from_slice(&val.to_be_bytes()[2 - reg_size(reg)..]);
+                }
+                to_hex(&bytes)
+            }
+            "G" => self.write_registers(args, emu),
+            "p" => match parse_hex(args).filter(|reg| *reg < REG_COUNT) {
+                Some(reg) => to_hex(&read_register(emu, reg).to_be_bytes()[2 - reg_size(reg)..]),
+                None => "E01".to_string(),
+            },
+            "P" => self.write_register(args, emu),
+            "m" => self.read_memory(args, emu),
+            "M" => self.write_memory(args, emu),
+            "Z" | "z" => self.breakpoint(command == "Z", args),
+            "c" | "s" => {
+                if let Some(addr) = parse_hex(args) {
+                    emu.cpu_mut().jump(addr as u16 & 0x0FFF);
+                }
+                self.resume(command == "s");
+                return None;
+            }
+            "v" => match args {
+                "Cont?" => "vCont;c;C;s;S".to_string(),
+                _ if args.starts_with("Cont;") => {
+                    // One thread, so the first action is the one that counts
+                    let step = matches!(args[5..].chars().next(), Some('s') | Some('S'));
+                    self.resume(step);
+                    return None;
+                }
+                _ => String::new(),
+            },
+            "q" => self.query(args),
+            "H" | "T" => "OK".to_string(),
+            "D" => {
+                self.detach();
+                "OK".to_string()
+            }
+            "k" => {
+                self.detach();
+                return None;
+            }
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn resume(&mut self, step: bool) {
+        self.state = if step { State::Stepping } else { State::Running { skip: true } };
+    }
+
+    fn write_registers(&mut self, args: &str, emu: &mut System) -> String {
+        let bytes = match from_hex(args) {
+            Some(bytes) if bytes.len() == (0..REG_COUNT).map(reg_size).sum::<usize>() => bytes,
+            _ => return "E01".to_string(),
+        };
+        let mut at = 0;
+        for reg in 0..REG_COUNT {
+            let size = reg_size(reg);
+            let val = bytes[at..at + size].iter().fold(0, |val, byte| val << 8 | *byte as u16);
+            if write_register(emu, reg, val).is_err() {
+                return "E02".to_string();
+            }
+            at += size;
+        }
+        "OK".to_string()
+    }
+
+    fn write_register(&mut self, args: &str, emu: &mut System) -> String {
+        let parsed = args.split_once('=').and_then(|(reg, val)| {
+            let reg = parse_hex(reg).filter(|reg| *reg < REG_COUNT)?;
+            let bytes = from_hex(val).filter(|bytes| bytes.len() == reg_size(reg))?;
+            Some((reg, bytes.iter().fold(0, |val, byte| val << 8 | *byte as u16)))
+        });
+        match parsed {
+            Some((reg, val)) if write_register(emu, reg, val).is_ok() => "OK".to_string(),
+            Some(_) => "E02".to_string(),
+            None => "E01".to_string(),
+        }
+    }
+
+    // Reads past the end of RAM are cut short.
+    fn read_memory(&self, args: &str, emu: &System) -> String {
+        match parse_range(args) {
+            Some((addr, len)) if addr < RAM_SIZE => {
+                let end = addr.saturating_add(len).min(RAM_SIZE);
+                let bytes: Vec<u8> = (addr..end).map(|addr| emu.memory().get_byte(addr as u16)).collect();
+                to_hex(&bytes)
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&self, args: &str, emu: &mut System) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_range(range)?;
+            let bytes = from_hex(data).filter(|bytes| bytes.len() == len && addr.saturating_add(len) <= RAM_SIZE)?;
+            Some((addr, bytes))
+        });
+        match parsed {
+            Some((addr, bytes)) => {
+                for (i, byte) in bytes.iter().enumerate() {
+                    emu.memory_mut().poke((addr + i) as u16, *byte);
+                }
+                "OK".to_string()
+            }
+            None => "E01".to_string(),
+        }
+    }
+
+    // Software and hardware breakpoints are both checked before each
+    // instruction; watchpoints are not supported.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let (kind, range) = match args.split_once(',') {
+            Some(split) => split,
+            None => return "E01".to_string(),
+        };
+        if kind != "0" && kind != "1" {
+            return String::new();
+        }
+        match parse_range(range) {
+            Some((addr, _)) if addr < RAM_SIZE => {
+                if insert {
+                    self.breakpoints.insert(addr as u16);
+                } else {
+                    self.breakpoints.remove(&(addr as u16));
+                }
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            return "PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+".to_string();
+        }
+        if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            return match parse_range(range) {
+                Some((offset, len)) if offset <= TARGET_XML.len() => {
+                    let end = offset.saturating_add(len).min(TARGET_XML.len());
+                    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                    format!("{}{}", more, &TARGET_XML[offset..end])
+                }
+                _ => "E01".to_string(),
+            };
+        }
+        match args {
+            "Attached" => "1",
+            "C" => "QC1",
+            "fThreadInfo" => "m1",
+            "sThreadInfo" => "l",
+            _ => "",
+        }
+        .to_string()
+    }
+}
+
+// What arrived from the debugger, once split up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Packet(String),
+    // A packet with the wrong checksum, to be answered with '-'.
+    Corrupt,
+    // The debugger wants the last packet again.
+    Nack,
+    Interrupt,
+}
+
+// Takes complete inputs off the front of `buffer`, leaving a partial packet
+// for later.
+pub fn parse_input(buffer: &mut Vec<u8>) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    let mut at = 0;
+    while at < buffer.len() {
+        match buffer[at] {
+            b'-' => inputs.push(Input::Nack),
+            0x03 => inputs.push(Input::Interrupt),
+            b'$' => {
+                let end = match buffer[at..].iter().position(|byte| *byte == b'#') {
+                    Some(end) if at + end + 2 < buffer.len() => at + end,
+                    _ => break,
+                };
+                let body = &buffer[at + 1..end];
+                let checksum = std::str::from_utf8(&buffer[end + 1..end + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if checksum == Some(body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))) {
+                    inputs.push(Input::Packet(unescape(body)));
+                } else {
+                    inputs.push(Input::Corrupt);
+                }
+                at = end + 3;
+                continue;
+            }
+            // '+' acks and anything else between packets
+            _ => {}
+        }
+        at += 1;
+    }
+    buffer.drain(..at);
+    inputs
+}
+
+fn unescape(body: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(body.len());
+    let mut escaped = false;
+    for byte in body {
+        match (escaped, *byte) {
+            (false, b'}') => escaped = true,
+            (true, byte) => {
+                bytes.push(byte ^ 0x20);
+                escaped = false;
+            }
+            (false, byte) => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// "$<data>#<checksum>", with the protocol's special characters escaped.
+pub fn frame(data: &str) -> String {
+    let mut body = String::with_capacity(data.len());
+    for c in data.chars() {
+        match c {
+            '$' | '#' | '}' | '*' => {
+                body.push('}');
+                body.push((c as u8 ^ 0x20) as char);
+            }
+            _ => body.push(c),
+        }
+    }
+    let checksum = body.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", body, checksum)
+}
+
+// A GDB remote serial protocol server for one debugger at a time. Nothing
+// blocks: `poll` is called from the main loop to accept a connection and
+// answer what has arrived.
+pub struct GdbServer {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    input: Vec<u8>,
+    last_sent: String,
+    stub: GdbStub,
+}
+
+impl GdbServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbServer {
+            listener,
+            stream: None,
+            input: Vec::new(),
+            last_sent: String::new(),
+            stub: GdbStub::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+    pub fn stub(&self) -> &GdbStub {
+        &self.stub
+    }
+    pub fn stub_mut(&mut self) -> &mut GdbStub {
+        &mut self.stub
+    }
+
+    pub fn poll(&mut self, emu: &mut System) {
+        if self.stream.is_none() {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        println!("Unable to set up GDB connection: {}", e);
+                        return;
+                    }
+                    let _ = stream.set_nodelay(true);
+                    println!("GDB connected from {}", addr);
+                    self.stream = Some(stream);
+                    self.input.clear();
+                    self.stub.attach();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("Unable to accept GDB connection: {}", e);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.exchange(emu) {
+            println!("GDB disconnected: {}", e);
+            self.stream = None;
+            self.stub.detach();
+        } else if !self.stub.is_attached() {
+            println!("GDB detached");
+            self.stream = None;
+        }
+    }
+
+    fn exchange(&mut self, emu: &mut System) -> io::Result<()> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        let mut buf = [0; 4096];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed")),
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut out = String::new();
+        for input in parse_input(&mut self.input) {
+            match input {
+                Input::Packet(packet) => {
+                    out.push('+');
+                    if let Some(reply) = self.stub.handle(&packet, emu) {
+                        self.last_sent = frame(&reply);
+                        out.push_str(&self.last_sent);
+                    }
+                }
+                Input::Corrupt => out.push('-'),
+                Input::Nack => out.push_str(&self.last_sent),
+                Input::Interrupt => self.stub.interrupt(),
+            }
+        }
+        for reply in self.stub.take_outbox() {
+            self.last_sent = frame(&reply);
+            out.push_str(&self.last_sent);
+        }
+        if !out.is_empty() {
+            // Replies are small, so waiting for them to go out is fine
+            stream.set_nonblocking(false)?;
+            stream.write_all(out.as_bytes())?;
+            stream.set_nonblocking(true)?;
+        }
+        Ok(())
+    }
+}
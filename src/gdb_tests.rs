@@ -0,0 +1,206 @@
+#[cfg(test)]
+mod tests {
+    use crate::gdb::{frame, parse_input, GdbServer, GdbStub, Input, REG_PC, REG_SP};
+    use crate::system::System;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    // 6005 A123 2208 1204 | 7001 00EE
+    const PROGRAM: [u8; 12] = [0x60, 0x05, 0xA1, 0x23, 0x22, 0x08, 0x12, 0x04, 0x70, 0x01, 0x00, 0xEE];
+
+    fn system() -> System {
+        let mut emu = System::new();
+        emu.load_test(PROGRAM.to_vec());
+        emu.set_trace(false);
+        emu
+    }
+
+    fn attached() -> GdbStub {
+        let mut stub = GdbStub::new();
+        stub.attach();
+        stub
+    }
+
+    // What the main loop does: ask the stub before each instruction.
+    fn run(stub: &mut GdbStub, emu: &mut System, max: usize) -> usize {
+        let mut ran = 0;
+        while ran < max && stub.should_run(emu.pc()) {
+            emu.tick();
+            stub.ran();
+            ran += 1;
+        }
+        ran
+    }
+
+    #[test]
+    fn packets_are_framed_with_checksums() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        assert_eq!(frame("a#b"), "$a}\u{3}b#43");
+
+        let mut buffer = b"+$?#3f$g#67-\x03$m200,2#xx$qSupp".to_vec();
+        let inputs = parse_input(&mut buffer);
+        assert_eq!(
+            inputs,
+            [
+                Input::Packet("?".to_string()),
+                Input::Packet("g".to_string()),
+                Input::Nack,
+                Input::Interrupt,
+                Input::Corrupt
+            ]
+        );
+        assert_eq!(buffer, b"$qSupp");
+    }
+
+    #[test]
+    fn registers_read_and_write() {
+        let mut emu = system();
+        let mut stub = attached();
+        emu.tick();
+        emu.tick();
+        emu.set_timers(7, 9);
+
+        // V0-VF, then I and PC big-endian, then SP, DT and ST
+        let expected = format!("05{}01230204000709", "00".repeat(15));
+        assert_eq!(stub.handle("g", &mut emu).unwrap(), expected);
+        assert_eq!(stub.handle("p11", &mut emu).unwrap(), "0204");
+        assert_eq!(stub.handle("p15", &mut emu).unwrap(), "E01");
+
+        assert_eq!(stub.handle("P3=2a", &mut emu).unwrap(), "OK");
+        assert_eq!(emu.cpu().get_v(3), 0x2a);
+        assert_eq!(stub.handle(&format!("P{:x}=0208", REG_PC), &mut emu).unwrap(), "OK");
+        assert_eq!(emu.pc(), 0x208);
+        assert_eq!(stub.handle(&format!("P{:x}=41", REG_SP), &mut emu).unwrap(), "E02");
+        // SP stays within the depth of the platform, and of the VIP's stack
+        // in RAM
+        emu.set_stack_depth(12);
+        assert_eq!(stub.handle(&format!("P{:x}=0d", REG_SP), &mut emu).unwrap(), "E02");
+        assert_eq!(stub.handle(&format!("P{:x}=0c", REG_SP), &mut emu).unwrap(), "OK");
+        emu.set_stack_depth(64);
+        emu.set_stack_in_memory(true);
+        assert_eq!(stub.handle(&format!("P{:x}=19", REG_SP), &mut emu).unwrap(), "E02");
+        assert_eq!(stub.handle(&format!("P{:x}=01", REG_SP), &mut emu).unwrap(), "OK");
+        assert_eq!(emu.cpu().sp(), 1);
+        emu.set_stack_in_memory(false);
+
+        let all = format!("{}0456020a0003ff", "11".repeat(16));
+        assert_eq!(stub.handle(&format!("G{}", all), &mut emu).unwrap(), "OK");
+        assert_eq!(emu.cpu().v(), [0x11; 16]);
+        assert_eq!(emu.cpu().index(), 0x456);
+        assert_eq!(emu.pc(), 0x20a);
+        assert_eq!(emu.timers(), (3, 0xff));
+        assert_eq!(stub.handle("G00", &mut emu).unwrap(), "E01");
+    }
+
+    #[test]
+    fn memory_reads_and_writes() {
+        let mut emu = system();
+        let mut stub = attached();
+        assert_eq!(stub.handle("m200,4", &mut emu).unwrap(), "6005a123");
+        assert_eq!(stub.handle("mffe,8", &mut emu).unwrap(), "0000");
+        assert_eq!(stub.handle("m1000,1", &mut emu).unwrap(), "E01");
+
+        assert_eq!(stub.handle("M300,3:0a0b0c", &mut emu).unwrap(), "OK");
+        assert_eq!(stub.handle("m300,3", &mut emu).unwrap(), "0a0b0c");
+        assert_eq!(stub.handle("M300,3:0a", &mut emu).unwrap(), "E01");
+        assert_eq!(stub.handle("Mfff,2:0102", &mut emu).unwrap(), "E01");
+
+        // lengths from a broken or hostile client do not overflow
+        assert_eq!(stub.handle("m1,ffffffffffffffff", &mut emu).unwrap().len(), 2 * 0xFFF);
+        assert_eq!(stub.handle("Mffffffffffffffff,1:00", &mut emu).unwrap(), "E01");
+        let reply = stub.handle("qXfer:features:read:target.xml:10,ffffffffffffffff", &mut emu).unwrap();
+        assert!(reply.starts_with('l'));
+    }
+
+    #[test]
+    fn breakpoints_step_and_continue() {
+        let mut emu = system();
+        let mut stub = attached();
+        assert_eq!(run(&mut stub, &mut emu, 10), 0);
+
+        assert!(stub.handle("s", &mut emu).is_none());
+        assert_eq!(run(&mut stub, &mut emu, 10), 1);
+        assert_eq!(stub.take_outbox(), ["T05"]);
+        assert_eq!(emu.pc(), 0x202);
+
+        // stops at the subroutine, then again each time round the loop
+        assert_eq!(stub.handle("Z0,208,2", &mut emu).unwrap(), "OK");
+        assert!(stub.handle("c", &mut emu).is_none());
+        assert_eq!(run(&mut stub, &mut emu, 10), 2);
+        assert_eq!(emu.pc(), 0x208);
+        assert_eq!(stub.take_outbox(), ["T05swbreak:;"]);
+        assert!(stub.handle("vCont;c:1", &mut emu).is_none());
+        assert_eq!(run(&mut stub, &mut emu, 10), 4);
+        assert_eq!(emu.pc(), 0x208);
+        assert_eq!(stub.take_outbox(), ["T05swbreak:;"]);
+        assert_eq!(stub.breakpoints().len(), 1);
+
+        assert_eq!(stub.handle("z0,208,2", &mut emu).unwrap(), "OK");
+        assert!(stub.handle("c", &mut emu).is_none());
+        assert_eq!(run(&mut stub, &mut emu, 10), 10);
+        stub.interrupt();
+        assert_eq!(stub.take_outbox(), ["T02"]);
+        assert!(stub.is_halted());
+
+        assert_eq!(stub.handle("Z2,300,1", &mut emu).unwrap(), "");
+        assert_eq!(stub.handle("D", &mut emu).unwrap(), "OK");
+        assert!(!stub.is_attached());
+        assert_eq!(run(&mut stub, &mut emu, 10), 10);
+    }
+
+    #[test]
+    fn queries_describe_the_target() {
+        let mut emu = system();
+        let mut stub = attached();
+        assert!(stub.handle("qSupported:multiprocess+", &mut emu).unwrap().contains("qXfer:features:read+"));
+        assert_eq!(stub.handle("?", &mut emu).unwrap(), "S05");
+        assert_eq!(stub.handle("qAttached", &mut emu).unwrap(), "1");
+        assert_eq!(stub.handle("vMustReplyEmpty", &mut emu).unwrap(), "");
+
+        let mut xml = String::new();
+        loop {
+            let reply = stub
+                .handle(&format!("qXfer:features:read:target.xml:{:x},80", xml.len()), &mut emu)
+                .unwrap();
+            xml.push_str(&reply[1..]);
+            if reply.starts_with('l') {
+                break;
+            }
+        }
+        assert!(xml.contains(r#"<reg name="pc" bitsize="16""#));
+        assert!(xml.trim_end().ends_with("</target>"));
+    }
+
+    #[test]
+    fn serves_a_debugger_over_tcp() {
+        let mut emu = system();
+        let mut server = GdbServer::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let mut exchange = |server: &mut GdbServer, emu: &mut System, send: &str, expect: &str| {
+            client.write_all(send.as_bytes()).unwrap();
+            let mut received = Vec::new();
+            while received.len() < expect.len() {
+                server.poll(emu);
+                let mut buf = [0; 256];
+                if let Ok(n) = client.read(&mut buf) {
+                    received.extend_from_slice(&buf[..n]);
+                }
+            }
+            assert_eq!(String::from_utf8(received).unwrap(), expect);
+        };
+
+        exchange(&mut server, &mut emu, &frame("m200,2"), &format!("+{}", frame("6005")));
+        assert!(server.stub().is_halted());
+        exchange(&mut server, &mut emu, &frame("s"), "+");
+        assert!(server.stub_mut().should_run(emu.pc()));
+        emu.tick();
+        server.stub_mut().ran();
+        exchange(&mut server, &mut emu, "", &frame("T05"));
+        exchange(&mut server, &mut emu, "-", &frame("T05"));
+        exchange(&mut server, &mut emu, &frame("D"), &format!("+{}", frame("OK")));
+        assert!(!server.stub().is_attached());
+    }
+}
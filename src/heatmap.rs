@@ -0,0 +1,149 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+
+use crate::mem::{Access, Memory, MemoryEvent, RAM_SIZE};
+
+// The 4 KB are laid out as a 64x64 grid, one cell per address.
+pub const GRID_WIDTH: usize = 64;
+pub const GRID_HEIGHT: usize = RAM_SIZE / GRID_WIDTH;
+pub const CELL_SIZE: u32 = 8;
+
+// Per-address access counts. Code is what gets executed, sprite data is what
+// DXYN reads, and variables are the data reads and writes done by
+// FX33/FX55/FX65. With the stack in RAM, CALL and RET also write and read
+// return addresses at 0xEA0-0xECF, which count as variables too.
+pub struct Heatmap {
+    executes: Vec<u64>,
+    sprite_reads: Vec<u64>,
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Heatmap {
+    pub fn new() -> Heatmap {
+        Heatmap {
+            executes: vec![0; RAM_SIZE],
+            sprite_reads: vec![0; RAM_SIZE],
+            reads: vec![0; RAM_SIZE],
+            writes: vec![0; RAM_SIZE],
+        }
+    }
+
+    // Creates a heatmap that records every access to `mem` from now on.
+    pub fn attach(mem: &mut Memory) -> Rc<RefCell<Heatmap>> {
+        let heatmap = Rc::new(RefCell::new(Heatmap::new()));
+        let recorder = heatmap.clone();
+        mem.add_hook(Box::new(move |event| recorder.borrow_mut().record(event)));
+        heatmap
+    }
+
+    pub fn record(&mut self, event: &MemoryEvent) {
+        let addr = event.addr as usize;
+        match event.access {
+            Access::Execute => self.executes[addr] += 1,
+            Access::Sprite => self.sprite_reads[addr] += 1,
+            Access::Read => self.reads[addr] += 1,
+            Access::Write => self.writes[addr] += 1,
+        }
+    }
+
+    pub fn executes(&self, addr: u16) -> u64 {
+        self.executes[addr as usize]
+    }
+    pub fn sprite_reads(&self, addr: u16) -> u64 {
+        self.sprite_reads[addr as usize]
+    }
+    pub fn reads(&self, addr: u16) -> u64 {
+        self.reads[addr as usize]
+    }
+    pub fn writes(&self, addr: u16) -> u64 {
+        self.writes[addr as usize]
+    }
+
+    // Red for variables, green for code and blue for sprite data, each on a
+    // log scale relative to the busiest address of that kind.
+    pub fn colors(&self) -> Vec<(u8, u8, u8)> {
+        let variables: Vec<u64> = (0..RAM_SIZE).map(|i| self.reads[i] + self.writes[i]).collect();
+        let (max_variables, max_executes, max_sprites) =
+            (max(&variables), max(&self.executes), max(&self.sprite_reads));
+        (0..RAM_SIZE)
+            .map(|i| {
+                (
+                    intensity(variables[i], max_variables),
+                    intensity(self.executes[i], max_executes),
+                    intensity(self.sprite_reads[i], max_sprites),
+                )
+            })
+            .collect()
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+        for (addr, (r, g, b)) in self.colors().into_iter().enumerate() {
+            let x = (addr % GRID_WIDTH) as i32;
+            let y = (addr / GRID_WIDTH) as i32;
+            canvas.set_draw_color(Color::RGB(r, g, b));
+            canvas
+                .fill_rect(Rect::new(
+                    x * CELL_SIZE as i32,
+                    y * CELL_SIZE as i32,
+                    CELL_SIZE,
+                    CELL_SIZE,
+                ))
+                .expect("Unable to draw rect!");
+        }
+    }
+
+    // Regions are those of `mem` as it is laid out now.
+    pub fn write_csv(&self, mem: &Memory, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "address,region,executes,sprite_reads,reads,writes")?;
+        for addr in 0..RAM_SIZE {
+            writeln!(
+                out,
+                "0x{:03x},{},{},{},{},{}",
+                addr,
+                mem.region_of(addr as u16),
+                self.executes[addr],
+                self.sprite_reads[addr],
+                self.reads[addr],
+                self.writes[addr]
+            )?;
+        }
+        Ok(())
+    }
+
+    // One pixel per address, same layout and colours as the live view.
+    pub fn write_png(&self, out: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, GRID_WIDTH as u32, GRID_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let data: Vec<u8> = self.colors().into_iter().flat_map(|(r, g, b)| vec![r, g, b]).collect();
+        encoder.write_header()?.write_image_data(&data)?;
+        Ok(())
+    }
+
+    // Writes `<prefix>.csv` and `<prefix>.png`.
+    pub fn export(&self, mem: &Memory, prefix: &str) -> io::Result<()> {
+        let mut csv = BufWriter::new(File::create(format!("{}.csv", prefix))?);
+        self.write_csv(mem, &mut csv)?;
+        self.write_png(BufWriter::new(File::create(format!("{}.png", prefix))?))
+    }
+}
+
+fn max(counts: &[u64]) -> u64 {
+    counts.iter().copied().max().unwrap_or(0)
+}
+
+fn intensity(count: u64, max: u64) -> u8 {
+    if count == 0 || max == 0 {
+        return 0;
+    }
+    // Anything touched at all stays visible against the black background.
+    let scaled = ((count as f64).ln_1p() / (max as f64).ln_1p() * 223.0) as u8;
+    32 + scaled
+}
@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use crate::heatmap::Heatmap;
+    use crate::mem::Memory;
+    use crate::system;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn run_program() -> Rc<RefCell<Heatmap>> {
+        let mut emu = system::System::new();
+        // I = 0x000, draw font "0", V0 = 123, I = 0x300, BCD, load V0-V2, loop
+        emu.load_rom(vec![
+            0xA0, 0x00, 0xD0, 0x05, 0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0xF2, 0x65, 0x12, 0x0C,
+        ])
+        .unwrap();
+        let heatmap = Heatmap::attach(emu.memory_mut());
+        for _ in 0..10 {
+            emu.tick();
+        }
+        heatmap
+    }
+
+    #[test]
+    fn counts_accesses_by_kind() {
+        let heatmap = run_program();
+        let heatmap = heatmap.borrow();
+
+        assert_eq!(heatmap.executes(0x200), 1);
+        assert_eq!(heatmap.executes(0x20C), 4);
+        assert_eq!(heatmap.executes(0x20E), 0);
+
+        assert_eq!(heatmap.sprite_reads(0x000), 1);
+        assert_eq!(heatmap.sprite_reads(0x004), 1);
+        assert_eq!(heatmap.sprite_reads(0x005), 0);
+
+        assert_eq!(heatmap.writes(0x300), 1);
+        assert_eq!(heatmap.writes(0x302), 1);
+        assert_eq!(heatmap.reads(0x302), 1);
+        assert_eq!(heatmap.reads(0x303), 0);
+    }
+
+    #[test]
+    fn exports_csv_and_png() {
+        let heatmap = run_program();
+        let heatmap = heatmap.borrow();
+
+        let mut csv = Vec::new();
+        heatmap.write_csv(&Memory::new(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4097);
+        assert_eq!(lines[0], "address,region,executes,sprite_reads,reads,writes");
+        assert_eq!(lines[1 + 0x300], "0x300,program,0,0,1,1");
+
+        // regions follow the memory layout, here of the ETI-660
+        let mut mem = Memory::new();
+        mem.set_layout(0x600, 4096);
+        let mut csv = Vec::new();
+        heatmap.write_csv(&mem, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().nth(1 + 0x300), Some("0x300,interpreter,0,0,1,1"));
+
+        let mut png = Vec::new();
+        heatmap.write_png(&mut png).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Host keys for the keypad by the character they type, the 1234/QWER/ASDF/
+// ZXCV block of the window, for frontends that see characters rather than
+// SDL keycodes.
+pub const KEY_LAYOUT: [char; 16] = [
+    'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+];
+
+pub fn key_for_char(c: char) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    KEY_LAYOUT.iter().position(|&key| key == c).map(|key| key as u8)
+}
+
+// How FX0A waits for a key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyWait {
+    // Done as soon as a key is down, as most modern interpreters do.
+    Press,
+    // Done when a key has been pressed and released again, as on the COSMAC
+    // VIP. Programs that loop on FX0A see each key once instead of repeating
+    // it while it is held.
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub key: u8,
+    pub pressed: bool,
+    // When the key changed, on any clock that does not go backwards. Events
+    // from different sources are merged in this order.
+    pub time: Duration,
+}
+
+// The 16 key hex keypad. The frontend reports presses and releases as they
+// happen and the program sees them at its next instruction. A key is held
+// for at least the rest of the 60 Hz frame it went down in, so a tap shorter
+// than the time between two instructions, or between two frames of a program
+// that checks its keys once a frame, is never lost.
+pub struct Keypad {
+    down: [bool; 16],
+    // Events the program has not seen yet, oldest first.
+    pending: VecDeque<KeyEvent>,
+    // Keys that went down in the current frame and are held until it ends.
+    pressed_this_frame: [bool; 16],
+    wait: KeyWait,
+    // The key FX0A saw go down, waiting for it to be released.
+    waiting_for: Option<u8>,
+}
+
+impl Keypad {
+    pub fn new() -> Keypad {
+        Keypad {
+            down: [false; 16],
+            pending: VecDeque::new(),
+            pressed_this_frame: [false; 16],
+            wait: KeyWait::Release,
+            waiting_for: None,
+        }
+    }
+
+    pub fn press(&mut self, key: u8, time: Duration) {
+        self.push(KeyEvent { key: key & 0xF, pressed: true, time });
+    }
+    pub fn release(&mut self, key: u8, time: Duration) {
+        self.push(KeyEvent { key: key & 0xF, pressed: false, time });
+    }
+    pub fn push(&mut self, event: KeyEvent) {
+        let at = self
+            .pending
+            .iter()
+            .rposition(|pending| pending.time <= event.time)
+            .map_or(0, |i| i + 1);
+        self.pending.insert(at, event);
+    }
+    // Lets go of every key, e.g. when the emulator stops receiving key
+    // events because a menu opened.
+    pub fn release_all(&mut self, time: Duration) {
+        for key in 0..16 {
+            let will_be_down = self
+                .pending
+                .iter()
+                .rev()
+                .find(|event| event.key == key)
+                .map_or(self.down[key as usize], |event| event.pressed);
+            if will_be_down {
+                self.release(key, time);
+            }
+        }
+    }
+
+    // Hands pending events to the program, up to the release of a key that
+    // went down this frame. The rest wait for the next frame so the order of
+    // events is kept.
+    pub fn update(&mut self) {
+        while let Some(event) = self.pending.front() {
+            let key = event.key as usize;
+            if !event.pressed && self.pressed_this_frame[key] {
+                break;
+            }
+            if event.pressed && !self.down[key] {
+                self.pressed_this_frame[key] = true;
+            }
+            self.down[key] = event.pressed;
+            self.pending.pop_front();
+        }
+    }
+    pub fn end_frame(&mut self) {
+        self.pressed_this_frame = [false; 16];
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.down[key as usize & 0xF]
+    }
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn set_wait(&mut self, wait: KeyWait) {
+        self.wait = wait;
+        self.waiting_for = None;
+    }
+    pub fn wait(&self) -> KeyWait {
+        self.wait
+    }
+    // One step of FX0A: the key once the wait is over. With several keys
+    // down the highest wins.
+    pub fn wait_for_key(&mut self) -> Option<u8> {
+        let highest = (0..16).rev().find(|&key| self.down[key as usize]);
+        match (self.wait, self.waiting_for) {
+            (KeyWait::Press, _) => highest,
+            (KeyWait::Release, None) => {
+                self.waiting_for = highest;
+                None
+            }
+            (KeyWait::Release, Some(key)) if self.down[key as usize] => None,
+            (KeyWait::Release, Some(key)) => {
+                self.waiting_for = None;
+                Some(key)
+            }
+        }
+    }
+    // Forgets a wait in progress, as when the program is reset.
+    pub fn cancel_wait(&mut self) {
+        self.waiting_for = None;
+    }
+}
@@ -0,0 +1,121 @@
+#[cfg(test)]
+mod tests {
+    use crate::keypad::{key_for_char, KeyEvent, KeyWait, Keypad};
+    use crate::system::System;
+    use crate::timing::{Timing, FIXED_TICKS_PER_FRAME};
+    use std::time::Duration;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn characters_follow_the_window_layout() {
+        assert_eq!(key_for_char('x'), Some(0x0));
+        assert_eq!(key_for_char('1'), Some(0x1));
+        assert_eq!(key_for_char('V'), Some(0xF));
+        assert_eq!(key_for_char('p'), None);
+    }
+
+    #[test]
+    fn taps_between_ticks_last_until_the_frame_ends() {
+        let mut keypad = Keypad::new();
+        keypad.press(5, ms(1));
+        keypad.release(5, ms(2));
+        keypad.update();
+        assert!(keypad.is_pressed(5));
+        keypad.update();
+        assert!(keypad.is_pressed(5));
+        keypad.end_frame();
+        keypad.update();
+        assert!(!keypad.is_pressed(5));
+
+        // a key already held is released straight away
+        keypad.press(3, ms(3));
+        keypad.update();
+        keypad.end_frame();
+        keypad.release(3, ms(4));
+        keypad.update();
+        assert!(!keypad.is_pressed(3));
+    }
+
+    #[test]
+    fn taps_last_a_whole_frame_with_fixed_timing() {
+        // V0 = 5, two other instructions, then V2 = 1 if key 5 is down
+        let rom = vec![0x60, 0x05, 0x71, 0x01, 0x71, 0x01, 0xE0, 0x9E, 0x12, 0x08, 0x62, 0x01, 0x12, 0x0C];
+        let mut emu = System::new();
+        emu.set_trace(false);
+        emu.load_rom(rom).unwrap();
+        emu.set_timing(Timing::Fixed);
+        emu.tick();
+        emu.keypad_mut().press(5, ms(1));
+        emu.keypad_mut().release(5, ms(2));
+        for _ in 0..4 {
+            emu.tick();
+        }
+        assert!(emu.frame_ended());
+        emu.tick();
+        assert_eq!(emu.cpu().get_v(2), 1);
+        assert!(!emu.keypad().is_pressed(5));
+    }
+
+    #[test]
+    fn events_are_seen_in_time_order() {
+        let mut keypad = Keypad::new();
+        keypad.press(1, ms(10));
+        keypad.release(1, ms(30));
+        keypad.press(2, ms(20));
+        keypad.update();
+        // the release of 1 waits for the next frame, and so does everything
+        // after it
+        assert!(keypad.is_pressed(1) && keypad.is_pressed(2));
+        assert_eq!(keypad.pending(), 1);
+        keypad.end_frame();
+        keypad.update();
+        assert!(!keypad.is_pressed(1) && keypad.is_pressed(2));
+
+        keypad.push(KeyEvent { key: 4, pressed: true, time: ms(40) });
+        keypad.release_all(ms(50));
+        keypad.update();
+        keypad.end_frame();
+        keypad.update();
+        assert!((0..16).all(|key| !keypad.is_pressed(key)));
+        assert_eq!(keypad.pending(), 0);
+    }
+
+    #[test]
+    fn fx0a_waits_for_press_and_release() {
+        // V2 = key, then spin
+        let mut emu = System::new();
+        emu.set_trace(false);
+        emu.load_rom(vec![0xF2, 0x0A, 0x12, 0x02]).unwrap();
+        assert_eq!(emu.keypad().wait(), KeyWait::Release);
+
+        emu.keypad_mut().press(0xB, ms(1));
+        emu.tick();
+        emu.tick();
+        assert_eq!(emu.pc(), 0x200);
+        // the key is held to the end of the frame it went down in
+        emu.keypad_mut().release(0xB, ms(2));
+        for _ in 0..FIXED_TICKS_PER_FRAME {
+            emu.tick();
+        }
+        assert_eq!(emu.pc(), 0x202);
+        assert_eq!(emu.cpu().get_v(2), 0xB);
+
+        // a tap between two ticks is a whole press and release
+        emu.reset();
+        emu.key_event(emu.key_code(6), true, ms(3));
+        emu.key_event(emu.key_code(6), false, ms(4));
+        for _ in 0..FIXED_TICKS_PER_FRAME + 1 {
+            emu.tick();
+        }
+        assert_eq!(emu.cpu().get_v(2), 6);
+
+        emu.reset();
+        emu.set_key_wait(KeyWait::Press);
+        emu.keypad_mut().press(0xC, ms(5));
+        emu.tick();
+        assert_eq!(emu.cpu().get_v(2), 0xC);
+    }
+}
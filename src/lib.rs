@@ -0,0 +1,49 @@
+#![allow(clippy::new_without_default)]
+
+pub mod browser;
+pub mod bus;
+pub mod cheat;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod font;
+pub mod gdb;
+pub mod heatmap;
+pub mod keypad;
+pub mod mem;
+pub mod osd;
+pub mod platform;
+pub mod profiler;
+pub mod romdb;
+pub mod speed;
+pub mod symbols;
+pub mod system;
+pub mod timing;
+pub mod tui;
+pub mod video;
+pub mod watch;
+
+mod browser_tests;
+mod cheat_tests;
+mod cpu_tests;
+mod debugger_tests;
+mod disasm_tests;
+mod font_tests;
+mod gdb_tests;
+mod heatmap_tests;
+mod keypad_tests;
+mod mem_tests;
+mod osd_tests;
+mod profiler_tests;
+#[cfg(test)]
+mod reference;
+mod reference_tests;
+mod romdb_tests;
+mod speed_tests;
+mod symbols_tests;
+mod system_tests;
+#[cfg(test)]
+mod test_machine;
+mod timing_tests;
+mod tui_tests;
+mod watch_tests;
@@ -3,14 +3,31 @@ extern crate sdl2;
 use sdl2::audio::{AudioCallback, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use std::collections::HashMap;
-use std::time::Duration;
+use sdl2::mouse::MouseButton;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
-mod cpu;
-mod mem;
-mod system;
-mod video;
-mod cpu_tests;
+use chip8::browser::{self, Browser, Recent};
+use chip8::cheat::{self, Cheat, CheatKind, Cheats, Comparison, Search};
+use chip8::debugger::{self, DebugAction, Debugger};
+use chip8::font::{FontSet, FONT_SETS};
+use chip8::gdb::GdbServer;
+use chip8::keypad::KeyWait;
+use chip8::mem::Region;
+use chip8::osd::{Menu, MenuAction, MenuKey, Osd};
+use chip8::platform::{Profile, PROFILES};
+use chip8::romdb::{RomDb, RomInfo};
+use chip8::speed::{Clock, Speed};
+use chip8::symbols::Symbols;
+use chip8::tui::{self, Buzzer};
+use chip8::watch::RomWatcher;
+use chip8::{heatmap, profiler, system, timing, video};
+
+const TONE_HZ: f32 = 440.0;
+const TICKS_PER_SECOND: u32 = timing::FIXED_TICKS_PER_FRAME * timing::FRAMES_PER_SECOND;
 
 struct SquareWave {
     phase_inc: f32,
@@ -34,14 +51,28 @@ impl AudioCallback for SquareWave {
     }
 }
 pub fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.iter().any(|a| a == "--tui") {
+        return run_tui(&args);
+    }
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let audio_subsystem = sdl_context.audio().expect("Unable to init audio");
 
+    // --debugger shows registers, the call stack, disassembly and memory to
+    // the right of the display
+    let game_width = (video::SCREEN_WIDTH * video::PIXEL_SIZE as usize) as u32;
+    let mut debug_view = if args.iter().any(|a| a == "--debugger") {
+        Some(Debugger::new(game_width as i32))
+    } else {
+        None
+    };
+    let panel_width = if debug_view.is_some() { debugger::PANEL_WIDTH } else { 0 };
+
     let window = video_subsystem
         .window(
             "chip8",
-            (video::SCREEN_WIDTH * video::PIXEL_SIZE as usize) as u32,
+            game_width + panel_width,
             (video::SCREEN_HEIGHT * video::PIXEL_SIZE as usize) as u32,
         )
         .position_centered()
@@ -50,30 +81,146 @@ pub fn main() {
     let mut canvas = window.into_canvas().build().unwrap();
 
     let mut emulator = system::System::new();
+    // --ram-stack keeps return addresses in RAM at 0xEA0-0xECF like the VIP
+    // interpreter, for programs that look at or change them
+    emulator.set_stack_in_memory(args.iter().any(|a| a == "--ram-stack"));
+    memory_checks(&args, &mut emulator);
+    // FX0A waits for a key to be pressed and released like the VIP did, or
+    // with --fx0a-press only for it to be pressed
+    if args.iter().any(|a| a == "--fx0a-press") {
+        emulator.set_key_wait(KeyWait::Press);
+    }
 
-    emulator.load_game("<Your rom file here!>");
+    // --heatmap shows memory accesses live in a second window,
+    // --heatmap-export <prefix> writes <prefix>.csv and <prefix>.png on exit
+    let show_heatmap = args.iter().any(|a| a == "--heatmap");
+    let heatmap_export = args
+        .iter()
+        .position(|a| a == "--heatmap-export")
+        .map(|i| args.get(i + 1).expect("--heatmap-export needs a file prefix").clone());
+    let heatmap = if show_heatmap || heatmap_export.is_some() {
+        Some(heatmap::Heatmap::attach(emulator.memory_mut()))
+    } else {
+        None
+    };
+    // --profile <prefix> writes <prefix>.txt and <prefix>.folded on exit
+    let profile_export = args
+        .iter()
+        .position(|a| a == "--profile")
+        .map(|i| args.get(i + 1).expect("--profile needs a file prefix").clone());
+    let mut profiler = profile_export.as_ref().map(|_| profiler::Profiler::new());
+    let rom_db = rom_db(&args);
+    // --rom <file> starts a ROM straight away, otherwise the ROM browser opens
+    // on --rom-dir <dir>, the current directory by default
+    let start_rom = args
+        .iter()
+        .position(|a| a == "--rom")
+        .map(|i| args.get(i + 1).expect("--rom needs a file").clone());
+    let rom_dir = args
+        .iter()
+        .position(|a| a == "--rom-dir")
+        .map_or(".".to_string(), |i| args.get(i + 1).expect("--rom-dir needs a directory").clone());
+    // --symbols <file> loads labels and source lines for the debugger and
+    // traces, otherwise <rom>.sym is used if there is one.
+    let symbols_path = args
+        .iter()
+        .position(|a| a == "--symbols")
+        .map(|i| args.get(i + 1).expect("--symbols needs a file").clone());
+    let forced = forced_settings(&args, &mut emulator);
+    let recent_path = home_path(".chip8-recent");
+    let mut recent = Recent::load(&recent_path);
+    let mut rom_path: Option<String> = None;
+    // Cheats for the loaded ROM, the memory search in progress and the value
+    // typed in for "equal to" searches.
+    let cheats_dir = home_path(".chip8-cheats");
+    let mut cheats = Cheats::new();
+    let mut search: Option<Search> = None;
+    let mut search_value: u8 = 0;
+    // --watch reloads the ROM whenever its file changes, e.g. after it is
+    // reassembled. If that fails the old program keeps running under an
+    // error until a reload works.
+    let watch = args.iter().any(|a| a == "--watch");
+    let mut watcher: Option<RomWatcher> = None;
+    // --gdb <port> accepts a GDB remote protocol debugger on localhost, which
+    // controls execution while it is attached. Its breakpoints survive
+    // reloads.
+    let mut gdb = args.iter().position(|a| a == "--gdb").map(|i| {
+        let port: u16 = args
+            .get(i + 1)
+            .and_then(|port| port.parse().ok())
+            .expect("--gdb needs a port number");
+        let server = GdbServer::bind(("127.0.0.1", port)).expect("Unable to start GDB server");
+        println!("Waiting for GDB on 127.0.0.1:{}", port);
+        server
+    });
+    let frame_duration = Duration::new(0, 1_000_000_000u32 / timing::FRAMES_PER_SECOND);
+    let tick_duration = Duration::new(0, 1_000_000_000u32 / TICKS_PER_SECOND);
+    // --fast-forward <N|max> sets the fast-forward speed, uncapped by default;
+    // --audio-pitch keeps the buzzer on at other speeds, pitched accordingly
+    let fast_forward = match args.iter().position(|a| a == "--fast-forward") {
+        Some(i) => match args.get(i + 1).map(|a| a.as_str()) {
+            Some("max") => Speed::Uncapped,
+            Some(n) => Speed::Scaled(n.parse().expect("--fast-forward needs a number or max")),
+            None => panic!("--fast-forward needs a number or max"),
+        },
+        None => Speed::Uncapped,
+    };
+    let audio_pitch = args.iter().any(|a| a == "--audio-pitch");
+    let mut clock = Clock::new(fast_forward);
+    // --fps shows the frames and instructions per second counter
+    let mut osd = Osd::new();
+    osd.set_show_counter(args.iter().any(|a| a == "--fps"));
+    // Whether the pause menu paused the clock, so closing it resumes
+    let mut paused_by_menu = false;
+    let mut heatmap_canvas = if show_heatmap {
+        let window = video_subsystem
+            .window(
+                "chip8 memory",
+                heatmap::GRID_WIDTH as u32 * heatmap::CELL_SIZE,
+                heatmap::GRID_HEIGHT as u32 * heatmap::CELL_SIZE,
+            )
+            .build()
+            .unwrap();
+        Some(window.into_canvas().build().unwrap())
+    } else {
+        None
+    };
 
     canvas.clear();
     canvas.present();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut keys = HashMap::new();
-    keys.insert(Keycode::Num1, 0);
-    keys.insert(Keycode::Num2, 0);
-    keys.insert(Keycode::Num3, 0);
-    keys.insert(Keycode::Num4, 0);
-    keys.insert(Keycode::Q, 0);
-    keys.insert(Keycode::W, 0);
-    keys.insert(Keycode::E, 0);
-    keys.insert(Keycode::R, 0);
-    keys.insert(Keycode::A, 0);
-    keys.insert(Keycode::S, 0);
-    keys.insert(Keycode::D, 0);
-    keys.insert(Keycode::F, 0);
-    keys.insert(Keycode::Z, 0);
-    keys.insert(Keycode::X, 0);
-    keys.insert(Keycode::C, 0);
-    keys.insert(Keycode::V, 0);
+    if let Some(path) = start_rom {
+        match load_rom(&path, symbols_path.as_deref(), &forced, &mut emulator, &rom_db) {
+            Ok(title) => {
+                osd.message(&title);
+                cheats = load_cheats(&cheats_dir, &mut emulator, &mut osd);
+                remember_rom(&mut recent, &recent_path, &path);
+                if watch {
+                    watcher = Some(RomWatcher::new(&path));
+                }
+                rom_path = Some(path);
+            }
+            Err(e) => osd.message(&e),
+        }
+    }
+    // --break <name>,... sets debugger breakpoints at labels, constants or
+    // addresses, resolved with the start ROM's symbols.
+    if let Some(i) = args.iter().position(|a| a == "--break") {
+        let names = args.get(i + 1).expect("--break needs labels or addresses");
+        let view = debug_view.as_mut().expect("--break needs --debugger");
+        for name in names.split(',') {
+            match emulator.symbols().resolve(name) {
+                Ok(addr) if !view.breakpoints().contains(&addr) => view.toggle_breakpoint(addr),
+                Ok(_) => {}
+                Err(e) => osd.message(&e),
+            }
+        }
+    }
+    let mut browser = match rom_path {
+        Some(_) => None,
+        None => Some(Browser::open(&rom_dir, &recent, &rom_db)),
+    };
 
     let desired_spec = AudioSpecDesired {
         freq: Some(44100),
@@ -81,48 +228,719 @@ pub fn main() {
         samples: None,     // default sample size
     };
 
-    let device = audio_subsystem
+    let mut device = audio_subsystem
         .open_playback(None, &desired_spec, |spec| {
             // initialize the audio callback
             SquareWave {
-                phase_inc: 440.0 / spec.freq as f32,
+                phase_inc: TONE_HZ / spec.freq as f32,
                 phase: 0.0,
                 volume: 0.25,
             }
         })
         .unwrap();
 
+    let sample_rate = device.spec().freq as f32;
+    let mut last_present = Instant::now();
+    let mut osd_was_visible = false;
+    let mut redraw = true;
+
+    // The ROM browser takes all keys while it is open: arrows, Page Up/Down,
+    // Home and End move, typing filters, Enter loads and Escape clears the
+    // filter or goes back to the running ROM.
+    // Hotkeys: Escape opens the pause menu, P pauses, N advances one frame
+    // while paused, holding Tab fast-forwards, - and = step through slow
+    // motion speeds.
+    // With the debugger open, F5 pauses, F10 steps, F9 toggles a breakpoint
+    // at the PC and Page Up/Down or the mouse wheel scroll memory.
     'running: loop {
-        // Start playback
+        let speed = clock.speed();
+        let paused = clock.is_paused();
+        let mut actions = Vec::new();
+        let mut debug_actions = Vec::new();
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'running,
-                Event::KeyDown { keycode, .. } => match keys.get(&keycode.unwrap()) {
-                    Some(_) => {
-                        keys.insert(keycode.unwrap(), 1);
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if browser.is_some() => {
+                    let mut close = false;
+                    if let Some(browser) = &mut browser {
+                        let page = browser::LIST_ROWS as i32;
+                        let count = browser.visible().len() as i32;
+                        match keycode {
+                            Keycode::Up => browser.move_selection(-1),
+                            Keycode::Down => browser.move_selection(1),
+                            Keycode::PageUp => browser.move_selection(-page),
+                            Keycode::PageDown => browser.move_selection(page),
+                            Keycode::Home => browser.move_selection(-count),
+                            Keycode::End => browser.move_selection(count),
+                            Keycode::Backspace => browser.pop_filter(),
+                            Keycode::Return | Keycode::KpEnter => {
+                                if let Some(entry) = browser.selected() {
+                                    actions.push(MenuAction::LoadRom(entry.path.clone()));
+                                }
+                            }
+                            Keycode::Escape if !browser.filter().is_empty() => browser.clear_filter(),
+                            Keycode::Escape => close = rom_path.is_some(),
+                            _ => {}
+                        }
                     }
-                    None => {}
-                },
-                Event::KeyUp { keycode, .. } => match keys.get(&keycode.unwrap()) {
-                    Some(_) => {
-                        keys.insert(keycode.unwrap(), 0);
+                    if close {
+                        browser = None;
+                    }
+                    redraw = true;
+                }
+                Event::TextInput { text, .. } => {
+                    if let Some(browser) = &mut browser {
+                        browser.push_filter(&text);
+                        redraw = true;
+                    } else if osd.menu().is_some_and(|menu| menu.title().starts_with("Cheats")) {
+                        // Hex digits shift into the value to search for
+                        for digit in text.chars().filter_map(|c| c.to_digit(16)) {
+                            search_value = search_value << 4 | digit as u8;
+                        }
+                        osd.replace_menu(cheat_menu(&search, &cheats, search_value));
+                        redraw = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if osd.is_menu_open() => {
+                    let key = match keycode {
+                        Keycode::Up => Some(MenuKey::Up),
+                        Keycode::Down => Some(MenuKey::Down),
+                        Keycode::Return | Keycode::KpEnter => Some(MenuKey::Select),
+                        Keycode::Escape | Keycode::Backspace => Some(MenuKey::Back),
+                        _ => None,
+                    };
+                    if let Some(action) = key.and_then(|key| osd.menu_key(key)) {
+                        actions.push(action);
                     }
-                    None => {}
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if debug_view.is_some() && debugger::is_debug_key(keycode) => {
+                    if let Some(view) = &mut debug_view {
+                        debug_actions.extend(view.key(keycode, emulator.pc()));
+                    }
+                    redraw = true;
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if let Some(view) = &mut debug_view {
+                        debug_actions.extend(view.click(x, y, emulator.pc()));
+                        redraw = true;
+                    }
+                }
+                Event::MouseWheel { y, .. } => {
+                    if let Some(view) = &mut debug_view {
+                        view.scroll_memory(-y);
+                        redraw = true;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    repeat: false,
+                    timestamp,
+                    ..
+                } => {
+                    osd.open_menu(pause_menu());
+                    if !clock.is_paused() {
+                        clock.toggle_pause();
+                        paused_by_menu = true;
+                    }
+                    emulator.keypad_mut().release_all(Duration::from_millis(timestamp as u64));
+                    redraw = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } if is_hotkey(keycode) => match keycode {
+                    Keycode::P => clock.toggle_pause(),
+                    Keycode::N => clock.advance_frame(),
+                    Keycode::Tab => clock.set_fast_forward(true),
+                    Keycode::Minus => clock.slower(),
+                    _ => clock.faster(),
                 },
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    timestamp,
+                    ..
+                } => {
+                    emulator.key_event(keycode, true, Duration::from_millis(timestamp as u64));
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => clock.set_fast_forward(false),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    timestamp,
+                    ..
+                } => {
+                    emulator.key_event(keycode, false, Duration::from_millis(timestamp as u64));
+                }
                 _ => {}
             }
         }
-        emulator.tick(&keys);
-        if emulator.should_draw() {
-            canvas.clear();
-            emulator.draw(&mut canvas);
-            canvas.present();
+
+        for action in actions {
+            match action {
+                MenuAction::Resume => {}
+                MenuAction::Reset => {
+                    emulator.reset();
+                    osd.message("Reset");
+                }
+                MenuAction::OpenLoadRom => browser = Some(Browser::open(&rom_dir, &recent, &rom_db)),
+                MenuAction::LoadRom(path) => match load_rom(&path, symbols_path.as_deref(), &forced, &mut emulator, &rom_db) {
+                    Ok(title) => {
+                        osd.message(&format!("Loaded {}", title));
+                        osd.set_error(None);
+                        cheats = load_cheats(&cheats_dir, &mut emulator, &mut osd);
+                        search = None;
+                        remember_rom(&mut recent, &recent_path, &path);
+                        if watch {
+                            watcher = Some(RomWatcher::new(&path));
+                        }
+                        rom_path = Some(path);
+                        browser = None;
+                    }
+                    Err(e) => osd.message(&e),
+                },
+                MenuAction::SaveState => {
+                    if let Some(rom_path) = &rom_path {
+                        match fs::write(state_path(rom_path), emulator.save_state()) {
+                            Ok(()) => osd.message("State saved"),
+                            Err(e) => osd.message(&format!("Unable to save state: {}", e)),
+                        }
+                    }
+                }
+                MenuAction::LoadState => {
+                    if let Some(rom_path) = &rom_path {
+                        let loaded = fs::read(state_path(rom_path))
+                            .map_err(|e| e.to_string())
+                            .and_then(|state| emulator.load_state(&state));
+                        match loaded {
+                            Ok(()) => osd.message("State loaded"),
+                            Err(e) => osd.message(&format!("Unable to load state: {}", e)),
+                        }
+                    }
+                }
+                MenuAction::OpenSettings => {
+                    osd.open_menu(settings_menu(&emulator, &osd));
+                    continue;
+                }
+                MenuAction::ToggleTiming => {
+                    emulator.set_timing(match emulator.timing() {
+                        timing::Timing::Fixed => timing::Timing::Vip,
+                        _ => timing::Timing::Fixed,
+                    });
+                    refresh_settings(&mut osd, &emulator);
+                    continue;
+                }
+                MenuAction::ToggleCounter => {
+                    osd.set_show_counter(!osd.show_counter());
+                    refresh_settings(&mut osd, &emulator);
+                    continue;
+                }
+                MenuAction::OpenCheats => {
+                    osd.open_menu(cheat_menu(&search, &cheats, search_value));
+                    continue;
+                }
+                MenuAction::NewSearch => {
+                    search = Some(Search::new(emulator.memory()));
+                    osd.replace_menu(cheat_menu(&search, &cheats, search_value));
+                    continue;
+                }
+                MenuAction::Search(comparison) => {
+                    if let Some(search) = &mut search {
+                        search.refine(emulator.memory(), comparison);
+                    }
+                    osd.replace_menu(cheat_menu(&search, &cheats, search_value));
+                    continue;
+                }
+                MenuAction::PinMatches => {
+                    if let Some(search) = &search {
+                        for &addr in search.candidates() {
+                            cheats.add(Cheat {
+                                kind: CheatKind::Pin,
+                                addr,
+                                value: emulator.memory().get_byte(addr),
+                                name: String::new(),
+                            });
+                        }
+                        osd.message(&format!("Pinned {} addresses", search.candidates().len()));
+                    }
+                    save_cheats(&cheats, &cheats_dir, &emulator, &mut osd);
+                    osd.replace_menu(cheat_menu(&search, &cheats, search_value));
+                    continue;
+                }
+                MenuAction::ClearCheats => {
+                    cheats.clear();
+                    emulator.set_patches(Vec::new());
+                    save_cheats(&cheats, &cheats_dir, &emulator, &mut osd);
+                    osd.message("Cheats removed");
+                    osd.replace_menu(cheat_menu(&search, &cheats, search_value));
+                    continue;
+                }
+                MenuAction::Quit => break 'running,
+            }
+            // Everything else closes the menu and carries on
+            osd.close_menus();
+            if paused_by_menu {
+                clock.toggle_pause();
+                paused_by_menu = false;
+            }
         }
-        if emulator.should_play_sound() {
-            device.resume();
+
+        for action in debug_actions {
+            match action {
+                DebugAction::TogglePause => clock.toggle_pause(),
+                DebugAction::Step => {
+                    if !clock.is_paused() {
+                        clock.toggle_pause();
+                    }
+                    if let Some(view) = &mut debug_view {
+                        view.request_step();
+                    }
+                }
+            }
+        }
+
+        if let Some(watcher) = &mut watcher {
+            if watcher.poll(Instant::now()) {
+                match load_rom(
+                    watcher.path(),
+                    symbols_path.as_deref(),
+                    &forced,
+                    &mut emulator,
+                    &rom_db,
+                ) {
+                    Ok(title) => {
+                        osd.set_error(None);
+                        osd.message(&format!("Reloaded {}", title));
+                        cheats = load_cheats(&cheats_dir, &mut emulator, &mut osd);
+                        search = None;
+                    }
+                    Err(e) => osd.set_error(Some(&format!("Reload failed: {}", e))),
+                }
+                redraw = true;
+            }
+        }
+
+        if clock.speed() != speed {
+            osd.message(&format!("Speed {}", clock.speed()));
+            if let Speed::Scaled(scale) = clock.speed() {
+                device.lock().phase_inc = TONE_HZ * scale as f32 / sample_rate;
+            }
+        }
+        if clock.is_paused() != paused && !osd.is_menu_open() && browser.is_none() {
+            osd.message(if clock.is_paused() { "Paused" } else { "Resumed" });
+        }
+
+        if let Some(gdb) = &mut gdb {
+            gdb.poll(&mut emulator);
+        }
+        let debugger = gdb.as_mut().map(|gdb| gdb.stub_mut()).filter(|stub| stub.is_attached());
+        let debugging = debugger.is_some();
+
+        // Nothing runs behind the ROM browser
+        let running = browser.is_none()
+            && match debugger {
+                Some(stub) => stub.should_run(emulator.pc()),
+                None => match &mut debug_view {
+                    Some(view) => {
+                        let clock_runs = clock.should_run();
+                        view.should_run(emulator.pc(), clock_runs)
+                    }
+                    None => clock.should_run(),
+                },
+            };
+        if let Some(addr) = debug_view.as_mut().and_then(|view| view.take_hit()) {
+            if !clock.is_paused() {
+                clock.toggle_pause();
+            }
+            osd.message(&format!("Breakpoint at {}", emulator.symbols().format_addr(addr)));
+            redraw = true;
+        }
+        let mut drew = false;
+        if running {
+            let pc = emulator.pc();
+            let instr = emulator.tick();
+            if let Some(gdb) = &mut gdb {
+                gdb.stub_mut().ran();
+            }
+            if let Some(view) = &mut debug_view {
+                view.ran(emulator.pc());
+            }
+            redraw |= debug_view.is_some();
+            if let Some(fault) = emulator.take_stack_fault() {
+                osd.message(&fault.to_string());
+                // Stop where it went wrong when there is a debugger to look
+                if debug_view.is_some() && !clock.is_paused() {
+                    clock.toggle_pause();
+                }
+            }
+            for event in emulator.take_write_alerts() {
+                osd.message(&event.alert().unwrap_or_default());
+                if event.blocked && debug_view.is_some() && !clock.is_paused() {
+                    clock.toggle_pause();
+                }
+            }
+            osd.record_tick();
+            if let Some(profiler) = &mut profiler {
+                profiler.record(pc, &instr, emulator.pc());
+            }
+            // A frame is a 60th of a second: FIXED_TICKS_PER_FRAME ticks with
+            // fixed timing, otherwise up to the display interrupt.
+            let end_of_frame = emulator.frame_ended();
+            drew = emulator.should_draw();
+            if end_of_frame {
+                clock.end_frame();
+                cheats.apply_pins(emulator.memory_mut());
+                if let Some(profiler) = &mut profiler {
+                    profiler.end_frame();
+                }
+            }
+
+            let audible = clock.is_normal_speed() || (audio_pitch && clock.speed() != Speed::Uncapped);
+            if emulator.should_play_sound() && audible {
+                device.resume();
+            } else {
+                device.pause();
+            }
         } else {
             device.pause();
         }
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 300));
+
+        // The OSD is redrawn at up to 60 Hz while it shows anything, and once
+        // more after it is gone.
+        let now = Instant::now();
+        let osd_visible = osd.is_visible(now);
+        let osd_due = (osd_visible || osd_was_visible) && now.duration_since(last_present) >= frame_duration;
+        let panel_due = redraw && debug_view.is_some() && now.duration_since(last_present) >= frame_duration;
+        if drew || osd_due || panel_due || (redraw && debug_view.is_none()) {
+            canvas.clear();
+            match &browser {
+                Some(browser) => browser.draw(&mut canvas),
+                None => emulator.draw(&mut canvas),
+            }
+            if let Some(view) = &debug_view {
+                view.draw(&mut canvas, &emulator, clock.is_paused());
+            }
+            osd.draw(&mut canvas, now);
+            canvas.present();
+            last_present = now;
+            osd_was_visible = osd_visible;
+            redraw = false;
+        }
+        if drew {
+            osd.record_frame();
+            if let (Some(heatmap), Some(heatmap_canvas)) = (&heatmap, &mut heatmap_canvas) {
+                heatmap.borrow().draw(heatmap_canvas);
+                heatmap_canvas.present();
+            }
+        }
+
+        if !running {
+            // A halted debugger wants its replies quickly
+            thread::sleep(if debugging { Duration::from_millis(1) } else { frame_duration });
+            continue;
+        }
+        match emulator.timing() {
+            timing::Timing::Fixed => clock.wait(tick_duration),
+            _ => {
+                if emulator.end_of_frame() {
+                    clock.wait(frame_duration);
+                }
+            }
+        }
+    }
+
+    if let (Some(profiler), Some(prefix)) = (&profiler, &profile_export) {
+        if let Err(e) = profiler.export(prefix) {
+            println!("Unable to write profile: {}", e);
+        }
+    }
+    if let (Some(heatmap), Some(prefix)) = (&heatmap, &heatmap_export) {
+        if let Err(e) = heatmap.borrow().export(emulator.memory(), prefix) {
+            println!("Unable to export heatmap: {}", e);
+        }
+    }
+}
+
+fn is_hotkey(keycode: Keycode) -> bool {
+    matches!(
+        keycode,
+        Keycode::P | Keycode::N | Keycode::Tab | Keycode::Minus | Keycode::Equals
+    )
+}
+
+fn pause_menu() -> Menu {
+    Menu::new("Paused")
+        .item("Resume", MenuAction::Resume)
+        .item("Reset", MenuAction::Reset)
+        .item("Load ROM", MenuAction::OpenLoadRom)
+        .item("Save state", MenuAction::SaveState)
+        .item("Load state", MenuAction::LoadState)
+        .item("Settings", MenuAction::OpenSettings)
+        .item("Cheats", MenuAction::OpenCheats)
+        .item("Quit", MenuAction::Quit)
+}
+
+// Starting a search snapshots memory; each comparison then keeps the
+// addresses that behaved that way since the previous step.
+fn cheat_menu(search: &Option<Search>, cheats: &Cheats, value: u8) -> Menu {
+    let title = match search {
+        Some(search) => format!("Cheats: {} matches", search.candidates().len()),
+        None => "Cheats".to_string(),
+    };
+    let mut menu = Menu::new(&title).item("New search", MenuAction::NewSearch);
+    if let Some(search) = search {
+        menu = menu
+            .item(&format!("Equal to 0x{:02X} (type hex)", value), MenuAction::Search(Comparison::Equal(value)))
+            .item("Changed", MenuAction::Search(Comparison::Changed))
+            .item("Unchanged", MenuAction::Search(Comparison::Unchanged))
+            .item("Increased", MenuAction::Search(Comparison::Increased))
+            .item("Decreased", MenuAction::Search(Comparison::Decreased));
+        let matches = search.candidates().len();
+        if matches > 0 && matches <= cheat::MAX_PINS {
+            menu = menu.item(&format!("Pin {} matches", matches), MenuAction::PinMatches);
+        }
+    }
+    if !cheats.is_empty() {
+        menu = menu.item(&format!("Remove {} cheats", cheats.cheats().len()), MenuAction::ClearCheats);
+    }
+    menu
+}
+
+fn settings_menu(emulator: &system::System, osd: &Osd) -> Menu {
+    let timing = match emulator.timing() {
+        timing::Timing::Fixed => "fixed".to_string(),
+        timing::Timing::Vip => "VIP".to_string(),
+        timing::Timing::Tickrate(tickrate) => format!("{} per frame", tickrate),
+    };
+    let counter = if osd.show_counter() { "on" } else { "off" };
+    Menu::new("Settings")
+        .item(&format!("Timing: {}", timing), MenuAction::ToggleTiming)
+        .item(&format!("FPS counter: {}", counter), MenuAction::ToggleCounter)
+}
+
+fn refresh_settings(osd: &mut Osd, emulator: &system::System) {
+    let menu = settings_menu(emulator, osd);
+    osd.replace_menu(menu);
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path)
+}
+
+// --rom-db <dir> loads the chip-8-database from its `database` directory
+// and --rom-overrides <file> local overrides, which set up quirks, tickrate,
+// colours and keys for known ROMs.
+fn rom_db(args: &[String]) -> RomDb {
+    let mut rom_db = RomDb::new();
+    if let Some(i) = args.iter().position(|a| a == "--rom-db") {
+        let dir = args.get(i + 1).expect("--rom-db needs a directory");
+        match RomDb::load_dir(dir) {
+            Ok(db) => rom_db = db,
+            Err(e) => println!("Unable to load ROM database: {}", e),
+        }
+    }
+    if let Some(i) = args.iter().position(|a| a == "--rom-overrides") {
+        let path = args.get(i + 1).expect("--rom-overrides needs a file");
+        if let Err(e) = rom_db.load_overrides(path) {
+            println!("Unable to load ROM overrides: {}", e);
+        }
+    }
+    rom_db
+}
+
+// --protect <regions> blocks writes to regions of the memory map, e.g.
+// --protect font,interpreter, and --self-modifying reports writes to code
+// that has already run. Both are shown on screen and in the trace.
+fn memory_checks(args: &[String], emulator: &mut system::System) {
+    if let Some(i) = args.iter().position(|a| a == "--protect") {
+        let list = args.get(i + 1).expect("--protect needs a list of regions");
+        for id in list.split(',') {
+            let region = Region::by_id(id)
+                .unwrap_or_else(|| panic!("Unknown region {}, expected font, interpreter, program or variables", id));
+            emulator.memory_mut().set_write_protect(region, true);
+        }
+    }
+    emulator.memory_mut().set_report_self_modifying(args.iter().any(|a| a == "--self-modifying"));
+}
+
+// --tui runs the ROM given with --rom in the terminal instead of a window,
+// e.g. over SSH. --tui-bell rings the terminal bell for the buzzer instead of
+// showing it next to the registers.
+fn run_tui(args: &[String]) {
+    let path = args
+        .iter()
+        .position(|a| a == "--rom")
+        .map(|i| args.get(i + 1).expect("--rom needs a file"))
+        .expect("--tui needs --rom <file>");
+    let mut emulator = system::System::new();
+    memory_checks(args, &mut emulator);
+    if args.iter().any(|a| a == "--fx0a-press") {
+        emulator.set_key_wait(KeyWait::Press);
+    }
+    let forced = forced_settings(args, &mut emulator);
+    if let Err(e) = load_rom(path, None, &forced, &mut emulator, &rom_db(args)) {
+        println!("Unable to load ROM: {}", e);
+        return;
+    }
+    let buzzer = if args.iter().any(|a| a == "--tui-bell") {
+        Buzzer::Bell
+    } else {
+        Buzzer::Visual
+    };
+    if let Err(e) = tui::run(&mut emulator, buzzer) {
+        println!("Terminal error: {}", e);
+    }
+}
+
+// Reads the settings that win over the ROM database, for both frontends.
+fn forced_settings(args: &[String], emulator: &mut system::System) -> Forced {
+    // --vip-timing runs instructions at COSMAC VIP speed, one 60 Hz frame of
+    // machine cycles at a time
+    let timing = if args.iter().any(|a| a == "--vip-timing") {
+        Some(timing::Timing::Vip)
+    } else {
+        None
+    };
+    // --machine <id> loads every ROM on that machine profile, e.g. eti660
+    // for programs that start at 0x600. Otherwise the ROM overrides choose.
+    let machine = args.iter().position(|a| a == "--machine").map(|i| {
+        let id = args.get(i + 1).expect("--machine needs a profile");
+        Profile::by_id(id).unwrap_or_else(|| {
+            let ids: Vec<&str> = PROFILES.iter().map(|profile| profile.id).collect();
+            panic!("Unknown machine {}, expected one of {}", id, ids.join(", "))
+        })
+    });
+    // --font <id> draws digits with one interpreter's font whatever the ROM
+    // database says, and --font-base <addr> moves the font, e.g. to 0x050.
+    let font = args.iter().position(|a| a == "--font").map(|i| {
+        let id = args.get(i + 1).expect("--font needs a font set");
+        FontSet::by_id(id).unwrap_or_else(|| {
+            let ids: Vec<&str> = FONT_SETS.iter().map(|font| font.id).collect();
+            panic!("Unknown font {}, expected one of {}", id, ids.join(", "))
+        })
+    });
+    if let Some(i) = args.iter().position(|a| a == "--font-base") {
+        let text = args.get(i + 1).expect("--font-base needs an address");
+        let base = u16::from_str_radix(text.trim_start_matches("0x"), 16).expect("--font-base needs a hex address");
+        emulator.set_font_base(base).expect("--font-base");
+    }
+    Forced { machine, timing, font }
+}
+
+// Settings from the command line that win over the ROM database.
+struct Forced {
+    machine: Option<Profile>,
+    timing: Option<timing::Timing>,
+    font: Option<FontSet>,
+}
+
+// Starts the ROM at `path` with its symbols, returning its title or file
+// name.
+fn load_rom(
+    path: &str,
+    symbols_path: Option<&str>,
+    forced: &Forced,
+    emulator: &mut system::System,
+    rom_db: &RomDb,
+) -> Result<String, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let profile = forced
+        .machine
+        .or_else(|| rom_db.lookup(&data).and_then(|info| info.profile))
+        .unwrap_or(Profile::DEFAULT);
+    emulator.load_program(data, profile).map_err(|e| format!("{}: {}", path, e))?;
+    let default_path = Path::new(path).with_extension("sym");
+    let symbols = match symbols_path {
+        Some(symbols_path) => Symbols::load(symbols_path),
+        None if default_path.exists() => Symbols::load(&default_path.to_string_lossy()),
+        None => Ok(Symbols::new()),
+    };
+    emulator.set_symbols(symbols.unwrap_or_else(|e| {
+        println!("Unable to load symbols: {}", e);
+        Symbols::new()
+    }));
+    let title = apply_rom_info(rom_db, emulator, forced);
+    Ok(title.unwrap_or_else(|| file_name(path).to_string()))
+}
+
+// Recently played ROMs are kept in ~/.chip8-recent and cheats in
+// ~/.chip8-cheats.
+fn home_path(name: &str) -> String {
+    let home = env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(name).to_string_lossy().into_owned()
+}
+
+// Reads the cheats for the loaded ROM and applies its patches.
+fn load_cheats(dir: &str, emulator: &mut system::System, osd: &mut Osd) -> Cheats {
+    match Cheats::load(&cheat::cheat_path(dir, emulator.rom())) {
+        Ok(cheats) => {
+            emulator.set_patches(cheats.patches());
+            if !cheats.is_empty() {
+                osd.message(&format!("{} cheats", cheats.cheats().len()));
+            }
+            cheats
+        }
+        Err(e) => {
+            osd.message(&e);
+            Cheats::new()
+        }
+    }
+}
+
+fn save_cheats(cheats: &Cheats, dir: &str, emulator: &system::System, osd: &mut Osd) {
+    if let Err(e) = cheats.save(&cheat::cheat_path(dir, emulator.rom())) {
+        osd.message(&format!("Unable to save cheats: {}", e));
     }
 }
+
+fn remember_rom(recent: &mut Recent, recent_path: &str, rom_path: &str) {
+    let full_path = fs::canonicalize(rom_path).map_or(rom_path.to_string(), |path| path.to_string_lossy().into_owned());
+    recent.add(&full_path);
+    if let Err(e) = recent.save(recent_path) {
+        println!("Unable to save recent ROMs: {}", e);
+    }
+}
+
+// Sets up the emulator for the loaded ROM from the database, returning its
+// title if known.
+fn apply_rom_info(
+    rom_db: &RomDb,
+    emulator: &mut system::System,
+    forced: &Forced,
+) -> Option<String> {
+    let mut info = rom_db
+        .lookup(emulator.rom())
+        .unwrap_or_else(|| RomInfo::unknown(emulator.rom()));
+    if let Some(font) = forced.font {
+        info.font = font;
+    }
+    info.apply(emulator, forced.timing);
+    if let Some(title) = &info.title {
+        println!("{} ({})", title, info.platform.map_or("unknown platform", |platform| platform.name));
+    }
+    info.title
+}
+
+fn state_path(rom_path: &str) -> String {
+    format!("{}.state", rom_path)
+}
@@ -1,47 +1,383 @@
+use crate::cpu::{Cpu, Instruction};
+use crate::font::{self, FontSet, LARGE_GLYPH_SIZE, SMALL_FONT_SIZE, SMALL_GLYPH_SIZE};
+
+// The most a 12-bit address reaches, and the memory of a 4K VIP. Machines
+// with less memory see it repeated.
+pub const RAM_SIZE: usize = 4096;
+pub const PROGRAM_START: usize = 0x200;
+// The largest ROM any machine profile can load.
+pub const MAX_ROM_SIZE: usize = RAM_SIZE - PROGRAM_START;
+
+// The memory map of the original COSMAC VIP interpreter layout, moved to
+// where the machine profile loads programs and ends memory. See
+// `Memory::region_of`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Font,
+    Interpreter,
+    Program,
+    Variables,
+}
+
+// Blocked and self-modifying writes are kept for the frontend up to this
+// many, then the oldest go.
+pub const MAX_WRITE_ALERTS: usize = 64;
+
+// The VIP interpreter keeps its stack and variables in the top 0x160 bytes,
+// 0xEA0-0xFFF with 4K.
+const VARIABLES_SIZE: usize = 0x160;
+
+impl Region {
+    pub fn by_id(id: &str) -> Option<Region> {
+        match id {
+            "font" => Some(Region::Font),
+            "interpreter" => Some(Region::Interpreter),
+            "program" => Some(Region::Program),
+            "variables" | "stack" => Some(Region::Variables),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Region::Font => write!(f, "font"),
+            Region::Interpreter => write!(f, "interpreter"),
+            Region::Program => write!(f, "program"),
+            Region::Variables => write!(f, "stack/variables"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+    // Read of sprite data by DXYN
+    Sprite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryEvent {
+    pub access: Access,
+    pub addr: u16,
+    pub val: u8,
+    pub region: Region,
+    // A write into a write protected region, which was not performed.
+    pub blocked: bool,
+    // A write to an address that has already been executed.
+    pub self_modifying: bool,
+}
+
+impl MemoryEvent {
+    // What to tell the user about a blocked or self-modifying write.
+    pub fn alert(&self) -> Option<String> {
+        if self.blocked {
+            Some(format!("Blocked write of 0x{:02X} to {} at 0x{:03X}", self.val, self.region, self.addr))
+        } else if self.self_modifying {
+            Some(format!("Self-modifying write of 0x{:02X} at 0x{:03X}", self.val, self.addr))
+        } else {
+            None
+        }
+    }
+}
+
+pub type Hook = Box<dyn FnMut(&MemoryEvent)>;
+
 pub struct Memory {
-    ram: [u8; 4096],
-}
-const FONTS: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+    ram: [u8; RAM_SIZE],
+    executed: [bool; RAM_SIZE],
+    protected: Vec<Region>,
+    hooks: Vec<Hook>,
+    // Blocked writes, and self-modifying ones if asked for, not yet taken.
+    write_alerts: Vec<MemoryEvent>,
+    report_self_modifying: bool,
+    // Instructions already decoded at each address. An entry covers two
+    // bytes, so a write drops the entries at its address and the one before.
+    decoded: Vec<Option<Instruction>>,
+    decode_cache: bool,
+    // Installed memory, at most RAM_SIZE, and where programs are loaded.
+    size: usize,
+    program_start: usize,
+    font: FontSet,
+    font_base: u16,
+}
 
 impl Memory {
     pub fn new() -> Memory {
-        let mut new_mem = Memory { ram: [0; 4096] };
-        new_mem.ram[0..80].copy_from_slice(&FONTS[0..80]);
+        let mut new_mem = Memory {
+            ram: [0; RAM_SIZE],
+            executed: [false; RAM_SIZE],
+            protected: Vec::new(),
+            hooks: Vec::new(),
+            write_alerts: Vec::new(),
+            report_self_modifying: false,
+            decoded: vec![None; RAM_SIZE],
+            decode_cache: true,
+            size: RAM_SIZE,
+            program_start: PROGRAM_START,
+            font: FontSet::DEFAULT,
+            font_base: font::DEFAULT_FONT_BASE,
+        };
+        new_mem.install_font();
 
         new_mem
     }
 
+    // Addresses wrap around at the end of RAM, as on the 12-bit address bus.
+    // `get_instruction` and `get_byte` only peek; `fetch`, `read` and `set`
+    // are accesses by the running program and go through the hooks.
     pub fn get_instruction(&self, addr: u16) -> u16 {
-        let left = (self.ram[addr as usize] as u16) << 8;
-        let right = self.ram[(addr + 1) as usize] as u16;
+        let left = (self.get_byte(addr) as u16) << 8;
+        let right = self.get_byte(addr.wrapping_add(1)) as u16;
         left | right
     }
-    pub fn get_byte(&self, addr:u16) -> u8{
-        self.ram[addr as usize]
+    pub fn get_byte(&self, addr: u16) -> u8 {
+        self.ram[addr as usize % self.size]
+    }
+
+    pub fn fetch(&mut self, addr: u16) -> u16 {
+        for addr in [addr, addr.wrapping_add(1)] {
+            self.executed[addr as usize % self.size] = true;
+            self.notify(Access::Execute, addr, self.get_byte(addr), false, false);
+        }
+        self.get_instruction(addr)
+    }
+    // Same accesses as `fetch`, decoding each address only once until the
+    // code there is written to. Without hooks a cached instruction is
+    // returned straight away: both its bytes were marked executed when it
+    // was decoded, and only a reset clears those marks and the cache.
+    pub fn fetch_decoded(&mut self, addr: u16) -> Instruction {
+        if !self.decode_cache {
+            return Cpu::decode(self.fetch(addr));
+        }
+        let at = addr as usize % self.size;
+        match self.decoded[at] {
+            Some(instr) if self.hooks.is_empty() => instr,
+            Some(instr) => {
+                self.fetch(addr);
+                instr
+            }
+            None => {
+                let instr = Cpu::decode(self.fetch(addr));
+                self.decoded[at] = Some(instr);
+                instr
+            }
+        }
+    }
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.notify(Access::Read, addr, self.get_byte(addr), false, false);
+        self.get_byte(addr)
+    }
+    pub fn read_sprite(&mut self, addr: u16) -> u8 {
+        self.notify(Access::Sprite, addr, self.get_byte(addr), false, false);
+        self.get_byte(addr)
     }
 
     pub fn set(&mut self, addr: usize, val: u8) {
-        self.ram[addr] = val
+        let addr = addr % self.size;
+        let region = self.region_of(addr as u16);
+        let blocked = self.protected.contains(&region);
+        if !blocked {
+            self.ram[addr] = val;
+            self.decoded[addr] = None;
+            self.decoded[(addr + self.size - 1) % self.size] = None;
+        }
+        let self_modifying = self.executed[addr];
+        if blocked || (self_modifying && self.report_self_modifying) {
+            if self.write_alerts.len() == MAX_WRITE_ALERTS {
+                self.write_alerts.remove(0);
+            }
+            let event = self.event(Access::Write, addr as u16, val, blocked, self_modifying);
+            self.write_alerts.push(event);
+        }
+        self.notify(Access::Write, addr as u16, val, blocked, self_modifying);
+    }
+
+    // A write from outside the program, e.g. a debugger: it is not seen by
+    // the hooks and ignores write protection.
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        let addr = addr as usize % self.size;
+        self.ram[addr] = val;
+        self.decoded[addr] = None;
+        self.decoded[(addr + self.size - 1) % self.size] = None;
     }
 
-    pub fn load(&mut self, data: Vec<u8>) {
-        self.ram[0x200..(0x200 + data.len())].copy_from_slice(&data);
+    pub fn set_write_protect(&mut self, region: Region, protect: bool) {
+        self.protected.retain(|r| *r != region);
+        if protect {
+            self.protected.push(region);
+        }
+    }
+    pub fn is_write_protected(&self, region: Region) -> bool {
+        self.protected.contains(&region)
+    }
+    // Whether writes to code that has already run are kept with the blocked
+    // ones for `take_write_alerts`.
+    pub fn set_report_self_modifying(&mut self, report: bool) {
+        self.report_self_modifying = report;
+    }
+    pub fn take_write_alerts(&mut self) -> Vec<MemoryEvent> {
+        std::mem::take(&mut self.write_alerts)
+    }
+
+    pub fn add_hook(&mut self, hook: Hook) {
+        self.hooks.push(hook);
+    }
+    pub fn clear_hooks(&mut self) {
+        self.hooks.clear();
+    }
+
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+        self.clear_decoded();
+    }
+    pub fn is_decoded(&self, addr: u16) -> bool {
+        self.decoded[addr as usize % self.size].is_some()
+    }
+    fn clear_decoded(&mut self) {
+        self.decoded.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    pub fn was_executed(&self, addr: u16) -> bool {
+        self.executed[addr as usize % self.size]
+    }
+
+    fn notify(&mut self, access: Access, addr: u16, val: u8, blocked: bool, self_modifying: bool) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let event = self.event(access, addr, val, blocked, self_modifying);
+        for hook in self.hooks.iter_mut() {
+            hook(&event);
+        }
+    }
+    fn event(&self, access: Access, addr: u16, val: u8, blocked: bool, self_modifying: bool) -> MemoryEvent {
+        MemoryEvent {
+            access,
+            addr: (addr as usize % self.size) as u16,
+            val,
+            region: self.region_of(addr),
+            blocked,
+            self_modifying,
+        }
+    }
+
+    // Sets the installed memory and the load address, which must lie within
+    // it. Takes effect from the next `load`.
+    pub fn set_layout(&mut self, program_start: usize, size: usize) {
+        assert!(size <= RAM_SIZE && program_start < size, "bad memory layout");
+        self.size = size;
+        self.program_start = program_start;
+    }
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    // Which part of the memory map `addr` is in. The font is wherever it was
+    // installed, small and large glyphs, the rest below the program is the
+    // interpreter's own, and the stack and variables take the top of
+    // installed memory, above the program.
+    pub fn region_of(&self, addr: u16) -> Region {
+        let addr = addr as usize % self.size;
+        let font_start = self.font_base as usize;
+        let variables_start = self.size.saturating_sub(VARIABLES_SIZE).max(self.program_start);
+        if (font_start..font_start + self.font.size()).contains(&addr) {
+            Region::Font
+        } else if addr < self.program_start {
+            Region::Interpreter
+        } else if addr < variables_start {
+            Region::Program
+        } else {
+            Region::Variables
+        }
+    }
+    pub fn program_start(&self) -> usize {
+        self.program_start
+    }
+    pub fn max_rom_size(&self) -> usize {
+        self.size - self.program_start
+    }
+
+    // Replaces the font, which must end before the program. The old glyphs
+    // are cleared, so the change can be made after a ROM is loaded.
+    pub fn set_font(&mut self, font: FontSet, base: u16) -> Result<(), String> {
+        let end = base as usize + font.size();
+        if end > self.program_start {
+            return Err(format!(
+                "the {} font needs 0x{:03X}-0x{:03X}, which runs into the program at 0x{:03X}",
+                font.name,
+                base,
+                end - 1,
+                self.program_start
+            ));
+        }
+        let old = self.font_base as usize;
+        for byte in &mut self.ram[old..old + self.font.size()] {
+            *byte = 0;
+        }
+        self.font = font;
+        self.font_base = base;
+        self.install_font();
+        self.clear_decoded();
+        Ok(())
+    }
+    fn install_font(&mut self) {
+        let base = self.font_base as usize;
+        self.ram[base..base + SMALL_FONT_SIZE].copy_from_slice(self.font.small);
+        if let Some(large) = self.font.large {
+            let start = base + SMALL_FONT_SIZE;
+            self.ram[start..start + large.len()].copy_from_slice(large);
+        }
+    }
+    pub fn font(&self) -> FontSet {
+        self.font
+    }
+    pub fn font_base(&self) -> u16 {
+        self.font_base
+    }
+    // Where FX29 points I for a digit.
+    pub fn font_address(&self, digit: u8) -> u16 {
+        self.font_base + (digit & 0xF) as u16 * SMALL_GLYPH_SIZE as u16
+    }
+    // Where FX30 points I, if the font has large glyphs.
+    pub fn large_font_address(&self, digit: u8) -> Option<u16> {
+        self.font.large?;
+        Some(self.font_base + (SMALL_FONT_SIZE + (digit & 0xF) as usize * LARGE_GLYPH_SIZE) as u16)
+    }
+
+    pub fn load(&mut self, data: Vec<u8>) -> Result<(), String> {
+        if data.len() > self.max_rom_size() {
+            return Err(format!(
+                "ROM is {} bytes, at most {} fit at 0x{:03X} in {} bytes of memory",
+                data.len(),
+                self.max_rom_size(),
+                self.program_start,
+                self.size
+            ));
+        }
+        let start = self.program_start;
+        self.ram[start..start + data.len()].copy_from_slice(&data);
+        self.clear_decoded();
+        Ok(())
+    }
+
+    // Back to power-on contents. Hooks and write protection stay.
+    pub fn reset(&mut self) {
+        self.ram = [0; RAM_SIZE];
+        self.install_font();
+        self.executed = [false; RAM_SIZE];
+        self.clear_decoded();
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+    // Replaces all of RAM, e.g. from a save state. Nothing in it has run yet.
+    pub fn set_ram(&mut self, ram: &[u8]) {
+        self.ram.copy_from_slice(ram);
+        self.executed = [false; RAM_SIZE];
+        self.clear_decoded();
     }
 }
@@ -0,0 +1,216 @@
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Instruction;
+    use crate::font;
+    use crate::mem::{Access, Memory, MemoryEvent, Region};
+    use crate::system;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn record(mem: &mut Memory) -> Rc<RefCell<Vec<MemoryEvent>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        mem.add_hook(Box::new(move |event| sink.borrow_mut().push(*event)));
+        events
+    }
+
+    #[test]
+    fn regions() {
+        let mut mem = Memory::new();
+        assert_eq!(mem.region_of(0x000), Region::Font);
+        assert_eq!(mem.region_of(0x04F), Region::Font);
+        assert_eq!(mem.region_of(0x100), Region::Interpreter);
+        assert_eq!(mem.region_of(0x200), Region::Program);
+        assert_eq!(mem.region_of(0xE9F), Region::Program);
+        assert_eq!(mem.region_of(0xEA0), Region::Variables);
+        assert_eq!(mem.region_of(0xFFF), Region::Variables);
+
+        // ETI-660 programs start at 0x600
+        mem.set_layout(0x600, 4096);
+        assert_eq!(mem.region_of(0x5FF), Region::Interpreter);
+        assert_eq!(mem.region_of(0x600), Region::Program);
+
+        // the variables are at the top of a 2K VIP, and memory repeats above
+        mem.set_layout(0x200, 2048);
+        assert_eq!(mem.region_of(0x69F), Region::Program);
+        assert_eq!(mem.region_of(0x6A0), Region::Variables);
+        assert_eq!(mem.region_of(0x7FF), Region::Variables);
+        assert_eq!(mem.region_of(0xEA0), Region::Variables);
+        assert_eq!(mem.region_of(0x800), Region::Font);
+
+        // the font covers the large glyphs, wherever it is
+        assert_eq!(mem.region_of(0x050), Region::Font);
+        assert_eq!(mem.region_of(0x0EF), Region::Font);
+        assert_eq!(mem.region_of(0x0F0), Region::Interpreter);
+        mem.set_font(font::VIP, 0x050).unwrap();
+        assert_eq!(mem.region_of(0x000), Region::Interpreter);
+        assert_eq!(mem.region_of(0x050), Region::Font);
+        assert_eq!(mem.region_of(0x09F), Region::Font);
+        assert_eq!(mem.region_of(0x0A0), Region::Interpreter);
+    }
+
+    #[test]
+    fn hooks_see_every_access() {
+        let mut mem = Memory::new();
+        mem.load(vec![0x12, 0x34]).unwrap();
+        let events = record(&mut mem);
+
+        assert_eq!(mem.fetch(0x200), 0x1234);
+        assert_eq!(mem.read(0x000), 0xF0);
+        mem.set(0x300, 0xAB);
+        assert_eq!(mem.get_byte(0x300), 0xAB);
+
+        let accesses: Vec<(Access, u16, u8)> = events.borrow().iter().map(|e| (e.access, e.addr, e.val)).collect();
+        assert_eq!(
+            accesses,
+            vec![
+                (Access::Execute, 0x200, 0x12),
+                (Access::Execute, 0x201, 0x34),
+                (Access::Read, 0x000, 0xF0),
+                (Access::Write, 0x300, 0xAB),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_protection_blocks_and_reports() {
+        let mut mem = Memory::new();
+        mem.set_write_protect(Region::Font, true);
+        let events = record(&mut mem);
+
+        mem.set(0x000, 0x00);
+        mem.set(0x100, 0x11);
+        assert_eq!(mem.get_byte(0x000), 0xF0);
+        assert_eq!(mem.get_byte(0x100), 0x11);
+
+        let blocked: Vec<(u16, u8, Region)> =
+            events.borrow().iter().filter(|e| e.blocked).map(|e| (e.addr, e.val, e.region)).collect();
+        assert_eq!(blocked, vec![(0x000, 0x00, Region::Font)]);
+
+        mem.set_write_protect(Region::Font, false);
+        mem.set(0x000, 0x00);
+        assert_eq!(mem.get_byte(0x000), 0x00);
+    }
+
+    #[test]
+    fn self_modifying_code_is_detected() {
+        let mut mem = Memory::new();
+        let events = record(&mut mem);
+
+        mem.fetch(0x200);
+        mem.set(0x201, 0xFF);
+        mem.set(0x202, 0xFF);
+
+        let self_modifying: Vec<u16> = events.borrow().iter().filter(|e| e.self_modifying).map(|e| e.addr).collect();
+        assert_eq!(self_modifying, vec![0x201]);
+
+        // code that ran before a state was loaded does not count
+        let ram = mem.ram().to_vec();
+        mem.set_ram(&ram);
+        events.borrow_mut().clear();
+        mem.set(0x200, 0xFF);
+        assert!(!events.borrow()[0].self_modifying);
+    }
+
+    #[test]
+    fn protected_font_survives_reg_dump() {
+        let mut emu = system::System::new();
+        // V0 = 0xAA, I = 0x000, dump V0
+        emu.load_rom(vec![0x60, 0xAA, 0xA0, 0x00, 0xF0, 0x55]).unwrap();
+        emu.memory_mut().set_write_protect(Region::Font, true);
+        for _ in 0..3 {
+            emu.tick();
+        }
+        assert_eq!(emu.memory().get_byte(0x000), 0xF0);
+    }
+
+    #[test]
+    fn blocked_and_self_modifying_writes_reach_the_frontend() {
+        assert_eq!(Region::by_id("interpreter"), Some(Region::Interpreter));
+        assert_eq!(Region::by_id("stack"), Some(Region::Variables));
+        assert_eq!(Region::by_id("rom"), None);
+
+        let mut emu = system::System::new();
+        emu.set_trace(false);
+        // I = 0x000, dump V0, I = 0x200, dump V0
+        emu.load_rom(vec![0xA0, 0x00, 0xF0, 0x55, 0xA2, 0x00, 0xF0, 0x55]).unwrap();
+        emu.memory_mut().set_write_protect(Region::Font, true);
+        for _ in 0..2 {
+            emu.tick();
+        }
+        let alerts = emu.take_write_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].alert().unwrap(), "Blocked write of 0x00 to font at 0x000");
+        assert!(emu.take_write_alerts().is_empty());
+
+        // self-modifying writes only when asked for
+        for _ in 0..2 {
+            emu.tick();
+        }
+        assert!(emu.take_write_alerts().is_empty());
+        emu.reset();
+        emu.memory_mut().set_report_self_modifying(true);
+        for _ in 0..4 {
+            emu.tick();
+        }
+        let alerts: Vec<String> = emu.take_write_alerts().iter().filter_map(|e| e.alert()).collect();
+        assert_eq!(alerts, vec!["Blocked write of 0x00 to font at 0x000", "Self-modifying write of 0x00 at 0x200"]);
+    }
+
+    #[test]
+    fn writes_invalidate_decoded_instructions() {
+        let mut mem = Memory::new();
+        mem.load(vec![0x12, 0x34, 0x66, 0x78]).unwrap();
+        assert_eq!(mem.fetch_decoded(0x200), Instruction::Jump(0x234));
+        assert_eq!(mem.fetch_decoded(0x202), Instruction::SetRegister(0x6, 0x78));
+        assert!(mem.is_decoded(0x200) && mem.is_decoded(0x202));
+
+        // the second byte of 0x200 is written, the instruction at 0x202 stays
+        mem.set(0x201, 0x00);
+        assert!(!mem.is_decoded(0x200));
+        assert!(mem.is_decoded(0x202));
+        assert_eq!(mem.fetch_decoded(0x200), Instruction::Jump(0x200));
+
+        mem.set_decode_cache(false);
+        assert_eq!(mem.fetch_decoded(0x202), Instruction::SetRegister(0x6, 0x78));
+        assert!(!mem.is_decoded(0x202));
+    }
+
+    #[test]
+    fn cached_instructions_still_reach_the_hooks() {
+        let mut mem = Memory::new();
+        mem.load(vec![0x12, 0x00]).unwrap();
+        mem.fetch_decoded(0x200);
+        assert!(mem.was_executed(0x200) && mem.was_executed(0x201));
+
+        // once hooks are added, cached fetches are seen like any other
+        let events = record(&mut mem);
+        assert_eq!(mem.fetch_decoded(0x200), Instruction::Jump(0x200));
+        let fetched: Vec<(Access, u16)> = events.borrow().iter().map(|e| (e.access, e.addr)).collect();
+        assert_eq!(fetched, vec![(Access::Execute, 0x200), (Access::Execute, 0x201)]);
+
+        mem.reset();
+        assert!(!mem.was_executed(0x200) && !mem.is_decoded(0x200));
+    }
+
+    #[test]
+    fn self_modifying_program_runs_the_new_code() {
+        let mut emu = system::System::new();
+        emu.load_rom(vec![
+            0xA2, 0x09, // I = 0x209
+            0x60, 0x42, // V0 = 0x42
+            0x12, 0x08, // jump 0x208
+            0xF0, 0x55, // store V0 at 0x209
+            0x61, 0x05, // V1 = 0x05, patched to V1 = 0x42
+            0x12, 0x06, // jump 0x206
+        ])
+        .unwrap();
+        for _ in 0..5 {
+            emu.tick();
+        }
+        emu.test_eq_v(1, 0x05);
+        emu.tick();
+        emu.tick();
+        emu.test_eq_v(1, 0x42);
+    }
+}
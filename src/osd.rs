@@ -0,0 +1,424 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use std::time::{Duration, Instant};
+
+use crate::cheat::Comparison;
+use crate::video::{PIXEL_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Text is drawn with a 3x5 bitmap font, each font pixel SCALE screen pixels
+// wide, with one empty column between characters.
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+pub(crate) const SCALE: i32 = 4;
+pub(crate) const LINE_HEIGHT: i32 = (GLYPH_HEIGHT as i32 + 2) * SCALE;
+pub(crate) const MARGIN: i32 = 2 * SCALE;
+pub(crate) const WINDOW_WIDTH: i32 = SCREEN_WIDTH as i32 * PIXEL_SIZE as i32;
+pub(crate) const WINDOW_HEIGHT: i32 = SCREEN_HEIGHT as i32 * PIXEL_SIZE as i32;
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+const MAX_MESSAGES: usize = 4;
+const MENU_ROWS: usize = 10;
+
+pub(crate) const TEXT_COLOR: Color = Color::RGB(255, 255, 0);
+pub(crate) const SELECTED_COLOR: Color = Color::RGB(0, 255, 255);
+const ERROR_COLOR: Color = Color::RGB(255, 64, 64);
+const BACKGROUND: Color = Color::RGBA(0, 0, 0, 192);
+
+// Rows of a character, three bits each with the leftmost pixel in bit 2.
+// Lower case is drawn as upper case, anything unknown as '?'.
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '[' => [0b011, 0b010, 0b010, 0b010, 0b011],
+        ']' => [0b110, 0b010, 0b010, 0b010, 0b110],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+// Width of `text` in font pixels.
+pub fn text_width(text: &str) -> usize {
+    let chars = text.chars().count();
+    if chars == 0 {
+        0
+    } else {
+        chars * (GLYPH_WIDTH + 1) - 1
+    }
+}
+
+// Lit font pixels of `text` as (x, y), with the top left corner at (0, 0).
+pub fn text_pixels(text: &str) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        for (y, row) in glyph(c).iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (0b100 >> x) != 0 {
+                    pixels.push((i * (GLYPH_WIDTH + 1) + x, y));
+                }
+            }
+        }
+    }
+    pixels
+}
+
+// Splits `text` into lines of at most `columns` characters, at spaces where
+// possible.
+pub fn wrap_text(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let mut word: Vec<char> = word.chars().collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.len() > columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        while line.chars().count() + word.len() > columns {
+            let split = columns - line.chars().count();
+            line.extend(word.drain(..split));
+            lines.push(std::mem::take(&mut line));
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuAction {
+    Resume,
+    Reset,
+    OpenLoadRom,
+    LoadRom(String),
+    SaveState,
+    LoadState,
+    OpenSettings,
+    ToggleTiming,
+    ToggleCounter,
+    OpenCheats,
+    NewSearch,
+    Search(Comparison),
+    PinMatches,
+    ClearCheats,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Menu {
+    title: String,
+    items: Vec<(String, MenuAction)>,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(title: &str) -> Menu {
+        Menu {
+            title: title.to_string(),
+            items: Vec::new(),
+            selected: 0,
+        }
+    }
+    pub fn item(mut self, label: &str, action: MenuAction) -> Menu {
+        self.items.push((label.to_string(), action));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+    pub fn selected(&self) -> Option<&MenuAction> {
+        self.items.get(self.selected).map(|(_, action)| action)
+    }
+}
+
+// Keys the OSD understands while a menu is open.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MenuKey {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+// Instructions and presented frames per second, measured over one second.
+struct Counter {
+    instructions: u64,
+    frames: u64,
+    since: Instant,
+    text: String,
+}
+
+// Everything drawn over the CHIP-8 display: transient messages, the FPS/IPS
+// counter, an error that stays until cleared and the pause menu with its
+// submenus.
+pub struct Osd {
+    messages: Vec<(String, Instant)>,
+    error: Option<String>,
+    show_counter: bool,
+    counter: Counter,
+    menus: Vec<Menu>,
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        Osd {
+            messages: Vec::new(),
+            error: None,
+            show_counter: false,
+            counter: Counter {
+                instructions: 0,
+                frames: 0,
+                since: Instant::now(),
+                text: String::new(),
+            },
+            menus: Vec::new(),
+        }
+    }
+
+    pub fn message(&mut self, text: &str) {
+        self.message_at(text, Instant::now());
+    }
+    pub fn message_at(&mut self, text: &str, now: Instant) {
+        self.messages.push((text.to_string(), now));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+    // Messages shown at `now`, oldest first.
+    pub fn messages(&self, now: Instant) -> Vec<&str> {
+        self.messages
+            .iter()
+            .filter(|(_, shown)| now.duration_since(*shown) < MESSAGE_DURATION)
+            .map(|(text, _)| text.as_str())
+            .collect()
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+    pub fn set_error(&mut self, error: Option<&str>) {
+        self.error = error.map(|error| error.to_string());
+    }
+
+    pub fn show_counter(&self) -> bool {
+        self.show_counter
+    }
+    pub fn set_show_counter(&mut self, show: bool) {
+        self.show_counter = show;
+    }
+    pub fn record_tick(&mut self) {
+        self.counter.instructions += 1;
+    }
+    pub fn record_frame(&mut self) {
+        self.counter.frames += 1;
+    }
+    // "FPS 60 IPS 300", refreshed once a second.
+    pub fn counter_text(&mut self, now: Instant) -> &str {
+        let elapsed = now.duration_since(self.counter.since).as_secs_f64();
+        if elapsed >= 1.0 {
+            self.counter.text = format!(
+                "FPS {:.0} IPS {:.0}",
+                self.counter.frames as f64 / elapsed,
+                self.counter.instructions as f64 / elapsed
+            );
+            self.counter.instructions = 0;
+            self.counter.frames = 0;
+            self.counter.since = now;
+        }
+        &self.counter.text
+    }
+
+    pub fn open_menu(&mut self, menu: Menu) {
+        self.menus.push(menu);
+    }
+    // Swaps the open menu for an updated one, keeping the selected row.
+    pub fn replace_menu(&mut self, mut menu: Menu) {
+        if let Some(old) = self.menus.pop() {
+            menu.selected = old.selected.min(menu.items.len().saturating_sub(1));
+        }
+        self.menus.push(menu);
+    }
+    pub fn close_menus(&mut self) {
+        self.menus.clear();
+    }
+    pub fn menu(&self) -> Option<&Menu> {
+        self.menus.last()
+    }
+    pub fn is_menu_open(&self) -> bool {
+        !self.menus.is_empty()
+    }
+    // Handles a key for the open menu, returning the action to carry out.
+    // Backing out of the last menu resumes.
+    pub fn menu_key(&mut self, key: MenuKey) -> Option<MenuAction> {
+        let menu = self.menus.last_mut()?;
+        let count = menu.items.len().max(1);
+        match key {
+            MenuKey::Up => menu.selected = (menu.selected + count - 1) % count,
+            MenuKey::Down => menu.selected = (menu.selected + 1) % count,
+            MenuKey::Select => return menu.selected().cloned(),
+            MenuKey::Back => {
+                self.menus.pop();
+                if self.menus.is_empty() {
+                    return Some(MenuAction::Resume);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn is_visible(&self, now: Instant) -> bool {
+        self.show_counter || self.error.is_some() || self.is_menu_open() || !self.messages(now).is_empty()
+    }
+
+    pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, now: Instant) {
+        canvas.set_blend_mode(BlendMode::Blend);
+        if self.show_counter {
+            let text = self.counter_text(now).to_string();
+            let x = WINDOW_WIDTH - MARGIN - text_width(&text) as i32 * SCALE;
+            draw_boxed_text(canvas, &text, x, MARGIN, TEXT_COLOR);
+        }
+        let messages: Vec<String> = self.messages(now).iter().map(|m| m.to_string()).collect();
+        for (i, text) in messages.iter().rev().enumerate() {
+            let y = WINDOW_HEIGHT - MARGIN - (i as i32 + 1) * LINE_HEIGHT;
+            draw_boxed_text(canvas, text, MARGIN, y, TEXT_COLOR);
+        }
+        if let Some(error) = &self.error {
+            draw_error(canvas, error);
+        }
+        if let Some(menu) = self.menu() {
+            draw_menu(canvas, menu);
+        }
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+pub(crate) fn draw_text<T: RenderTarget>(canvas: &mut Canvas<T>, text: &str, x: i32, y: i32, color: Color) {
+    draw_text_scaled(canvas, text, x, y, SCALE, color);
+}
+
+// Text with each font pixel `scale` screen pixels wide.
+pub(crate) fn draw_text_scaled<T: RenderTarget>(
+    canvas: &mut Canvas<T>,
+    text: &str,
+    x: i32,
+    y: i32,
+    scale: i32,
+    color: Color,
+) {
+    canvas.set_draw_color(color);
+    for (px, py) in text_pixels(text) {
+        canvas
+            .fill_rect(Rect::new(x + px as i32 * scale, y + py as i32 * scale, scale as u32, scale as u32))
+            .expect("Unable to draw rect!");
+    }
+}
+
+pub(crate) fn draw_background<T: RenderTarget>(canvas: &mut Canvas<T>, x: i32, y: i32, width: i32, height: i32) {
+    canvas.set_draw_color(BACKGROUND);
+    canvas
+        .fill_rect(Rect::new(x, y, width.max(1) as u32, height.max(1) as u32))
+        .expect("Unable to draw rect!");
+}
+
+fn draw_boxed_text<T: RenderTarget>(canvas: &mut Canvas<T>, text: &str, x: i32, y: i32, color: Color) {
+    let width = text_width(text) as i32 * SCALE;
+    draw_background(canvas, x - SCALE, y - SCALE, width + 2 * SCALE, LINE_HEIGHT);
+    draw_text(canvas, text, x, y, color);
+}
+
+// Centred across the top, below the counter.
+fn draw_error<T: RenderTarget>(canvas: &mut Canvas<T>, error: &str) {
+    let columns = ((WINDOW_WIDTH - 4 * MARGIN) / ((GLYPH_WIDTH as i32 + 1) * SCALE)) as usize;
+    for (row, line) in wrap_text(error, columns).iter().enumerate() {
+        let x = (WINDOW_WIDTH - text_width(line) as i32 * SCALE) / 2;
+        let y = MARGIN + (row as i32 + 1) * LINE_HEIGHT;
+        draw_boxed_text(canvas, line, x, y, ERROR_COLOR);
+    }
+}
+
+// The selected item is kept in view when there are more than MENU_ROWS.
+fn draw_menu<T: RenderTarget>(canvas: &mut Canvas<T>, menu: &Menu) {
+    let first = (menu.selected + 1).saturating_sub(MENU_ROWS);
+    let rows: Vec<(usize, &(String, MenuAction))> = menu.items.iter().enumerate().skip(first).take(MENU_ROWS).collect();
+
+    let widest = rows
+        .iter()
+        .map(|(_, (label, _))| text_width(label) + 2 * (GLYPH_WIDTH + 1))
+        .chain(std::iter::once(text_width(&menu.title)))
+        .max()
+        .unwrap_or(0) as i32;
+    let width = widest * SCALE + 2 * MARGIN;
+    let height = (rows.len() as i32 + 2) * LINE_HEIGHT + 2 * MARGIN;
+    let x = (WINDOW_WIDTH - width) / 2;
+    let y = (WINDOW_HEIGHT - height) / 2;
+
+    draw_background(canvas, x, y, width, height);
+    draw_text(canvas, &menu.title, x + MARGIN, y + MARGIN, TEXT_COLOR);
+    for (row, (i, (label, _))) in rows.iter().enumerate() {
+        let y = y + MARGIN + (row as i32 + 2) * LINE_HEIGHT;
+        if *i == menu.selected {
+            draw_text(canvas, &format!("> {}", label), x + MARGIN, y, SELECTED_COLOR);
+        } else {
+            draw_text(canvas, &format!("  {}", label), x + MARGIN, y, TEXT_COLOR);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod tests {
+    use crate::osd::{glyph, text_pixels, text_width, wrap_text, Menu, MenuAction, MenuKey, Osd};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn text_is_laid_out_with_one_column_between_glyphs() {
+        assert_eq!(text_width(""), 0);
+        assert_eq!(text_width("A"), 3);
+        assert_eq!(text_width("AB"), 7);
+
+        // "1" has its stem in the middle column, "-" is the middle row
+        let pixels = text_pixels("1-");
+        assert!(pixels.contains(&(1, 0)) && pixels.contains(&(1, 4)));
+        assert!(pixels.contains(&(4, 2)) && pixels.contains(&(6, 2)));
+        assert!(!pixels.contains(&(3, 2)));
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('~'), glyph('?'));
+    }
+
+    #[test]
+    fn messages_expire() {
+        let mut osd = Osd::new();
+        let start = Instant::now();
+        osd.message_at("Saved", start);
+        osd.message_at("Loaded", start + Duration::from_secs(2));
+        assert_eq!(osd.messages(start + Duration::from_secs(1)), vec!["Saved", "Loaded"]);
+        assert_eq!(osd.messages(start + Duration::from_secs(4)), vec!["Loaded"]);
+        assert!(!osd.is_visible(start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn counter_reports_frames_and_instructions_per_second() {
+        let mut osd = Osd::new();
+        let start = Instant::now();
+        for _ in 0..600 {
+            osd.record_tick();
+        }
+        for _ in 0..120 {
+            osd.record_frame();
+        }
+        let text = osd.counter_text(start + Duration::from_secs(2)).to_string();
+        assert_eq!(text, "FPS 60 IPS 300");
+    }
+
+    #[test]
+    fn menus_navigate_select_and_back_out() {
+        let mut osd = Osd::new();
+        osd.open_menu(
+            Menu::new("Paused")
+                .item("Resume", MenuAction::Resume)
+                .item("Reset", MenuAction::Reset)
+                .item("Settings", MenuAction::OpenSettings),
+        );
+        assert_eq!(osd.menu_key(MenuKey::Down), None);
+        assert_eq!(osd.menu_key(MenuKey::Select), Some(MenuAction::Reset));
+        // wraps around at both ends
+        osd.menu_key(MenuKey::Down);
+        osd.menu_key(MenuKey::Down);
+        assert_eq!(osd.menu_key(MenuKey::Select), Some(MenuAction::Resume));
+        osd.menu_key(MenuKey::Up);
+        assert_eq!(osd.menu_key(MenuKey::Select), Some(MenuAction::OpenSettings));
+
+        osd.open_menu(Menu::new("Settings").item("Timing", MenuAction::ToggleTiming));
+        assert_eq!(osd.menu().unwrap().title(), "Settings");
+        assert_eq!(osd.menu_key(MenuKey::Back), None);
+        assert_eq!(osd.menu().unwrap().title(), "Paused");
+        assert_eq!(osd.menu_key(MenuKey::Back), Some(MenuAction::Resume));
+        assert!(!osd.is_menu_open());
+    }
+
+    #[test]
+    fn replaced_menu_keeps_its_selection() {
+        let mut osd = Osd::new();
+        let settings = || {
+            Menu::new("Settings")
+                .item("Timing", MenuAction::ToggleTiming)
+                .item("Counter", MenuAction::ToggleCounter)
+        };
+        osd.open_menu(settings());
+        osd.menu_key(MenuKey::Down);
+        osd.replace_menu(settings());
+        assert_eq!(osd.menu().unwrap().selected(), Some(&MenuAction::ToggleCounter));
+    }
+
+    #[test]
+    fn text_wraps_at_spaces_and_splits_long_words() {
+        assert_eq!(wrap_text("", 10), [""]);
+        assert_eq!(wrap_text("Reload failed: bad ROM", 10), ["Reload", "failed:", "bad ROM"]);
+        assert_eq!(wrap_text("/a/long/path.ch8 missing", 8), ["/a/long/", "path.ch8", "missing"]);
+    }
+
+    #[test]
+    fn error_stays_until_cleared() {
+        let mut osd = Osd::new();
+        let start = Instant::now();
+        osd.set_error(Some("Reload failed"));
+        assert_eq!(osd.error(), Some("Reload failed"));
+        assert!(osd.is_visible(start + Duration::from_secs(60)));
+        osd.set_error(None);
+        assert!(!osd.is_visible(start + Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::font::{self, FontSet};
+
+// Behaviour differences between CHIP-8 interpreters, named as in the
+// community chip-8-database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of shifting VY into VX.
+    pub shift: bool,
+    // FX55/FX65 add X to I instead of X + 1.
+    pub memory_increment_by_x: bool,
+    // FX55/FX65 leave I unchanged. Wins over `memory_increment_by_x`.
+    pub memory_leave_i_unchanged: bool,
+    // Sprites wrap around the screen edges instead of being clipped.
+    pub wrap: bool,
+    // BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump: bool,
+    // DXYN waits for the display interrupt.
+    pub vblank: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic: bool,
+}
+
+impl Quirks {
+    // What this interpreter has always done.
+    pub const DEFAULT: Quirks = Quirks {
+        shift: true,
+        memory_increment_by_x: false,
+        memory_leave_i_unchanged: false,
+        wrap: true,
+        jump: false,
+        vblank: false,
+        logic: false,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Platform {
+    // Identifier used by the database, e.g. "originalChip8".
+    pub id: &'static str,
+    pub name: &'static str,
+    pub quirks: Quirks,
+    // Instructions per 60 Hz frame.
+    pub tickrate: u32,
+    // Nested calls that fit on the stack.
+    pub stack_depth: usize,
+    pub font: FontSet,
+}
+
+// The platforms from the database that run plain CHIP-8 programs. Extended
+// instruction sets (SCHIP, XO-CHIP) are not emulated, but their programs
+// written for the CHIP-8 subset run with these quirks.
+pub const PLATFORMS: [Platform; 6] = [
+    Platform {
+        id: "originalChip8",
+        name: "Cosmac VIP CHIP-8",
+        quirks: Quirks {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: false,
+            vblank: true,
+            logic: true,
+        },
+        tickrate: 15,
+        stack_depth: 12,
+        font: font::VIP,
+    },
+    Platform {
+        id: "hybridVIP",
+        name: "CHIP-8 with Cosmac VIP instructions",
+        quirks: Quirks {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: false,
+            vblank: true,
+            logic: true,
+        },
+        tickrate: 15,
+        stack_depth: 12,
+        font: font::VIP,
+    },
+    Platform {
+        id: "modernChip8",
+        name: "Modern CHIP-8",
+        quirks: Quirks {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+        },
+        tickrate: 12,
+        stack_depth: 16,
+        font: font::OCTO,
+    },
+    Platform {
+        id: "chip48",
+        name: "CHIP-48",
+        quirks: Quirks {
+            shift: true,
+            memory_increment_by_x: true,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: true,
+            vblank: false,
+            logic: false,
+        },
+        tickrate: 30,
+        stack_depth: 16,
+        font: font::CHIP48,
+    },
+    Platform {
+        id: "superchip1",
+        name: "SUPER-CHIP 1.1",
+        quirks: Quirks {
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: false,
+            jump: true,
+            vblank: false,
+            logic: false,
+        },
+        tickrate: 30,
+        stack_depth: 16,
+        font: font::SCHIP,
+    },
+    Platform {
+        id: "superchip",
+        name: "Modern SUPER-CHIP",
+        quirks: Quirks {
+            shift: true,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            wrap: false,
+            jump: true,
+            vblank: false,
+            logic: false,
+        },
+        tickrate: 30,
+        stack_depth: 16,
+        font: font::SCHIP,
+    },
+];
+
+impl Platform {
+    pub fn by_id(id: &str) -> Option<Platform> {
+        PLATFORMS.iter().find(|platform| platform.id == id).copied()
+    }
+}
+
+// The machine a program was written for: where it is loaded and starts, and
+// how much memory there is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub load_address: u16,
+    pub memory_size: usize,
+}
+
+pub const PROFILES: [Profile; 3] = [
+    Profile {
+        id: "vip",
+        name: "COSMAC VIP 4K",
+        load_address: 0x200,
+        memory_size: 4096,
+    },
+    Profile {
+        id: "vip2k",
+        name: "COSMAC VIP 2K",
+        load_address: 0x200,
+        memory_size: 2048,
+    },
+    Profile {
+        id: "eti660",
+        name: "ETI-660",
+        load_address: 0x600,
+        memory_size: 4096,
+    },
+];
+
+impl Profile {
+    pub const DEFAULT: Profile = PROFILES[0];
+
+    pub fn by_id(id: &str) -> Option<Profile> {
+        PROFILES.iter().find(|profile| profile.id == id).copied()
+    }
+
+    pub fn max_rom_size(&self) -> usize {
+        self.memory_size - self.load_address as usize
+    }
+}
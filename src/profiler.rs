@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use crate::cpu::Instruction;
+use crate::mem::RAM_SIZE;
+
+const HOT_ADDRESSES: usize = 20;
+
+// Collects execution statistics from the main loop: call `record` after every
+// tick and `end_frame` at the end of every 60 Hz frame.
+pub struct Profiler {
+    instructions: u64,
+    by_name: HashMap<&'static str, u64>,
+    by_pc: Vec<u64>,
+    calls: HashMap<u16, u64>,
+    // Entry addresses of the subroutines currently running, outermost first.
+    call_stack: Vec<u16>,
+    folded: HashMap<Vec<u16>, u64>,
+    frame_instructions: u64,
+    frames: Vec<u64>,
+    key_wait_ticks: u64,
+    key_wait_time: Duration,
+    last_record: Option<Instant>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            instructions: 0,
+            by_name: HashMap::new(),
+            by_pc: vec![0; RAM_SIZE],
+            calls: HashMap::new(),
+            call_stack: Vec::new(),
+            folded: HashMap::new(),
+            frame_instructions: 0,
+            frames: Vec::new(),
+            key_wait_ticks: 0,
+            key_wait_time: Duration::new(0, 0),
+            last_record: None,
+        }
+    }
+
+    // `pc` is where `instr` was fetched from, `next_pc` where the CPU went
+    // after executing it.
+    pub fn record(&mut self, pc: u16, instr: &Instruction, next_pc: u16) {
+        let now = Instant::now();
+        self.instructions += 1;
+        self.frame_instructions += 1;
+        *self.by_name.entry(instr.name()).or_insert(0) += 1;
+        self.by_pc[pc as usize % RAM_SIZE] += 1;
+        *self.folded.entry(self.call_stack.clone()).or_insert(0) += 1;
+
+        match instr {
+            Instruction::Call(addr) if next_pc == *addr => {
+                *self.calls.entry(*addr).or_insert(0) += 1;
+                self.call_stack.push(*addr);
+            }
+            Instruction::Return => {
+                self.call_stack.pop();
+            }
+            Instruction::GetKeyVx(_) if next_pc == pc => {
+                self.key_wait_ticks += 1;
+                if let Some(last) = self.last_record {
+                    self.key_wait_time += now - last;
+                }
+            }
+            _ => {}
+        }
+        self.last_record = Some(now);
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frames.push(self.frame_instructions);
+        self.frame_instructions = 0;
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+    pub fn count(&self, name: &str) -> u64 {
+        *self.by_name.get(name).unwrap_or(&0)
+    }
+    pub fn count_at(&self, pc: u16) -> u64 {
+        self.by_pc[pc as usize % RAM_SIZE]
+    }
+    pub fn calls_to(&self, addr: u16) -> u64 {
+        *self.calls.get(&addr).unwrap_or(&0)
+    }
+    pub fn key_wait_ticks(&self) -> u64 {
+        self.key_wait_ticks
+    }
+
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        let percent = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+
+        writeln!(out, "Instructions executed: {}", self.instructions)?;
+        if !self.frames.is_empty() {
+            let total: u64 = self.frames.iter().sum();
+            writeln!(
+                out,
+                "Frames: {}, instructions per frame: min {} avg {:.1} max {}",
+                self.frames.len(),
+                self.frames.iter().min().unwrap(),
+                total as f64 / self.frames.len() as f64,
+                self.frames.iter().max().unwrap()
+            )?;
+        }
+        writeln!(
+            out,
+            "Waiting for a key (FX0A): {} ticks, {:.3}s",
+            self.key_wait_ticks,
+            self.key_wait_time.as_secs_f64()
+        )?;
+
+        writeln!(out, "\nInstructions:")?;
+        let mut by_name: Vec<(&&str, &u64)> = self.by_name.iter().collect();
+        by_name.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (name, count) in by_name {
+            writeln!(out, "  {:<18} {:>10} {:>6.2}%", name, count, percent(*count))?;
+        }
+
+        writeln!(out, "\nHot addresses:")?;
+        let mut by_pc: Vec<(usize, u64)> = self.by_pc.iter().copied().enumerate().filter(|(_, c)| *c > 0).collect();
+        by_pc.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for (pc, count) in by_pc.into_iter().take(HOT_ADDRESSES) {
+            writeln!(out, "  0x{:03x} {:>10} {:>6.2}%", pc, count, percent(count))?;
+        }
+
+        writeln!(out, "\nSubroutine calls:")?;
+        let mut calls: Vec<(&u16, &u64)> = self.calls.iter().collect();
+        calls.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (addr, count) in calls {
+            writeln!(out, "  0x{:03x} {:>10}", addr, count)?;
+        }
+        Ok(())
+    }
+
+    // One line per call stack with the number of instructions executed in
+    // it, as expected by flamegraph.pl and inferno.
+    pub fn write_folded(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut stacks: Vec<(String, u64)> = self
+            .folded
+            .iter()
+            .map(|(stack, count)| {
+                let mut frames = vec!["main".to_string()];
+                frames.extend(stack.iter().map(|addr| format!("0x{:03x}", addr)));
+                (frames.join(";"), *count)
+            })
+            .collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(out, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+
+    // Writes `<prefix>.txt` and `<prefix>.folded`.
+    pub fn export(&self, prefix: &str) -> io::Result<()> {
+        self.write_report(&mut BufWriter::new(File::create(format!("{}.txt", prefix))?))?;
+        self.write_folded(&mut BufWriter::new(File::create(format!("{}.folded", prefix))?))
+    }
+}
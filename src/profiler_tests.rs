@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use crate::profiler::Profiler;
+    use crate::system;
+
+    fn profile(rom: Vec<u8>, ticks: usize, ticks_per_frame: usize) -> Profiler {
+        let mut emu = system::System::new();
+        emu.load_rom(rom).unwrap();
+        let mut profiler = Profiler::new();
+        for tick in 1..=ticks {
+            let pc = emu.pc();
+            let instr = emu.tick();
+            profiler.record(pc, &instr, emu.pc());
+            if tick % ticks_per_frame == 0 {
+                profiler.end_frame();
+            }
+        }
+        profiler
+    }
+
+    // 0x200: call 0x206, jump 0x200
+    // 0x206: V0 += 1, return
+    fn call_loop() -> Vec<u8> {
+        vec![0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE]
+    }
+
+    #[test]
+    fn counts_instructions_addresses_and_calls() {
+        let profiler = profile(call_loop(), 8, 4);
+
+        assert_eq!(profiler.instructions(), 8);
+        assert_eq!(profiler.count("Call"), 2);
+        assert_eq!(profiler.count("AddRegister"), 2);
+        assert_eq!(profiler.count("Draw"), 0);
+        assert_eq!(profiler.count_at(0x206), 2);
+        assert_eq!(profiler.calls_to(0x206), 2);
+    }
+
+    #[test]
+    fn counts_key_wait() {
+        let profiler = profile(vec![0xF0, 0x0A], 5, 5);
+        assert_eq!(profiler.key_wait_ticks(), 5);
+    }
+
+    #[test]
+    fn writes_report_and_folded_stacks() {
+        let profiler = profile(call_loop(), 8, 4);
+
+        let mut report = Vec::new();
+        profiler.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("Instructions executed: 8"));
+        assert!(report.contains("Frames: 2, instructions per frame: min 4 avg 4.0 max 4"));
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        // The call and the jump run in main, the add and the return in 0x206
+        assert_eq!(String::from_utf8(folded).unwrap(), "main 4\nmain;0x206 4\n");
+    }
+}
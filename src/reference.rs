@@ -0,0 +1,165 @@
+// A deliberately simple CHIP-8 interpreter used as an oracle in tests. It
+// shares no code with `cpu`/`system`: it works directly on a
+// `test_machine::State` and decodes opcodes by nibble, one tick at a time.
+
+use crate::test_machine::State;
+use crate::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Reasons the reference stops. Behaviour past these points is either
+// undefined for the machine (stack/memory out of range) or not reproducible
+// (random numbers), so comparisons end there.
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    PcOutOfRange,
+    StackUnderflow,
+    StackOverflow,
+    MemoryOutOfRange,
+    IndexOverflow,
+    Random,
+}
+
+pub fn step(s: &mut State, keys: &[bool; 16]) -> Result<(), Stop> {
+    let pc = s.pc as usize;
+    if pc + 1 >= s.ram.len() {
+        return Err(Stop::PcOutOfRange);
+    }
+    let opcode = (s.ram[pc] as u16) << 8 | s.ram[pc + 1] as u16;
+    let op = (opcode >> 12) as u8;
+    let x = ((opcode >> 8) & 0xF) as usize;
+    let y = ((opcode >> 4) & 0xF) as usize;
+    let n = (opcode & 0xF) as u8;
+    let nn = (opcode & 0xFF) as u8;
+    let nnn = opcode & 0xFFF;
+
+    let mut next = s.pc + 2;
+    match (op, n) {
+        (0x0, _) if opcode == 0x00E0 => s.pixels.iter_mut().for_each(|p| *p = 0),
+        (0x0, _) if opcode == 0x00EE => {
+            if s.sp == 0 {
+                return Err(Stop::StackUnderflow);
+            }
+            s.sp -= 1;
+            next = s.stack[s.sp] + 2;
+        }
+        (0x1, _) => next = nnn,
+        (0x2, _) => {
+            if s.sp == s.stack.len() {
+                return Err(Stop::StackOverflow);
+            }
+            s.stack[s.sp] = s.pc;
+            s.sp += 1;
+            next = nnn;
+        }
+        (0x3, _) if s.v[x] == nn => next += 2,
+        (0x4, _) if s.v[x] != nn => next += 2,
+        (0x5, 0) if s.v[x] == s.v[y] => next += 2,
+        (0x6, _) => s.v[x] = nn,
+        (0x7, _) => s.v[x] = s.v[x].wrapping_add(nn),
+        (0x8, 0x0) => s.v[x] = s.v[y],
+        (0x8, 0x1) => s.v[x] |= s.v[y],
+        (0x8, 0x2) => s.v[x] &= s.v[y],
+        (0x8, 0x3) => s.v[x] ^= s.v[y],
+        (0x8, 0x4) => {
+            let sum = s.v[x] as u16 + s.v[y] as u16;
+            s.v[x] = sum as u8;
+            s.v[0xF] = if sum > 0xFF { 1 } else { 0 };
+        }
+        (0x8, 0x5) => {
+            let no_borrow = s.v[x] >= s.v[y];
+            s.v[x] = s.v[x].wrapping_sub(s.v[y]);
+            s.v[0xF] = if no_borrow { 1 } else { 0 };
+        }
+        (0x8, 0x6) => {
+            let lsb = s.v[x] & 1;
+            s.v[x] >>= 1;
+            s.v[0xF] = lsb;
+        }
+        (0x8, 0x7) => {
+            let no_borrow = s.v[y] >= s.v[x];
+            s.v[x] = s.v[y].wrapping_sub(s.v[x]);
+            s.v[0xF] = if no_borrow { 1 } else { 0 };
+        }
+        (0x8, 0xE) => {
+            let msb = s.v[x] >> 7;
+            s.v[x] <<= 1;
+            s.v[0xF] = msb;
+        }
+        (0x9, 0) if s.v[x] != s.v[y] => next += 2,
+        (0xA, _) => s.index = nnn,
+        (0xB, _) => next = (nnn + s.v[0] as u16) % 0x1000,
+        (0xC, _) => return Err(Stop::Random),
+        (0xD, _) => {
+            if s.index as usize + n as usize > s.ram.len() {
+                return Err(Stop::MemoryOutOfRange);
+            }
+            let mut collision = 0;
+            for row in 0..n as usize {
+                let sprite = s.ram[s.index as usize + row];
+                for col in 0..8 {
+                    if sprite & (0x80 >> col) != 0 {
+                        let px = (s.v[x] as usize + col) % SCREEN_WIDTH;
+                        let py = (s.v[y] as usize + row) % SCREEN_HEIGHT;
+                        let pixel = &mut s.pixels[py * SCREEN_WIDTH + px];
+                        if *pixel == 1 {
+                            collision = 1;
+                        }
+                        *pixel ^= 1;
+                    }
+                }
+            }
+            s.v[0xF] = collision;
+        }
+        (0xE, _) if nn == 0x9E && s.v[x] < 16 && keys[s.v[x] as usize] => next += 2,
+        (0xE, _) if nn == 0xA1 && s.v[x] < 16 && !keys[s.v[x] as usize] => next += 2,
+        (0xF, _) => match nn {
+            0x07 => s.v[x] = s.delay_timer,
+            0x0A => match (0..16).rev().find(|&k| keys[k]) {
+                Some(k) => s.v[x] = k as u8,
+                None => next = s.pc,
+            },
+            0x15 => s.delay_timer = s.v[x],
+            0x18 => s.sound_timer = s.v[x],
+            0x1E => {
+                let sum = s.index as u32 + s.v[x] as u32;
+                if sum > 0xFFFF {
+                    return Err(Stop::IndexOverflow);
+                }
+                s.index = sum as u16;
+                s.v[0xF] = if sum > 0xFFF { 1 } else { 0 };
+            }
+            0x29 => s.index = (s.v[x] & 0xF) as u16 * 5,
+            0x30 => s.index = 80 + (s.v[x] & 0xF) as u16 * 10,
+            0x33 => {
+                let i = s.index as usize;
+                if i + 3 > s.ram.len() {
+                    return Err(Stop::MemoryOutOfRange);
+                }
+                s.ram[i] = s.v[x] / 100;
+                s.ram[i + 1] = s.v[x] / 10 % 10;
+                s.ram[i + 2] = s.v[x] % 10;
+            }
+            0x55 | 0x65 => {
+                let i = s.index as usize;
+                if i + x + 1 > s.ram.len() {
+                    return Err(Stop::MemoryOutOfRange);
+                }
+                for r in 0..=x {
+                    if nn == 0x55 {
+                        s.ram[i + r] = s.v[r];
+                    } else {
+                        s.v[r] = s.ram[i + r];
+                    }
+                }
+                s.index += x as u16 + 1;
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+    // PC wraps at the end of the 12-bit address space
+    s.pc = next & 0x0FFF;
+
+    s.delay_timer = s.delay_timer.saturating_sub(1);
+    s.sound_timer = s.sound_timer.saturating_sub(1);
+    Ok(())
+}
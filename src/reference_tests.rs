@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests {
+    use crate::keypad::KeyWait;
+    use crate::reference;
+    use crate::system;
+    use crate::test_machine::{self, State};
+    use proptest::prelude::*;
+
+    const PROGRAM_START: u16 = 0x200;
+    const MAX_PROGRAM_LEN: usize = 32;
+
+    // Kept separate from `State` so a shrunk failure prints as a short,
+    // readable reproducer instead of 4 KB of RAM.
+    #[derive(Clone)]
+    struct Case {
+        program: Vec<u16>,
+        v: [u8; 16],
+        index: u16,
+        stack: Vec<u16>,
+        delay_timer: u8,
+        sound_timer: u8,
+        pixels: Vec<(usize, usize)>,
+        keys: [bool; 16],
+    }
+
+    impl std::fmt::Debug for Case {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let program: Vec<String> = self.program.iter().map(|op| format!("{:04X}", op)).collect();
+            let keys: Vec<usize> = (0..16).filter(|&k| self.keys[k]).collect();
+            f.debug_struct("Case")
+                .field("program", &program)
+                .field("v", &format_args!("{:02x?}", self.v))
+                .field("index", &format_args!("0x{:03x}", self.index))
+                .field("stack", &format_args!("{:03x?}", self.stack))
+                .field("delay_timer", &self.delay_timer)
+                .field("sound_timer", &self.sound_timer)
+                .field("pixels", &self.pixels)
+                .field("keys", &keys)
+                .finish()
+        }
+    }
+
+    impl Case {
+        fn state(&self, system: &system::System) -> State {
+            let mut state = system.test_state();
+            let start = PROGRAM_START as usize;
+            for (i, opcode) in self.program.iter().enumerate() {
+                state.ram[start + i * 2] = (opcode >> 8) as u8;
+                state.ram[start + i * 2 + 1] = (opcode & 0xFF) as u8;
+            }
+            state.pc = PROGRAM_START;
+            state.v = self.v;
+            state.index = self.index;
+            state.stack[..self.stack.len()].copy_from_slice(&self.stack);
+            state.sp = self.stack.len();
+            state.delay_timer = self.delay_timer;
+            state.sound_timer = self.sound_timer;
+            for (x, y) in &self.pixels {
+                state.pixels[x + y * crate::video::SCREEN_WIDTH] = 1;
+            }
+            state
+        }
+    }
+
+    // Mostly well-formed opcodes, with jump targets kept inside the program
+    // so control flow stays interesting, plus some completely random words.
+    fn opcode() -> impl Strategy<Value = u16> {
+        let well_formed = any::<u16>().prop_map(|op| {
+            let x_y = op & 0x0FF0;
+            match op >> 12 {
+                0x0 => [0x00E0, 0x00EE][(op & 1) as usize],
+                0x1 | 0x2 | 0xB => (op & 0xF000) | PROGRAM_START | (op & 0x3E),
+                0x5 | 0x9 => op & 0xFFF0,
+                0x8 => 0x8000 | x_y | [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE][(op & 0xF) as usize % 9],
+                0xC => 0x6000 | (op & 0x0FFF),
+                0xE => 0xE000 | (op & 0x0F00) | [0x9E, 0xA1][(op & 1) as usize],
+                0xF => {
+                    let low = [0x07, 0x0A, 0x15, 0x18, 0x1E, 0x29, 0x33, 0x55, 0x65];
+                    0xF000 | (op & 0x0F00) | low[(op & 0xFF) as usize % low.len()]
+                }
+                _ => op,
+            }
+        });
+        prop_oneof![9 => well_formed, 1 => any::<u16>()]
+    }
+
+    prop_compose! {
+        fn case()(
+            program in prop::collection::vec(opcode(), 1..MAX_PROGRAM_LEN),
+            v in any::<[u8; 16]>(),
+            index in 0u16..0x1000,
+            stack in prop::collection::vec((0u16..MAX_PROGRAM_LEN as u16).prop_map(|i| PROGRAM_START + i * 2), 0..4),
+            delay_timer in any::<u8>(),
+            sound_timer in any::<u8>(),
+            pixels in prop::collection::vec((0usize..crate::video::SCREEN_WIDTH, 0usize..crate::video::SCREEN_HEIGHT), 0..16),
+            keys in any::<[bool; 16]>(),
+        ) -> Case {
+            Case { program, v, index, stack, delay_timer, sound_timer, pixels, keys }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn system_matches_reference(case in case()) {
+            let mut emu = system::System::new();
+            let mut expected = case.state(&emu);
+            emu.load_test_state(&expected);
+            // the reference finishes FX0A as soon as a key is down
+            emu.set_key_wait(KeyWait::Press);
+            test_machine::press_keys(&mut emu, &case.keys);
+
+            for tick in 0..case.program.len() * 2 {
+                let pc = expected.pc;
+                if reference::step(&mut expected, &case.keys).is_err() {
+                    break;
+                }
+                emu.tick();
+                let differences = test_machine::diff(&emu.test_state(), &expected);
+                prop_assert!(
+                    differences.is_empty(),
+                    "diverged on tick {} at PC 0x{:03x} (system != reference): {:#?}",
+                    tick,
+                    pc,
+                    differences
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reference_stops_on_stack_underflow() {
+        let emu = system::System::new();
+        let case = Case {
+            program: vec![0x00EE],
+            v: [0; 16],
+            index: 0,
+            stack: vec![],
+            delay_timer: 0,
+            sound_timer: 0,
+            pixels: vec![],
+            keys: [false; 16],
+        };
+        let mut state = case.state(&emu);
+        assert_eq!(reference::step(&mut state, &case.keys), Err(reference::Stop::StackUnderflow));
+    }
+}
@@ -0,0 +1,329 @@
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::cpu;
+use crate::font::FontSet;
+use crate::platform::{Platform, Profile, Quirks};
+use crate::system::System;
+use crate::timing::Timing;
+
+// Metadata about known ROMs, keyed by the SHA-1 of the ROM file. The files
+// are those of the community chip-8-database (sha1-hashes.json maps hashes
+// to indices into programs.json), plus an optional file of local overrides
+// keyed by hash, e.g.
+//
+//     { "0123...": { "platform": "chip48", "machine": "eti660",
+//                    "tickrate": 20, "stackDepth": 12, "font": "vip",
+//                    "quirks": { "shift": false },
+//                    "colors": { "pixels": ["#000000", "#33ff66"] },
+//                    "keys": { "left": 4, "right": 6 } } }
+pub struct RomDb {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+    overrides: HashMap<String, Override>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Program {
+    title: String,
+    description: Option<String>,
+    release: Option<String>,
+    authors: Vec<String>,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Rom {
+    file: Option<String>,
+    embedded_title: Option<String>,
+    platforms: Vec<String>,
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    keys: Option<HashMap<String, u8>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct Colors {
+    pixels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct QuirkOverrides {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl QuirkOverrides {
+    fn apply(&self, quirks: &mut Quirks) {
+        let fields = [
+            (self.shift, &mut quirks.shift),
+            (self.memory_increment_by_x, &mut quirks.memory_increment_by_x),
+            (self.memory_leave_i_unchanged, &mut quirks.memory_leave_i_unchanged),
+            (self.wrap, &mut quirks.wrap),
+            (self.jump, &mut quirks.jump),
+            (self.vblank, &mut quirks.vblank),
+            (self.logic, &mut quirks.logic),
+        ];
+        for (value, quirk) in fields {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Override {
+    title: Option<String>,
+    platform: Option<String>,
+    machine: Option<String>,
+    quirks: QuirkOverrides,
+    tickrate: Option<u32>,
+    stack_depth: Option<usize>,
+    font: Option<String>,
+    colors: Option<Colors>,
+    keys: Option<HashMap<String, u8>>,
+}
+
+impl Override {
+    // Unknown ids are errors rather than silently falling back to defaults.
+    fn check(&self) -> Result<(), String> {
+        if let Some(id) = self.platform.as_deref().filter(|id| Platform::by_id(id).is_none()) {
+            return Err(format!("unknown platform \"{}\"", id));
+        }
+        if let Some(id) = self.machine.as_deref().filter(|id| Profile::by_id(id).is_none()) {
+            return Err(format!("unknown machine \"{}\"", id));
+        }
+        if let Some(id) = self.font.as_deref().filter(|id| FontSet::by_id(id).is_none()) {
+            return Err(format!("unknown font \"{}\"", id));
+        }
+        Ok(())
+    }
+}
+
+// What is known about one ROM, with overrides applied.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub sha1: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub description: Option<String>,
+    // None when none of the ROM's platforms is emulated.
+    pub platform: Option<Platform>,
+    // The machine profile to load the ROM on, if not the default.
+    pub profile: Option<Profile>,
+    pub quirks: Quirks,
+    pub tickrate: Option<u32>,
+    pub stack_depth: usize,
+    pub font: FontSet,
+    // Colours of unlit and lit pixels.
+    pub palette: Option<(Color, Color)>,
+    // Database key names ("up", "a", ...) and the keypad key they press.
+    pub keys: Vec<(String, u8)>,
+}
+
+// Host keys for the database's key names. Only player one is mapped.
+const HOST_KEYS: [(&str, Keycode); 6] = [
+    ("up", Keycode::Up),
+    ("down", Keycode::Down),
+    ("left", Keycode::Left),
+    ("right", Keycode::Right),
+    ("a", Keycode::Space),
+    ("b", Keycode::LCtrl),
+];
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+impl RomDb {
+    pub fn new() -> RomDb {
+        RomDb {
+            hashes: HashMap::new(),
+            programs: Vec::new(),
+            overrides: HashMap::new(),
+        }
+    }
+
+    // Loads sha1-hashes.json and programs.json from a checkout of the
+    // database's `database` directory.
+    pub fn load_dir(dir: &str) -> Result<RomDb, String> {
+        let read = |name: &str| {
+            let path = Path::new(dir).join(name);
+            fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))
+        };
+        RomDb::from_json(&read("sha1-hashes.json")?, &read("programs.json")?)
+    }
+
+    pub fn from_json(hashes: &str, programs: &str) -> Result<RomDb, String> {
+        let mut db = RomDb::new();
+        db.hashes = serde_json::from_str(hashes).map_err(|e| format!("sha1-hashes.json: {}", e))?;
+        db.programs = serde_json::from_str(programs).map_err(|e| format!("programs.json: {}", e))?;
+        Ok(db)
+    }
+
+    pub fn load_overrides(&mut self, path: &str) -> Result<(), String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.set_overrides(&json).map_err(|e| format!("{}: {}", path, e))
+    }
+    pub fn set_overrides(&mut self, json: &str) -> Result<(), String> {
+        let overrides: HashMap<String, Override> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for (sha1, user) in overrides.iter() {
+            user.check().map_err(|e| format!("{}: {}", sha1, e))?;
+        }
+        self.overrides = overrides;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    // None when the ROM is neither in the database nor overridden.
+    pub fn lookup(&self, rom: &[u8]) -> Option<RomInfo> {
+        let sha1 = sha1_hex(rom);
+        let program = self.hashes.get(&sha1).and_then(|i| self.programs.get(*i));
+        let entry = program.and_then(|program| program.roms.get(&sha1));
+        let user = self.overrides.get(&sha1);
+        if entry.is_none() && user.is_none() {
+            return None;
+        }
+        let entry = entry.cloned().unwrap_or_default();
+        let user = user.cloned().unwrap_or_default();
+
+        let platform = user
+            .platform
+            .as_deref()
+            .and_then(Platform::by_id)
+            .or_else(|| entry.platforms.iter().find_map(|id| Platform::by_id(id)));
+        let mut quirks = platform.map_or(Quirks::DEFAULT, |platform| platform.quirks);
+        if let Some(platform) = platform {
+            if let Some(quirky) = entry.quirky_platforms.get(platform.id) {
+                quirky.apply(&mut quirks);
+            }
+        }
+        user.quirks.apply(&mut quirks);
+
+        let colors = user.colors.or(entry.colors);
+        let mut keys: Vec<(String, u8)> = user.keys.or(entry.keys).unwrap_or_default().into_iter().collect();
+        keys.sort();
+
+        Some(RomInfo {
+            sha1,
+            title: user
+                .title
+                .or_else(|| program.map(|program| program.title.clone()))
+                .or(entry.embedded_title)
+                .or(entry.file),
+            authors: program.map(|program| program.authors.clone()).unwrap_or_default(),
+            release: program.and_then(|program| program.release.clone()),
+            description: program.and_then(|program| program.description.clone()),
+            platform,
+            profile: user.machine.as_deref().and_then(Profile::by_id),
+            quirks,
+            tickrate: user
+                .tickrate
+                .or(entry.tickrate)
+                .or_else(|| platform.map(|platform| platform.tickrate)),
+            stack_depth: user
+                .stack_depth
+                .or_else(|| platform.map(|platform| platform.stack_depth))
+                .unwrap_or(cpu::DEFAULT_STACK_DEPTH),
+            font: user
+                .font
+                .as_deref()
+                .and_then(FontSet::by_id)
+                .or_else(|| platform.map(|platform| platform.font))
+                .unwrap_or(FontSet::DEFAULT),
+            palette: colors.and_then(|colors| palette(&colors.pixels)),
+            keys,
+        })
+    }
+}
+
+impl RomInfo {
+    // Defaults for a ROM the database knows nothing about.
+    pub fn unknown(rom: &[u8]) -> RomInfo {
+        RomInfo {
+            sha1: sha1_hex(rom),
+            title: None,
+            authors: Vec::new(),
+            release: None,
+            description: None,
+            platform: None,
+            profile: None,
+            quirks: Quirks::DEFAULT,
+            tickrate: None,
+            stack_depth: cpu::DEFAULT_STACK_DEPTH,
+            font: FontSet::DEFAULT,
+            palette: None,
+            keys: Vec::new(),
+        }
+    }
+
+    // Configures `emu` for this ROM. `timing` wins over the tickrate, e.g.
+    // because it was chosen on the command line.
+    pub fn apply(&self, emu: &mut System, timing: Option<Timing>) {
+        emu.set_quirks(self.quirks);
+        emu.set_stack_depth(self.stack_depth);
+        if let Err(e) = emu.set_font(self.font) {
+            println!("Unable to use the font: {}", e);
+        }
+        emu.set_timing(match (timing, self.tickrate) {
+            (Some(timing), _) => timing,
+            (None, Some(tickrate)) => Timing::Tickrate(tickrate),
+            (None, None) => Timing::Fixed,
+        });
+        let (off, on) = self.palette.unwrap_or((Color::BLACK, Color::WHITE));
+        emu.set_palette(off, on);
+        let aliases = self
+            .keys
+            .iter()
+            .filter_map(|(name, key)| {
+                let keycode = HOST_KEYS.iter().find(|(host, _)| host == name)?.1;
+                Some((keycode, *key))
+            })
+            .collect();
+        emu.set_key_aliases(aliases);
+    }
+}
+
+fn palette(pixels: &[String]) -> Option<(Color, Color)> {
+    match pixels {
+        [off, on, ..] => Some((parse_color(off)?, parse_color(on)?)),
+        _ => None,
+    }
+}
+
+// "#rrggbb" or "#rgb".
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    match hex.len() {
+        6 => Some(Color::RGB((value >> 16) as u8, (value >> 8) as u8, value as u8)),
+        3 => {
+            let digit = |shift: u32| ((value >> shift) & 0xF) as u8 * 0x11;
+            Some(Color::RGB(digit(8), digit(4), digit(0)))
+        }
+        _ => None,
+    }
+}
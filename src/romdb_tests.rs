@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod tests {
+    use crate::platform::{Platform, Quirks};
+    use crate::romdb::{sha1_hex, RomDb, RomInfo};
+    use crate::system::System;
+    use crate::timing::Timing;
+    use sdl2::keyboard::Keycode;
+    use sdl2::pixels::Color;
+    use std::time::Duration;
+
+    const ROM: [u8; 4] = [0x60, 0x04, 0x12, 0x02];
+    const OTHER_ROM: [u8; 2] = [0x12, 0x00];
+
+    fn db() -> RomDb {
+        let hashes = format!(r#"{{ "{}": 0 }}"#, sha1_hex(&ROM));
+        let programs = format!(
+            r##"[{{
+                "title": "Test Game",
+                "authors": ["Someone", "Someone Else"],
+                "release": "1990",
+                "roms": {{
+                    "{}": {{
+                        "file": "test.ch8",
+                        "platforms": ["xochip", "chip48", "originalChip8"],
+                        "quirkyPlatforms": {{ "chip48": {{ "jump": false }} }},
+                        "colors": {{ "pixels": ["#102030", "#fff"] }},
+                        "keys": {{ "left": 4, "right": 6, "player2Up": 1 }}
+                    }}
+                }}
+            }}]"##,
+            sha1_hex(&ROM)
+        );
+        RomDb::from_json(&hashes, &programs).unwrap()
+    }
+
+    #[test]
+    fn sha1_matches_known_digest() {
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn lookup_finds_metadata_by_hash() {
+        let db = db();
+        assert_eq!(db.len(), 1);
+        assert!(db.lookup(&OTHER_ROM).is_none());
+
+        let info = db.lookup(&ROM).unwrap();
+        assert_eq!(info.sha1, sha1_hex(&ROM));
+        assert_eq!(info.title.as_deref(), Some("Test Game"));
+        assert_eq!(info.authors, ["Someone", "Someone Else"]);
+        assert_eq!(info.release.as_deref(), Some("1990"));
+        // xochip is not emulated, so the first supported platform is used
+        assert_eq!(info.platform.map(|p| p.id), Some("chip48"));
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(
+            info.palette,
+            Some((Color::RGB(0x10, 0x20, 0x30), Color::RGB(0xFF, 0xFF, 0xFF)))
+        );
+    }
+
+    #[test]
+    fn quirky_platforms_adjust_the_platform_quirks() {
+        let info = db().lookup(&ROM).unwrap();
+        let chip48 = Platform::by_id("chip48").unwrap().quirks;
+        assert!(chip48.jump);
+        assert_eq!(info.quirks, Quirks { jump: false, ..chip48 });
+    }
+
+    #[test]
+    fn overrides_win_over_the_database() {
+        let mut db = db();
+        let overrides = format!(
+            r##"{{
+                "{}": {{ "platform": "originalChip8", "tickrate": 20,
+                         "quirks": {{ "wrap": true }} }},
+                "{}": {{ "title": "Homebrew", "stackDepth": 8, "machine": "eti660",
+                         "font": "dream6800", "colors": {{ "pixels": ["#000", "#0f0"] }} }}
+            }}"##,
+            sha1_hex(&ROM),
+            sha1_hex(&OTHER_ROM)
+        );
+        db.set_overrides(&overrides).unwrap();
+
+        let info = db.lookup(&ROM).unwrap();
+        let vip = Platform::by_id("originalChip8").unwrap().quirks;
+        assert_eq!(info.platform.map(|p| p.id), Some("originalChip8"));
+        assert_eq!(info.quirks, Quirks { wrap: true, ..vip });
+        assert_eq!(info.tickrate, Some(20));
+        assert_eq!(info.stack_depth, 12);
+        assert_eq!(info.font.id, "vip");
+        assert_eq!(info.title.as_deref(), Some("Test Game"));
+
+        // ROMs missing from the database can be described entirely locally
+        let info = db.lookup(&OTHER_ROM).unwrap();
+        assert_eq!(info.title.as_deref(), Some("Homebrew"));
+        assert_eq!(info.platform, None);
+        assert_eq!(info.quirks, Quirks::DEFAULT);
+        assert_eq!(info.tickrate, None);
+        assert_eq!(info.stack_depth, 8);
+        assert_eq!(info.font.id, "dream6800");
+        assert_eq!(info.profile.map(|profile| profile.load_address), Some(0x600));
+        assert_eq!(info.palette, Some((Color::BLACK, Color::RGB(0, 0xFF, 0))));
+
+        assert!(db.set_overrides("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn overrides_with_unknown_ids_are_rejected() {
+        let mut db = db();
+        let sha1 = sha1_hex(&ROM);
+        for (field, id) in [("platform", "chip9"), ("machine", "pdp11"), ("font", "comic")] {
+            let overrides = format!(r#"{{ "{}": {{ "{}": "{}" }} }}"#, sha1, field, id);
+            assert_eq!(
+                db.set_overrides(&overrides),
+                Err(format!("{}: unknown {} \"{}\"", sha1, field, id))
+            );
+        }
+        // the rejected overrides were not kept
+        assert_eq!(db.lookup(&ROM).unwrap().platform.map(|p| p.id), Some("chip48"));
+    }
+
+    #[test]
+    fn apply_configures_the_system() {
+        let info = db().lookup(&ROM).unwrap();
+        let mut emu = System::new();
+        emu.load_test(vec![0x60, 0x04, 0xE0, 0x9E, 0x12, 0x04, 0x12, 0x06]);
+        info.apply(&mut emu, None);
+        assert_eq!(emu.quirks(), info.quirks);
+        assert_eq!(emu.timing(), Timing::Tickrate(30));
+        // player 2 keys are not mapped
+        assert_eq!(emu.host_keys().len(), 16 + 2);
+
+        // Left is an alias for key 4, so E09E skips while it is held
+        assert!(emu.key_event(Keycode::Left, true, Duration::ZERO));
+        assert!(!emu.key_event(Keycode::Home, true, Duration::ZERO));
+        emu.set_timing(Timing::Fixed);
+        emu.tick();
+        emu.tick();
+        assert_eq!(emu.pc(), 0x206);
+
+        info.apply(&mut emu, Some(Timing::Vip));
+        assert_eq!(emu.timing(), Timing::Vip);
+
+        RomInfo::unknown(&ROM).apply(&mut emu, None);
+        assert_eq!(emu.quirks(), Quirks::DEFAULT);
+        assert_eq!(emu.timing(), Timing::Fixed);
+        assert_eq!(emu.host_keys().len(), 16);
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    // Multiple of real time, 1.0 being normal speed.
+    Scaled(f64),
+    // As fast as the host can run.
+    Uncapped,
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Speed::Scaled(scale) => write!(f, "{}x", scale),
+            Speed::Uncapped => write!(f, "uncapped"),
+        }
+    }
+}
+
+// Speeds the slower/faster hotkeys step through when not fast-forwarding.
+pub const SLOW_MOTION: [f64; 3] = [0.25, 0.5, 1.0];
+
+// Paces the main loop at the chosen speed and handles pause and frame
+// advance. A "unit" is whatever the loop runs between waits: one tick with
+// fixed timing, one 60 Hz frame with VIP timing.
+pub struct Clock {
+    // Normal or slow motion speed, one of SLOW_MOTION.
+    scale: f64,
+    fast_forward: Speed,
+    fast_forwarding: bool,
+    paused: bool,
+    // Frames still to run while paused.
+    advance: u32,
+    // When the next unit may start.
+    next: Option<Instant>,
+}
+
+impl Clock {
+    pub fn new(fast_forward: Speed) -> Clock {
+        Clock {
+            scale: 1.0,
+            fast_forward,
+            fast_forwarding: false,
+            paused: false,
+            advance: 0,
+            next: None,
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        if self.fast_forwarding {
+            self.fast_forward
+        } else {
+            Speed::Scaled(self.scale)
+        }
+    }
+    pub fn is_normal_speed(&self) -> bool {
+        self.speed() == Speed::Scaled(1.0)
+    }
+
+    // Fast-forward lasts while its key is held.
+    pub fn set_fast_forward(&mut self, on: bool) {
+        if self.fast_forwarding != on {
+            self.fast_forwarding = on;
+            self.next = None;
+        }
+    }
+    pub fn slower(&mut self) {
+        self.step_scale(-1);
+    }
+    pub fn faster(&mut self) {
+        self.step_scale(1);
+    }
+    fn step_scale(&mut self, step: isize) {
+        let i = SLOW_MOTION.iter().position(|s| *s == self.scale).unwrap_or(SLOW_MOTION.len() - 1) as isize;
+        let i = (i + step).max(0).min(SLOW_MOTION.len() as isize - 1);
+        self.scale = SLOW_MOTION[i as usize];
+        self.fast_forwarding = false;
+        self.next = None;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = 0;
+        self.next = None;
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    // Lets one more frame run while paused.
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.advance += 1;
+        }
+    }
+
+    pub fn should_run(&self) -> bool {
+        !self.paused || self.advance > 0
+    }
+    pub fn end_frame(&mut self) {
+        if self.paused && self.advance > 0 {
+            self.advance -= 1;
+        }
+    }
+
+    // How long to wait before the next unit, given that a unit takes
+    // `period` at normal speed. Falling behind does not build up a burst of
+    // catch-up units.
+    pub fn delay(&mut self, period: Duration, now: Instant) -> Duration {
+        let period = match self.speed() {
+            Speed::Uncapped => {
+                self.next = None;
+                return Duration::new(0, 0);
+            }
+            Speed::Scaled(scale) => period.div_f64(scale),
+        };
+        let start = self.next.map_or(now, |next| next.max(now));
+        self.next = Some(start + period);
+        start - now
+    }
+    pub fn wait(&mut self, period: Duration) {
+        let delay = self.delay(period, Instant::now());
+        if delay > Duration::new(0, 0) {
+            thread::sleep(delay);
+        }
+    }
+}
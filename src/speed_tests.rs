@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use crate::speed::{Clock, Speed};
+    use std::time::{Duration, Instant};
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn delay_keeps_a_steady_pace() {
+        let mut clock = Clock::new(Speed::Uncapped);
+        let start = Instant::now();
+        assert_eq!(clock.delay(PERIOD, start), Duration::new(0, 0));
+        assert_eq!(clock.delay(PERIOD, start + Duration::from_millis(4)), Duration::from_millis(6));
+        // late by 15ms, the next unit starts right away without catching up
+        assert_eq!(clock.delay(PERIOD, start + Duration::from_millis(35)), Duration::new(0, 0));
+        assert_eq!(clock.delay(PERIOD, start + Duration::from_millis(35)), PERIOD);
+    }
+
+    #[test]
+    fn slow_motion_and_fast_forward_scale_the_period() {
+        let mut clock = Clock::new(Speed::Scaled(4.0));
+        let start = Instant::now();
+
+        clock.slower();
+        assert_eq!(clock.speed(), Speed::Scaled(0.5));
+        clock.delay(PERIOD, start);
+        assert_eq!(clock.delay(PERIOD, start), 2 * PERIOD);
+
+        clock.slower();
+        clock.slower();
+        assert_eq!(clock.speed(), Speed::Scaled(0.25));
+
+        clock.set_fast_forward(true);
+        assert_eq!(clock.speed(), Speed::Scaled(4.0));
+        clock.delay(PERIOD, start);
+        assert_eq!(clock.delay(PERIOD, start), PERIOD / 4);
+
+        // letting go goes back to slow motion
+        clock.set_fast_forward(false);
+        assert_eq!(clock.speed(), Speed::Scaled(0.25));
+        clock.faster();
+        clock.faster();
+        clock.faster();
+        assert!(clock.is_normal_speed());
+    }
+
+    #[test]
+    fn uncapped_never_waits() {
+        let mut clock = Clock::new(Speed::Uncapped);
+        clock.set_fast_forward(true);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(clock.delay(PERIOD, start), Duration::new(0, 0));
+        }
+        assert!(!clock.is_normal_speed());
+    }
+
+    #[test]
+    fn paused_clock_runs_one_frame_per_advance() {
+        let mut clock = Clock::new(Speed::Uncapped);
+        assert!(clock.should_run());
+        clock.toggle_pause();
+        assert!(!clock.should_run());
+
+        clock.advance_frame();
+        clock.advance_frame();
+        assert!(clock.should_run());
+        clock.end_frame();
+        assert!(clock.should_run());
+        clock.end_frame();
+        assert!(!clock.should_run());
+
+        clock.toggle_pause();
+        clock.end_frame();
+        assert!(clock.should_run());
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+// Labels, constants and a map from addresses to source lines, as exported by
+// an assembler. The file has one entry per line:
+//
+//     # comment
+//     main 0x200
+//     main_loop = 0x2A4
+//     :const LIVES 3
+//     line 0x2A4 game.8o:12
+//
+// Values are hex with a 0x prefix or decimal. Source files named by `line`
+// entries are read relative to the symbol file, so the text of each line can
+// be shown too.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+    names: HashMap<String, u16>,
+    constants: HashMap<String, u16>,
+    lines: BTreeMap<u16, (String, usize)>,
+    sources: HashMap<String, Vec<String>>,
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    pub fn load(path: &str) -> Result<Symbols, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut symbols = Symbols::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+        let files: Vec<String> = symbols.lines.values().map(|(file, _)| file.clone()).collect();
+        for file in files {
+            if symbols.sources.contains_key(&file) {
+                continue;
+            }
+            if let Ok(source) = fs::read_to_string(dir.join(&file)) {
+                symbols.set_source(&file, &source);
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+        for (i, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().filter(|field| *field != "=").collect();
+            let error = || format!("line {}: cannot read \"{}\"", i + 1, line.trim());
+            match fields[..] {
+                [] => {}
+                [comment, ..] if comment.starts_with('#') => {}
+                [":const", name, value] | ["const", name, value] => {
+                    let value = parse_number(value).ok_or_else(error)?;
+                    symbols.constants.insert(name.to_string(), value);
+                }
+                ["line", addr, location] => {
+                    let addr = parse_number(addr).ok_or_else(error)?;
+                    let (file, number) = location.rsplit_once(':').ok_or_else(error)?;
+                    let number = number.parse().map_err(|_| error())?;
+                    symbols.lines.insert(addr & 0x0FFF, (file.to_string(), number));
+                }
+                [name, addr] => {
+                    let addr = parse_number(addr).ok_or_else(error)?;
+                    symbols.add_label(name, addr);
+                }
+                _ => return Err(error()),
+            }
+        }
+        Ok(symbols)
+    }
+
+    // The first label given for an address is the one shown for it.
+    pub fn add_label(&mut self, name: &str, addr: u16) {
+        let addr = addr & 0x0FFF;
+        self.labels.entry(addr).or_insert_with(|| name.to_string());
+        self.names.insert(name.to_string(), addr);
+    }
+    pub fn set_source(&mut self, file: &str, text: &str) {
+        self.sources.insert(file.to_string(), text.lines().map(|line| line.to_string()).collect());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.constants.is_empty() && self.lines.is_empty()
+    }
+
+    // The address of a label, or the value of a constant.
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.names.get(name).or_else(|| self.constants.get(name)).copied()
+    }
+    // A label, a constant or a plain address, e.g. for a breakpoint.
+    pub fn resolve(&self, text: &str) -> Result<u16, String> {
+        self.lookup(text)
+            .or_else(|| parse_number(text))
+            .map(|addr| addr & 0x0FFF)
+            .ok_or_else(|| format!("Unknown label {}", text))
+    }
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&(addr & 0x0FFF)).map(|name| name.as_str())
+    }
+
+    // `main_loop+0x4` after the nearest label at or before `addr`, or the
+    // plain address if there is none.
+    pub fn format_addr(&self, addr: u16) -> String {
+        let addr = addr & 0x0FFF;
+        match self.labels.range(..=addr).next_back() {
+            Some((&start, name)) if start == addr => name.clone(),
+            Some((&start, name)) => format!("{}+0x{:X}", name, addr - start),
+            None => format!("0x{:03X}", addr),
+        }
+    }
+
+    // `game.8o:12` followed by the text of the line when the source was
+    // found, for the instruction at exactly `addr`.
+    pub fn source_line(&self, addr: u16) -> Option<String> {
+        let (file, number) = self.lines.get(&(addr & 0x0FFF))?;
+        let text = self
+            .sources
+            .get(file)
+            .and_then(|lines| lines.get(number.wrapping_sub(1)))
+            .map(|text| text.trim());
+        Some(match text {
+            Some(text) => format!("{}:{} {}", file, number, text),
+            None => format!("{}:{}", file, number),
+        })
+    }
+}
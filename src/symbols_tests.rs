@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Cpu;
+    use crate::disasm::{disassemble_with, listing};
+    use crate::mem::Memory;
+    use crate::symbols::Symbols;
+    use std::fs;
+
+    const SYMBOLS: &str = "# exported labels\nmain 0x200\nmain_loop = 0x204\ndraw 520\n\
+                           :const LIVES 3\nline 0x200 game.8o:2\nline 0x204 game.8o:3\n";
+
+    #[test]
+    fn addresses_are_shown_relative_to_labels() {
+        let symbols = Symbols::parse(SYMBOLS).unwrap();
+        assert_eq!(symbols.format_addr(0x200), "main");
+        assert_eq!(symbols.format_addr(0x202), "main+0x2");
+        assert_eq!(symbols.format_addr(0x20C), "draw+0x4");
+        assert_eq!(symbols.format_addr(0x1FE), "0x1FE");
+        assert_eq!(symbols.label(0x204), Some("main_loop"));
+        assert_eq!(symbols.label(0x206), None);
+        assert_eq!(symbols.lookup("draw"), Some(0x208));
+        assert_eq!(symbols.lookup("LIVES"), Some(3));
+        assert_eq!(symbols.lookup("lives"), None);
+        assert_eq!(symbols.resolve("main_loop"), Ok(0x204));
+        assert_eq!(symbols.resolve("0x2A0"), Ok(0x2A0));
+        assert_eq!(symbols.resolve("lives"), Err("Unknown label lives".to_string()));
+
+        let text = |opcode| disassemble_with(&Cpu::decode(opcode), &symbols);
+        assert_eq!(text(0x2208), "CALL draw");
+        assert_eq!(text(0x1206), "JP main_loop+0x2");
+        assert_eq!(text(0xA100), "LD I, 0x100");
+
+        let mut mem = Memory::new();
+        mem.load(vec![0x60, 0x03, 0x22, 0x08, 0x12, 0x04]).unwrap();
+        let lines = listing(&mem, 0x200, 3, &symbols);
+        assert_eq!(lines[2].text, "JP main_loop");
+    }
+
+    #[test]
+    fn source_lines_come_from_files_next_to_the_symbols() {
+        let dir = std::env::temp_dir().join(format!("chip8_symbols_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("game.sym");
+        fs::write(&path, SYMBOLS).unwrap();
+        fs::write(dir.join("game.8o"), ": main\n  v0 := 3\n: main_loop\n").unwrap();
+
+        let symbols = Symbols::load(&path.to_string_lossy()).unwrap();
+        assert_eq!(symbols.source_line(0x200).as_deref(), Some("game.8o:2 v0 := 3"));
+        assert_eq!(symbols.source_line(0x204).as_deref(), Some("game.8o:3 : main_loop"));
+        assert_eq!(symbols.source_line(0x202), None);
+
+        fs::remove_file(dir.join("game.8o")).unwrap();
+        let symbols = Symbols::load(&path.to_string_lossy()).unwrap();
+        assert_eq!(symbols.source_line(0x200).as_deref(), Some("game.8o:2"));
+        assert!(Symbols::load(&dir.join("missing.sym").to_string_lossy()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        assert!(Symbols::parse("").unwrap().is_empty());
+        assert!(Symbols::parse("main 0xZZZ").is_err());
+        assert!(Symbols::parse("line 0x200 game.8o").is_err());
+        assert!(Symbols::parse("main 0x200 extra words").is_err());
+        let err = Symbols::parse("main 0x200\n:const LIVES").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{}", err);
+    }
+}
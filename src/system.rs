@@ -1,34 +1,135 @@
 use sdl2::keyboard::Keycode;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-use std::collections::HashMap;
+use sdl2::render::{Canvas, RenderTarget};
 use std::fs;
-use std::num::Wrapping;
-use std::usize;
+use std::time::Duration;
 
+use crate::bus::Bus;
 use crate::cpu;
+use crate::disasm;
+use crate::font::FontSet;
+use crate::keypad::{KeyEvent, KeyWait, Keypad};
 use crate::mem;
+use crate::platform::{Profile, Quirks};
+use crate::symbols::Symbols;
+#[cfg(test)]
+use crate::test_machine;
+use crate::timing::{self, Timing};
 use crate::video;
 
-const ROMS_DIR : &str = "<YOUR ROM PATH HERE>";
+// Save states start with this, followed by a format version.
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 1;
+const STATE_SIZE: usize = 4 + 1 + 2 + 2 + 1 + cpu::DEFAULT_STACK_DEPTH * 2 + 16 + 2 + mem::RAM_SIZE + video::SCREEN_WIDTH * video::SCREEN_HEIGHT;
 
-pub struct System {
+// The machine around the CPU: memory, display, keypad and timers.
+struct SystemBus {
     video: video::Video,
-    cpu: cpu::Cpu,
     mem: mem::Memory,
+    keypad: Keypad,
+    delay_timer: u8,
+    sound_timer: u8,
     should_draw: bool,
+    // Machine cycles spent by the last DXYN, for VIP timing.
+    draw_cycles: u32,
+}
+
+impl Bus for SystemBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.mem.read(addr)
+    }
+    fn write(&mut self, addr: u16, val: u8) {
+        self.mem.set(addr as usize, val)
+    }
+    fn fetch(&mut self, addr: u16) -> u16 {
+        self.mem.fetch(addr)
+    }
+    fn fetch_decoded(&mut self, addr: u16) -> cpu::Instruction {
+        self.mem.fetch_decoded(addr)
+    }
+
+    fn clear_screen(&mut self) {
+        self.video.clear();
+        self.should_draw = true;
+    }
+    fn draw_sprite(&mut self, x: u8, y: u8, n: u16, addr: u16) -> bool {
+        self.should_draw = true;
+        self.draw_cycles = timing::draw_cycles(x, n);
+        let sprite: Vec<u8> = (0..n).map(|row| self.mem.read_sprite(addr.wrapping_add(row))).collect();
+        self.video.draw_sprite(x, y, &sprite)
+    }
+
+    fn font_address(&self, digit: u8) -> u16 {
+        self.mem.font_address(digit)
+    }
+    fn large_font_address(&self, digit: u8) -> Option<u16> {
+        self.mem.large_font_address(digit)
+    }
+
+    fn is_key_pressed(&self, key: u8) -> bool {
+        self.keypad.is_pressed(key)
+    }
+    fn wait_for_key(&mut self) -> Option<u8> {
+        self.keypad.wait_for_key()
+    }
+
+    fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    fn set_delay_timer(&mut self, val: u8) {
+        self.delay_timer = val
+    }
+    fn set_sound_timer(&mut self, val: u8) {
+        self.sound_timer = val
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        rand::random::<u8>()
+    }
+}
+
+pub struct System {
+    cpu: cpu::Cpu,
+    bus: SystemBus,
     should_play_sound: bool,
     key_map: [sdl2::keyboard::Keycode; 16],
-    delay_timer: u8,
-    sound_timer: u8,
+    // Host keys pressing a keypad key in addition to `key_map`.
+    key_aliases: Vec<(Keycode, u8)>,
+    timing: Timing,
+    // Machine cycles (VIP timing) or instructions (fixed and tickrate timing)
+    // used so far in the current frame.
+    frame_cycles: u32,
+    end_of_frame: bool,
+    frame_ended: bool,
+    // Print every executed instruction.
+    trace: bool,
+    // Print unknown opcodes and stack faults as they happen. Frontends that
+    // own the terminal turn this off and show them themselves.
+    log: bool,
+    stack_fault: Option<cpu::StackFault>,
+    // The loaded ROM, for resets.
+    rom: Vec<u8>,
+    // Bytes written over the ROM on every reset, from patch cheats.
+    patches: Vec<(u16, u8)>,
+    // Labels and source lines of the loaded program, for traces and the
+    // debugger.
+    symbols: Symbols,
+    profile: Profile,
+    // Blocked and self-modifying writes the frontend has not taken yet.
+    write_alerts: Vec<mem::MemoryEvent>,
 }
 impl System {
     pub fn new() -> System {
-        let new_system = System {
-            video: video::Video::new(),
+        System {
             cpu: cpu::Cpu::new(),
-            mem: mem::Memory::new(),
-            should_draw: false,
+            bus: SystemBus {
+                video: video::Video::new(),
+                mem: mem::Memory::new(),
+                keypad: Keypad::new(),
+                delay_timer: 0,
+                sound_timer: 0,
+                should_draw: false,
+                draw_cycles: 0,
+            },
             should_play_sound: false,
             key_map: [
                 Keycode::X,    // 0x0
@@ -48,324 +149,486 @@ impl System {
                 Keycode::F,    // 0xE
                 Keycode::V,    // 0xF
             ],
-            delay_timer: 0,
-            sound_timer: 0,
-        };
-        new_system
+            key_aliases: Vec::new(),
+            timing: Timing::Fixed,
+            frame_cycles: 0,
+            end_of_frame: false,
+            frame_ended: false,
+            trace: true,
+            log: true,
+            stack_fault: None,
+            rom: Vec::new(),
+            patches: Vec::new(),
+            symbols: Symbols::new(),
+            profile: Profile::DEFAULT,
+            write_alerts: Vec::new(),
+        }
     }
 
-    pub fn load_game(&mut self, file_path: &str) {
-        let file_path = ROMS_DIR.to_owned() + file_path;
-        match fs::read(file_path) {
-            Ok(data) => {
-                self.mem.load(data);
-            }
-            Err(e) => {
-                panic!("ERROR {}", e);
-            }
+    pub fn load_rom(&mut self, data: Vec<u8>) -> Result<(), String> {
+        self.bus.mem.load(data.clone())?;
+        self.rom = data;
+        Ok(())
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+    // Patches are applied straight away and again on every reset.
+    pub fn set_patches(&mut self, patches: Vec<(u16, u8)>) {
+        for &(addr, val) in &patches {
+            self.bus.mem.poke(addr, val);
+        }
+        self.patches = patches;
+    }
+
+    // Resets the machine and starts the ROM at `path` on the current
+    // profile. On error the running program is left alone.
+    pub fn load_file(&mut self, path: &str) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.load_program(data, self.profile).map_err(|e| format!("{}: {}", path, e))
+    }
+    // Turns the machine into `profile` and starts `data` on it. On error the
+    // running program is left alone.
+    pub fn load_program(&mut self, data: Vec<u8>, profile: Profile) -> Result<(), String> {
+        if data.len() > profile.max_rom_size() {
+            return Err(format!(
+                "ROM is {} bytes, at most {} fit at 0x{:03X} on the {}",
+                data.len(),
+                profile.max_rom_size(),
+                profile.load_address,
+                profile.name
+            ));
+        }
+        self.profile = profile;
+        self.bus.mem.set_layout(profile.load_address as usize, profile.memory_size);
+        // Patches belong to the old ROM
+        self.rom = data;
+        self.patches.clear();
+        self.reset();
+        Ok(())
+    }
+    pub fn profile(&self) -> Profile {
+        self.profile
+    }
+
+    // The font stays where it is; the ROM database changes it per platform.
+    pub fn set_font(&mut self, font: FontSet) -> Result<(), String> {
+        let base = self.bus.mem.font_base();
+        self.bus.mem.set_font(font, base)
+    }
+    pub fn set_font_base(&mut self, base: u16) -> Result<(), String> {
+        let font = self.bus.mem.font();
+        self.bus.mem.set_font(font, base)
+    }
+    pub fn font(&self) -> FontSet {
+        self.bus.mem.font()
+    }
+
+    // A CPU in its power on state, configured like the current one.
+    fn fresh_cpu(&self) -> cpu::Cpu {
+        let mut cpu = cpu::Cpu::new();
+        cpu.set_quirks(self.cpu.quirks());
+        cpu.set_stack_depth(self.cpu.stack_depth());
+        cpu.set_stack_in_memory(self.cpu.stack_in_memory());
+        cpu.jump(self.profile.load_address);
+        cpu
+    }
+
+    // Power cycles the machine and reloads the ROM.
+    pub fn reset(&mut self) {
+        self.cpu = self.fresh_cpu();
+        self.bus.mem.reset();
+        self.bus.mem.load(self.rom.clone()).expect("ROM was checked when loaded");
+        for &(addr, val) in &self.patches {
+            self.bus.mem.poke(addr, val);
+        }
+        self.bus.video.clear();
+        self.bus.delay_timer = 0;
+        self.bus.sound_timer = 0;
+        self.bus.should_draw = true;
+        self.bus.keypad.cancel_wait();
+        self.should_play_sound = false;
+        self.frame_cycles = 0;
+        self.end_of_frame = false;
+        self.frame_ended = false;
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(STATE_MAGIC);
+        state.push(STATE_VERSION);
+        state.extend_from_slice(&self.cpu.pc().to_be_bytes());
+        state.extend_from_slice(&self.cpu.index().to_be_bytes());
+        state.push(self.cpu.sp() as u8);
+        for addr in self.cpu.stack().iter() {
+            state.extend_from_slice(&addr.to_be_bytes());
+        }
+        state.extend_from_slice(&self.cpu.v());
+        state.push(self.bus.delay_timer);
+        state.push(self.bus.sound_timer);
+        state.extend_from_slice(self.bus.mem.ram());
+        state.extend_from_slice(self.bus.video.pixels());
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        if state.len() != STATE_SIZE || &state[0..4] != STATE_MAGIC {
+            return Err("Not a save state".to_string());
+        }
+        if state[4] != STATE_VERSION {
+            return Err(format!("Unsupported save state version {}", state[4]));
+        }
+        let word = |at: usize| u16::from_be_bytes([state[at], state[at + 1]]);
+        let sp = state[9] as usize;
+        let mut stack = [0; cpu::DEFAULT_STACK_DEPTH];
+        for (i, addr) in stack.iter_mut().enumerate() {
+            *addr = word(10 + i * 2);
+        }
+        let mut at = 10 + cpu::DEFAULT_STACK_DEPTH * 2;
+        let mut cpu = self.fresh_cpu();
+        cpu.set_stack(stack, 0);
+        cpu.set_sp(sp).map_err(|e| format!("Save state has stack pointer {}, but {}", sp, e))?;
+        cpu.jump(word(5));
+        cpu.set_index(word(7));
+        for i in 0..16 {
+            cpu.set_v(state[at + i], i);
+        }
+        self.cpu = cpu;
+        at += 16;
+        self.bus.delay_timer = state[at];
+        self.bus.sound_timer = state[at + 1];
+        at += 2;
+        self.bus.mem.set_ram(&state[at..at + mem::RAM_SIZE]);
+        at += mem::RAM_SIZE;
+        self.bus.video.set_pixels(&state[at..]);
+        self.bus.should_draw = true;
+        self.bus.keypad.cancel_wait();
+        self.frame_cycles = 0;
+        Ok(())
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+    pub fn cpu(&self) -> &cpu::Cpu {
+        &self.cpu
+    }
+    pub fn cpu_mut(&mut self) -> &mut cpu::Cpu {
+        &mut self.cpu
+    }
+    // (delay, sound)
+    pub fn timers(&self) -> (u8, u8) {
+        (self.bus.delay_timer, self.bus.sound_timer)
+    }
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.bus.delay_timer = delay;
+        self.bus.sound_timer = sound;
+    }
+
+    pub fn memory(&self) -> &mem::Memory {
+        &self.bus.mem
+    }
+    pub fn memory_mut(&mut self) -> &mut mem::Memory {
+        &mut self.bus.mem
+    }
+
+    pub fn key_code(&self, key: usize) -> Keycode {
+        self.key_map[key]
+    }
+    pub fn set_key_aliases(&mut self, aliases: Vec<(Keycode, u8)>) {
+        self.key_aliases = aliases;
+    }
+    // Passes a host key going down or up to the keypad, returning whether
+    // the key is mapped to one.
+    pub fn key_event(&mut self, keycode: Keycode, pressed: bool, time: Duration) -> bool {
+        let aliases = self.key_aliases.iter().filter(|(alias, _)| *alias == keycode).map(|(_, key)| *key);
+        let keys: Vec<u8> = (0..16u8)
+            .filter(|&key| self.key_map[key as usize] == keycode)
+            .chain(aliases)
+            .collect();
+        for &key in &keys {
+            self.bus.keypad.push(KeyEvent { key: key & 0xF, pressed, time });
+        }
+        !keys.is_empty()
+    }
+    pub fn keypad(&self) -> &Keypad {
+        &self.bus.keypad
+    }
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.bus.keypad
+    }
+    pub fn set_key_wait(&mut self, wait: KeyWait) {
+        self.bus.keypad.set_wait(wait);
+    }
+    // Every host key that presses a keypad key.
+    pub fn host_keys(&self) -> Vec<Keycode> {
+        let mut keys = self.key_map.to_vec();
+        keys.extend(self.key_aliases.iter().map(|(keycode, _)| *keycode));
+        keys
+    }
+
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.cpu.set_stack_depth(depth);
+    }
+    pub fn set_stack_in_memory(&mut self, in_memory: bool) {
+        self.cpu.set_stack_in_memory(in_memory);
+    }
+    pub fn take_stack_fault(&mut self) -> Option<cpu::StackFault> {
+        self.stack_fault.take()
+    }
+    pub fn take_write_alerts(&mut self) -> Vec<mem::MemoryEvent> {
+        std::mem::take(&mut self.write_alerts)
+    }
+    // Addresses of the CALLs being executed, outermost first. A stack in
+    // memory holds return addresses, which the program may have changed.
+    pub fn call_stack(&self) -> Vec<u16> {
+        let sp = self.cpu.sp();
+        if !self.cpu.stack_in_memory() {
+            return self.cpu.stack()[..sp].to_vec();
         }
+        (0..sp)
+            .map(|i| {
+                let ret = self.bus.mem.get_instruction(cpu::Cpu::memory_stack_entry(i));
+                ret.wrapping_sub(2) & 0x0FFF
+            })
+            .collect()
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.cpu.quirks()
+    }
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.set_quirks(quirks);
+        self.bus.video.set_wrap(quirks.wrap);
+    }
+    pub fn set_palette(&mut self, off: sdl2::pixels::Color, on: sdl2::pixels::Color) {
+        self.bus.video.set_palette(off, on);
+        self.bus.should_draw = true;
     }
 
     pub fn should_draw(&self) -> bool {
-        self.should_draw
+        self.bus.should_draw
     }
     pub fn should_play_sound(&self) -> bool {
         self.should_play_sound
     }
 
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
 
-    pub fn tick(&mut self, keys_pressed: &HashMap<sdl2::keyboard::Keycode, i32>) {
-        let instr = self.cpu.fetch_decode(&self.mem);
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+    pub fn set_log(&mut self, log: bool) {
+        self.log = log;
+    }
 
-        match instr {
-            cpu::Instruction::Invalid(x) => println!("Unknown opcode 0x{:x}", x),
-            _ => println!("{:?}", instr),
-        }
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.frame_cycles = 0;
+    }
+    // Whether the last tick ran into the 60 Hz display interrupt. Always true
+    // with fixed timing, where every tick counts down the timers.
+    pub fn end_of_frame(&self) -> bool {
+        self.end_of_frame
+    }
+    // Whether the last tick finished a 60 Hz frame. With fixed timing that is
+    // every FIXED_TICKS_PER_FRAME ticks, otherwise the display interrupt.
+    pub fn frame_ended(&self) -> bool {
+        self.frame_ended
+    }
+    pub fn frame_cycles(&self) -> u32 {
+        self.frame_cycles
+    }
+
+    // Runs one instruction and returns it.
+    pub fn tick(&mut self) -> cpu::Instruction {
+        self.bus.keypad.update();
+        let pc = self.cpu.pc();
+        let instr = self.cpu.step(&mut self.bus);
 
         match instr {
-            cpu::Instruction::Invalid(_) => self.cpu.inc_pc(),
-            cpu::Instruction::ClearScreen => {
-                self.cpu.inc_pc();
-                self.video.clear();
-                self.should_draw = true;
-            }
-            cpu::Instruction::Return => self.cpu.return_to_sp(),
-            cpu::Instruction::Jump(addr) => self.cpu.jump(addr),
-            cpu::Instruction::Call(addr) => self.cpu.jump_store(addr),
-            cpu::Instruction::Draw(x, y, n) => {
-                self.cpu.inc_pc();
-                self.should_draw = true;
-                match self.video.draw_sprite(x, y, n, self.cpu.index(), &self.mem) {
-                    true => self.cpu.set_v(1, 0xF),
-                    false => self.cpu.set_v(0, 0xF),
-                }
-            }
-            cpu::Instruction::VxNNSkip(x, nn) => {
-                self.cpu.inc_pc();
-                if self.cpu.get_v(x as usize) == nn as u8 {
-                    self.cpu.inc_pc();
-                }
-            }
-            cpu::Instruction::VxNNNotSkip(x, nn) => {
-                self.cpu.inc_pc();
-                if self.cpu.get_v(x as usize) != nn as u8 {
-                    self.cpu.inc_pc();
-                }
-            }
-            cpu::Instruction::VxVySkip(x, y) => {
-                self.cpu.inc_pc();
-                if self.cpu.get_v(x as usize) == self.cpu.get_v(y as usize) {
-                    self.cpu.inc_pc();
-                }
-            }
-            cpu::Instruction::SetRegister(x, nn) => {
-                self.cpu.inc_pc();
-                //println!("Setting register: 0x{:x} to value 0x{:x}", x, nn);
-                self.cpu.set_v(nn as u8, x as usize);
-            }
-            cpu::Instruction::AddRegister(x, nn) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let new_val = Wrapping(self.cpu.get_v(x)) + Wrapping(nn as u8);
-                self.cpu.set_v(new_val.0, x);
-            }
-            cpu::Instruction::VxSetVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let y = y as usize;
-                let y_val = self.cpu.get_v(y);
-                self.cpu.set_v(y_val, x);
-            }
-            cpu::Instruction::VxBitOrVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                self.cpu.set_v(val_x | val_y, x);
-            }
-            cpu::Instruction::VxBitAndVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                self.cpu.set_v(val_x & val_y, x);
-            }
-            cpu::Instruction::VxBitXOrVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                self.cpu.set_v(val_x ^ val_y, x);
-            }
-            cpu::Instruction::VxBitAddVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                if val_y > (0xFF - val_x) {
-                    self.cpu.set_v(1, 0xF); // Carry
-                } else {
-                    self.cpu.set_v(0, 0xF);
-                }
-                let result = Wrapping(val_x) + Wrapping(val_y);
-                println!("{}+{}={}",val_x,val_y,result);
-                self.cpu.set_v(result.0, x);
-            }
-            cpu::Instruction::VxSubVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                if val_y > val_x {
-                    self.cpu.set_v(0, 0xF); //Borrow
-                } else {
-                    self.cpu.set_v(1, 0xF);
-                }
-                self.cpu.set_v((Wrapping(val_x) - Wrapping(val_y)).0, x);
-            }
-            cpu::Instruction::VxBitShiftRVy(x, _) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                self.cpu.set_v(val_x & 0b0001, 0xF);
-                //println!("{:x}>>1={:x}",val_x,val_x>>1);
-                self.cpu.set_v(val_x >> 1, x);
-            }
-            cpu::Instruction::VxBitShiftLVy(x, _) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                self.cpu.set_v(val_x & 0b1000, 0xF);
-                self.cpu.set_v(val_x << 1, x);
-            }
-            cpu::Instruction::VxMinusVy(x, y) => {
-                self.cpu.inc_pc();
-                let x = x as usize;
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                if val_x > val_y {
-                    self.cpu.set_v(0, 0xF); //Borrow
-                } else {
-                    self.cpu.set_v(1, 0xF);
-                }
-                self.cpu.set_v(val_y - val_x, x);
-            }
-            cpu::Instruction::VxNotVySkip(x, y) => {
-                self.cpu.inc_pc();
-                let val_x = self.cpu.get_v(x as usize);
-                let val_y = self.cpu.get_v(y as usize);
-                if val_x != val_y {
-                    self.cpu.inc_pc()
-                }
-            }
-            cpu::Instruction::SetIndexRegister(nnn) => {
-                self.cpu.inc_pc();
-                self.cpu.set_index(nnn);
-            }
-            cpu::Instruction::V0Jump(addr) => self.cpu.jump(addr),
-            cpu::Instruction::VxRand(x, n) => {
-                self.cpu.inc_pc();
-                self.cpu.set_v(n as u8, x as usize);
-            }
-            cpu::Instruction::KeyVxSkip(x) => {
-                self.cpu.inc_pc();
-                let vx_key = self.cpu.get_v(x as usize);
-                if vx_key <= 0xF {
-                    let requested_key = &self.key_map[vx_key as usize];
-                    match keys_pressed.get(requested_key) {
-                        Some(k) => {
-                            if *k == 1 {
-                                self.cpu.inc_pc();
-                            }
-                        }
-                        None => {
-                            println!("Unknown key {}", requested_key)
-                        }
-                    }
+            cpu::Instruction::Invalid(x) if self.log => println!("Unknown opcode 0x{:x}", x),
+            cpu::Instruction::Invalid(_) => {}
+            _ if self.trace && self.symbols.is_empty() => println!("{:?}", instr),
+            _ if self.trace => {
+                let text = disasm::disassemble_with(&instr, &self.symbols);
+                match self.symbols.source_line(pc) {
+                    Some(line) => println!("{}: {}  ; {}", self.symbols.format_addr(pc), text, line),
+                    None => println!("{}: {}", self.symbols.format_addr(pc), text),
                 }
             }
-            cpu::Instruction::KeyNotVxSkip(x) => {
-                self.cpu.inc_pc();
-                let vx_key = self.cpu.get_v(x as usize);
-                if vx_key <= 0xF {
-                    let requested_key = &self.key_map[vx_key as usize];
-                    match keys_pressed.get(requested_key) {
-                        Some(k) => {
-                            if *k == 0 {
-                                self.cpu.inc_pc();
-                            }
-                        }
-                        None => {
-                            println!("Unknown key {}", requested_key)
-                        }
-                    }
-                }
-            }
-            cpu::Instruction::GetKeyVx(x) => {
-                let mut press_detected = false;
-                for i in 0..16 {
-                    let requested_key = &self.key_map[i];
-                    match keys_pressed.get(requested_key) {
-                        Some(k) => {
-                            if *k == 1 {
-                                press_detected = true;
-                                self.cpu.set_v(i as u8, x as usize);
-                            }
-                        }
-                        None => {
-                            println!("Unknown key {}", requested_key)
-                        }
-                    }
-                }
-                if press_detected {
-                    self.cpu.inc_pc();
-                }
-            }
-            cpu::Instruction::SetTimerVx(x) => {
-                self.cpu.set_v(self.delay_timer, x as usize);
-                self.cpu.inc_pc()
-            }
-            cpu::Instruction::GetTimerVx(x) => {
-                self.delay_timer = self.cpu.get_v(x as usize);
-                self.cpu.inc_pc()
-            }
-            cpu::Instruction::SetSoundTimerVx(x) => {
-                self.sound_timer = self.cpu.get_v(x as usize);
-                self.cpu.inc_pc()
-            }
-            cpu::Instruction::AddIVx(x) => {
-                let vx = self.cpu.get_v(x as usize) as u16;
-                if vx + self.cpu.index() > 0xFFF{
-                    self.cpu.set_v(1,0xF)
-                }else{
-                    self.cpu.set_v(0,0xF)
-                }
-                let new_value =  vx + self.cpu.index();
-                self.cpu.set_index(new_value);
-                self.cpu.inc_pc();
-            }
-            cpu::Instruction::SetISprite(x) => {
-                let addr = (self.cpu.get_v(x as usize) * 0x5) as u16;
-                self.cpu.set_index(addr);
-                self.cpu.inc_pc()
+            _ => {}
+        }
+        if let Some(fault) = self.cpu.take_stack_fault() {
+            if self.log {
+                println!("{}", fault);
             }
-            cpu::Instruction::BCDVX(x) => {
-                let i = self.cpu.index() as usize;
-                let vx = self.cpu.get_v(x as usize);
-                self.mem.set(i, vx / 100);
-                self.mem.set(i + 1, (vx / 10) % 10);
-                self.mem.set(i + 2, (vx % 100) % 10);
-                //println!("vx: {} i: {} i+1: {} i+2: {}",vx,vx / 100,(vx / 10) % 10, (vx % 100) % 10);
-                self.cpu.inc_pc();
+            self.stack_fault = Some(fault);
+        }
+        for event in self.bus.mem.take_write_alerts() {
+            if self.trace {
+                println!("  ; {}", event.alert().unwrap_or_default());
             }
-            cpu::Instruction::RegDump(x) => {
-                for vx in 0..x + 1 {
-                    let i = self.cpu.index() + vx;
-                    self.mem.set(i as usize, self.cpu.get_v(vx as usize));
-                }
-                self.cpu.set_index(x + self.cpu.index() + 1);
-                self.cpu.inc_pc()
+            if self.write_alerts.len() == mem::MAX_WRITE_ALERTS {
+                self.write_alerts.remove(0);
             }
+            self.write_alerts.push(event);
+        }
 
-            cpu::Instruction::RegLoad(x) => {
-                for vx in 0..x + 1 {
-                    let i = self.cpu.index() + vx;
-                    self.cpu
-                        .set_v(self.mem.get_byte(i as u16) as u8, vx as usize);
-                }
-                self.cpu.set_index(x + self.cpu.index() + 1);
-                self.cpu.inc_pc()
+        self.end_of_frame = match self.timing {
+            Timing::Fixed => true,
+            Timing::Vip => self.vip_cycles(pc, &instr),
+            Timing::Tickrate(tickrate) => self.count_instruction(&instr, tickrate),
+        };
+        self.frame_ended = match self.timing {
+            Timing::Fixed => self.count_fixed_tick(),
+            _ => self.end_of_frame,
+        };
+        if self.end_of_frame {
+            self.count_down_timers();
+        }
+        if self.frame_ended {
+            self.bus.keypad.end_frame();
+        }
+        instr
+    }
+
+    // Runs instructions up to the end of a 60 Hz frame, for frontends that
+    // are driven a frame at a time. With fixed timing every instruction ends
+    // a frame, so FIXED_TICKS_PER_FRAME of them are run instead.
+    pub fn run_frame(&mut self) {
+        if self.timing == Timing::Fixed {
+            for _ in 0..timing::FIXED_TICKS_PER_FRAME {
+                self.tick();
+            }
+            return;
+        }
+        for _ in 0..timing::MAX_TICKS_PER_FRAME {
+            self.tick();
+            if self.end_of_frame {
+                break;
             }
         }
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+    }
+
+    // Adds the cost of `instr` to the current frame and returns whether the
+    // display interrupt happened. DXYN waits for the interrupt before drawing,
+    // so its own cost goes to the next frame.
+    fn vip_cycles(&mut self, pc: u16, instr: &cpu::Instruction) -> bool {
+        let skipped = self.cpu.pc() == pc.wrapping_add(4) & 0x0FFF;
+        let cycles = timing::vip_cycles(instr, skipped);
+        if let cpu::Instruction::Draw(..) = instr {
+            self.frame_cycles = cycles + self.bus.draw_cycles;
+            return true;
+        }
+        self.frame_cycles += cycles;
+        if self.frame_cycles >= timing::INTERPRETER_CYCLES_PER_FRAME {
+            self.frame_cycles -= timing::INTERPRETER_CYCLES_PER_FRAME;
+            return true;
         }
+        false
+    }
 
-        self.should_play_sound = self.sound_timer == 1;
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+    // Counts `instr` towards a frame of `tickrate` instructions and returns
+    // whether the frame is over.
+    fn count_instruction(&mut self, instr: &cpu::Instruction, tickrate: u32) -> bool {
+        self.frame_cycles += 1;
+        let vblank = matches!(instr, cpu::Instruction::Draw(..)) && self.cpu.quirks().vblank;
+        if vblank || self.frame_cycles >= tickrate {
+            self.frame_cycles = 0;
+            return true;
         }
+        false
     }
 
-    pub fn draw(&mut self, canvas: &mut Canvas<Window>) {
-        self.video.draw(canvas);
-        self.should_draw = false;
+    // Counts a tick towards a frame of FIXED_TICKS_PER_FRAME ticks and returns
+    // whether the frame is over.
+    fn count_fixed_tick(&mut self) -> bool {
+        self.frame_cycles += 1;
+        if self.frame_cycles >= timing::FIXED_TICKS_PER_FRAME {
+            self.frame_cycles = 0;
+            return true;
+        }
+        false
+    }
+
+    fn count_down_timers(&mut self) {
+        if self.bus.delay_timer > 0 {
+            self.bus.delay_timer -= 1;
+        }
+
+        self.should_play_sound = self.bus.sound_timer == 1;
+        if self.bus.sound_timer > 0 {
+            self.bus.sound_timer -= 1;
+        }
+    }
+
+    pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) {
+        self.bus.video.draw(canvas);
+        self.bus.should_draw = false;
+    }
+    // For frontends that draw `video()` themselves.
+    pub fn video(&self) -> &video::Video {
+        &self.bus.video
+    }
+    pub fn mark_drawn(&mut self) {
+        self.bus.should_draw = false;
     }
 
     // for tests
 
-    pub fn load_test(&mut self, data:Vec<u8>){
-        self.mem.load(data)
+    #[cfg(test)]
+    pub fn load_test(&mut self, data: Vec<u8>) {
+        self.bus.mem.load(data).unwrap()
     }
-    pub fn load_test_v(&mut self, data:Vec<u8>){
-        for (i, v) in data.iter().enumerate(){
+    #[cfg(test)]
+    pub fn load_test_v(&mut self, data: Vec<u8>) {
+        for (i, v) in data.iter().enumerate() {
             self.cpu.set_v(*v, i);
         }
     }
 
-    pub fn test_eq_v(&self, vx:usize, val :u8){
+    #[cfg(test)]
+    pub fn test_eq_v(&self, vx: usize, val: u8) {
         let vx_val = self.cpu.get_v(vx);
-        assert_eq!(vx_val,val);
+        assert_eq!(vx_val, val);
+    }
+
+    #[cfg(test)]
+    pub fn test_state(&self) -> test_machine::State {
+        test_machine::State {
+            pc: self.cpu.pc(),
+            index: self.cpu.index(),
+            sp: self.cpu.sp(),
+            stack: self.cpu.stack(),
+            v: self.cpu.v(),
+            delay_timer: self.bus.delay_timer,
+            sound_timer: self.bus.sound_timer,
+            ram: self.bus.mem.ram().to_vec(),
+            pixels: self.bus.video.pixels().to_vec(),
+        }
     }
 
+    #[cfg(test)]
+    pub fn load_test_state(&mut self, state: &test_machine::State) {
+        self.cpu.jump(state.pc);
+        self.cpu.set_index(state.index);
+        self.cpu.set_stack(state.stack, state.sp);
+        for (i, v) in state.v.iter().enumerate() {
+            self.cpu.set_v(*v, i);
+        }
+        self.bus.delay_timer = state.delay_timer;
+        self.bus.sound_timer = state.sound_timer;
+        self.bus.mem.set_ram(&state.ram);
+        self.bus.video.set_pixels(&state.pixels);
+    }
 }
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod tests {
+    use crate::platform::Profile;
+    use crate::system::System;
+    use std::fs;
+
+    fn run(emu: &mut System, ticks: usize) {
+        for _ in 0..ticks {
+            emu.tick();
+        }
+    }
+
+    // V0 = 5, delay timer = V0, I = font of V0, draw it, V0 += 1, call 0x300
+    const ROM: [u8; 12] = [0x60, 0x05, 0xF0, 0x15, 0xF0, 0x29, 0xD0, 0x05, 0x70, 0x01, 0x23, 0x00];
+
+    #[test]
+    fn save_state_round_trips() {
+        let mut emu = System::new();
+        emu.load_rom(ROM.to_vec()).unwrap();
+        run(&mut emu, 6);
+        let saved = emu.test_state();
+        let state = emu.save_state();
+
+        run(&mut emu, 3);
+        assert_ne!(emu.test_state(), saved);
+
+        let mut other = System::new();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.test_state(), saved);
+        emu.load_state(&state).unwrap();
+        assert_eq!(emu.test_state(), saved);
+    }
+
+    #[test]
+    fn bad_save_states_are_rejected() {
+        let mut emu = System::new();
+        emu.load_rom(ROM.to_vec()).unwrap();
+        let state = emu.save_state();
+
+        assert!(emu.load_state(&state[1..]).is_err());
+        let mut corrupt = state.clone();
+        corrupt[0] = b'X';
+        assert!(emu.load_state(&corrupt).is_err());
+        let mut newer = state.clone();
+        newer[4] += 1;
+        assert!(emu.load_state(&newer).is_err());
+
+        // the stack pointer has to fit the configured depth
+        let mut deep = state;
+        deep[9] = 13;
+        emu.set_stack_depth(12);
+        assert_eq!(
+            emu.load_state(&deep),
+            Err("Save state has stack pointer 13, but the stack is 12 deep".to_string())
+        );
+        deep[9] = 12;
+        emu.load_state(&deep).unwrap();
+        assert_eq!(emu.cpu().sp(), 12);
+    }
+
+    #[test]
+    fn reset_reloads_the_rom() {
+        let mut emu = System::new();
+        emu.load_rom(ROM.to_vec()).unwrap();
+        let initial = emu.test_state();
+        run(&mut emu, 6);
+        emu.memory_mut().set(0x201, 0x99);
+
+        emu.reset();
+        assert_eq!(emu.test_state(), initial);
+    }
+
+    #[test]
+    fn load_file_replaces_the_program_or_keeps_it() {
+        let dir = std::env::temp_dir().join(format!("chip8-system-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.ch8");
+        let big = dir.join("big.ch8");
+        fs::write(&good, [0x61, 0x07]).unwrap();
+        fs::write(&big, vec![0; 0xE01]).unwrap();
+
+        let mut emu = System::new();
+        emu.load_rom(ROM.to_vec()).unwrap();
+        run(&mut emu, 2);
+        assert!(emu.load_file(big.to_str().unwrap()).is_err());
+        assert!(emu.load_file(dir.join("missing.ch8").to_str().unwrap()).is_err());
+        assert_eq!(emu.pc(), 0x204);
+
+        emu.load_file(good.to_str().unwrap()).unwrap();
+        assert_eq!(emu.pc(), 0x200);
+        assert_eq!(emu.memory().get_byte(0x202), 0x00);
+        run(&mut emu, 1);
+        emu.test_eq_v(1, 0x07);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn profiles_set_the_load_address_and_memory_size() {
+        let eti660 = Profile::by_id("eti660").unwrap();
+        let mut emu = System::new();
+        emu.load_program(vec![0x61, 0x07], eti660).unwrap();
+        assert_eq!(emu.pc(), 0x600);
+        assert_eq!(emu.memory().get_byte(0x600), 0x61);
+        assert_eq!(emu.memory().get_byte(0x200), 0x00);
+        run(&mut emu, 1);
+        emu.test_eq_v(1, 0x07);
+        emu.reset();
+        assert_eq!(emu.pc(), 0x600);
+
+        // too big for the ETI-660 although it would fit at 0x200
+        let err = emu.load_program(vec![0; 0xA01], eti660).unwrap_err();
+        assert_eq!(err, "ROM is 2561 bytes, at most 2560 fit at 0x600 on the ETI-660");
+        assert_eq!(emu.profile(), eti660);
+        emu.load_program(vec![0; 0xA01], Profile::DEFAULT).unwrap();
+        assert_eq!(emu.pc(), 0x200);
+
+        // 2K of memory repeats through the address space
+        let vip2k = Profile::by_id("vip2k").unwrap();
+        assert!(emu.load_program(vec![0; 0x601], vip2k).is_err());
+        emu.load_program(ROM.to_vec(), vip2k).unwrap();
+        emu.memory_mut().set(0xEA0, 0x42);
+        assert_eq!(emu.memory().get_byte(0x6A0), 0x42);
+        assert_eq!(emu.memory().get_byte(0xA00), 0x60);
+        assert_eq!(emu.memory().size(), 2048);
+    }
+}
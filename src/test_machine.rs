@@ -0,0 +1,240 @@
+// Builder for per-instruction tests: set up a complete machine state, run a
+// number of ticks and assert on everything that changed (and nothing else).
+
+use std::time::Duration;
+
+use crate::keypad::KeyWait;
+use crate::platform::Quirks;
+use crate::system;
+use crate::video;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub pc: u16,
+    pub index: u16,
+    pub sp: usize,
+    pub stack: [u16; 64],
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub ram: Vec<u8>,
+    pub pixels: Vec<u8>,
+}
+
+pub struct Machine {
+    system: system::System,
+    state: State,
+    keys: [bool; 16],
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        let system = system::System::new();
+        let state = system.test_state();
+        Machine {
+            system,
+            state,
+            keys: [false; 16],
+        }
+    }
+
+    // Writes the opcodes at the current PC, so call `pc` first when needed.
+    pub fn program(mut self, opcodes: &[u16]) -> Machine {
+        let start = self.state.pc as usize;
+        for (i, opcode) in opcodes.iter().enumerate() {
+            self.state.ram[start + i * 2] = (opcode >> 8) as u8;
+            self.state.ram[start + i * 2 + 1] = (opcode & 0xFF) as u8;
+        }
+        self
+    }
+    pub fn pc(mut self, addr: u16) -> Machine {
+        self.state.pc = addr;
+        self
+    }
+    pub fn index(mut self, addr: u16) -> Machine {
+        self.state.index = addr;
+        self
+    }
+    pub fn v(mut self, x: usize, val: u8) -> Machine {
+        self.state.v[x] = val;
+        self
+    }
+    pub fn stack(mut self, entries: &[u16]) -> Machine {
+        self.state.stack[..entries.len()].copy_from_slice(entries);
+        self.state.sp = entries.len();
+        self
+    }
+    pub fn delay_timer(mut self, val: u8) -> Machine {
+        self.state.delay_timer = val;
+        self
+    }
+    pub fn sound_timer(mut self, val: u8) -> Machine {
+        self.state.sound_timer = val;
+        self
+    }
+    pub fn mem(mut self, addr: usize, data: &[u8]) -> Machine {
+        self.state.ram[addr..addr + data.len()].copy_from_slice(data);
+        self
+    }
+    pub fn pixel(mut self, x: usize, y: usize) -> Machine {
+        self.state.pixels[x + y * video::SCREEN_WIDTH] = 1;
+        self
+    }
+    pub fn key(mut self, key: usize) -> Machine {
+        self.keys[key] = true;
+        self
+    }
+    pub fn key_wait(mut self, wait: KeyWait) -> Machine {
+        self.system.set_key_wait(wait);
+        self
+    }
+    pub fn quirks(mut self, quirks: Quirks) -> Machine {
+        self.system.set_quirks(quirks);
+        self
+    }
+    pub fn stack_depth(mut self, depth: usize) -> Machine {
+        self.system.set_stack_depth(depth);
+        self
+    }
+    pub fn stack_in_memory(mut self) -> Machine {
+        self.system.set_stack_in_memory(true);
+        self
+    }
+
+    // Runs `ticks` ticks. The expected state starts out as the initial state
+    // with PC advanced one instruction per tick and both timers decremented.
+    pub fn run(mut self, ticks: usize) -> Outcome {
+        self.system.load_test_state(&self.state);
+
+        press_keys(&mut self.system, &self.keys);
+        for _ in 0..ticks {
+            self.system.tick();
+        }
+
+        let mut expected = self.state.clone();
+        expected.pc += 2 * ticks as u16;
+        expected.delay_timer = expected.delay_timer.saturating_sub(ticks as u8);
+        expected.sound_timer = expected.sound_timer.saturating_sub(ticks as u8);
+        Outcome {
+            system: self.system,
+            expected,
+        }
+    }
+}
+
+pub struct Outcome {
+    system: system::System,
+    expected: State,
+}
+
+impl Outcome {
+    pub fn pc(mut self, addr: u16) -> Outcome {
+        self.expected.pc = addr;
+        self
+    }
+    // Expect the final instruction to have skipped the following one.
+    pub fn skipped(mut self) -> Outcome {
+        self.expected.pc += 2;
+        self
+    }
+    pub fn index(mut self, addr: u16) -> Outcome {
+        self.expected.index = addr;
+        self
+    }
+    pub fn v(mut self, x: usize, val: u8) -> Outcome {
+        self.expected.v[x] = val;
+        self
+    }
+    pub fn vf(self, val: u8) -> Outcome {
+        self.v(0xF, val)
+    }
+    pub fn stack(mut self, entries: &[u16]) -> Outcome {
+        self.expected.stack[..entries.len()].copy_from_slice(entries);
+        self.expected.sp = entries.len();
+        self
+    }
+    pub fn sp(mut self, sp: usize) -> Outcome {
+        self.expected.sp = sp;
+        self
+    }
+    pub fn delay_timer(mut self, val: u8) -> Outcome {
+        self.expected.delay_timer = val;
+        self
+    }
+    pub fn sound_timer(mut self, val: u8) -> Outcome {
+        self.expected.sound_timer = val;
+        self
+    }
+    pub fn mem(mut self, addr: usize, data: &[u8]) -> Outcome {
+        self.expected.ram[addr..addr + data.len()].copy_from_slice(data);
+        self
+    }
+    pub fn pixel(mut self, x: usize, y: usize, on: bool) -> Outcome {
+        self.expected.pixels[x + y * video::SCREEN_WIDTH] = on as u8;
+        self
+    }
+    pub fn cleared_screen(mut self) -> Outcome {
+        self.expected.pixels.iter_mut().for_each(|p| *p = 0);
+        self
+    }
+
+    // Escape hatch for results that can't be predicted exactly (CXNN).
+    pub fn actual(&self) -> State {
+        self.system.test_state()
+    }
+
+    pub fn check(self) -> system::System {
+        let differences = diff(&self.system.test_state(), &self.expected);
+        assert!(differences.is_empty(), "state differs (actual != expected): {:#?}", differences);
+        self.system
+    }
+}
+
+// Holds down the keys set in `keys`, from keypad indices.
+pub fn press_keys(system: &mut system::System, keys: &[bool; 16]) {
+    for (key, _) in keys.iter().enumerate().filter(|(_, pressed)| **pressed) {
+        system.keypad_mut().press(key as u8, Duration::ZERO);
+    }
+}
+
+// Describes every field where `actual` and `expected` disagree.
+pub fn diff(actual: &State, expected: &State) -> Vec<String> {
+    let mut differences = Vec::new();
+    if actual.pc != expected.pc {
+        differences.push(format!("PC: 0x{:03x} != 0x{:03x}", actual.pc, expected.pc));
+    }
+    if actual.index != expected.index {
+        differences.push(format!("I: 0x{:03x} != 0x{:03x}", actual.index, expected.index));
+    }
+    for x in 0..16 {
+        if actual.v[x] != expected.v[x] {
+            differences.push(format!("V{:X}: 0x{:02x} != 0x{:02x}", x, actual.v[x], expected.v[x]));
+        }
+    }
+    if actual.sp != expected.sp {
+        differences.push(format!("SP: {} != {}", actual.sp, expected.sp));
+    }
+    for i in 0..actual.stack.len() {
+        if actual.stack[i] != expected.stack[i] {
+            differences.push(format!("stack[{}]: 0x{:03x} != 0x{:03x}", i, actual.stack[i], expected.stack[i]));
+        }
+    }
+    if actual.delay_timer != expected.delay_timer {
+        differences.push(format!("delay timer: {} != {}", actual.delay_timer, expected.delay_timer));
+    }
+    if actual.sound_timer != expected.sound_timer {
+        differences.push(format!("sound timer: {} != {}", actual.sound_timer, expected.sound_timer));
+    }
+    for i in 0..actual.ram.len() {
+        if actual.ram[i] != expected.ram[i] {
+            differences.push(format!("mem 0x{:03x}: 0x{:02x} != 0x{:02x}", i, actual.ram[i], expected.ram[i]));
+        }
+    }
+    for i in 0..actual.pixels.len() {
+        if actual.pixels[i] != expected.pixels[i] {
+            let (x, y) = (i % video::SCREEN_WIDTH, i / video::SCREEN_WIDTH);
+            differences.push(format!("pixel ({}, {}): {} != {}", x, y, actual.pixels[i], expected.pixels[i]));
+        }
+    }
+    differences
+}
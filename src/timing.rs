@@ -0,0 +1,80 @@
+use crate::cpu::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    // Every instruction takes one tick and timers count down every tick.
+    Fixed,
+    // Instructions cost COSMAC VIP machine cycles, timers count down on the
+    // 60 Hz display interrupt and DXYN waits for that interrupt.
+    Vip,
+    // A fixed number of instructions per 60 Hz frame, as used by the
+    // chip-8-database. Timers count down once per frame, and DXYN ends the
+    // frame with the vblank quirk.
+    Tickrate(u32),
+}
+
+// The VIP's 1802 runs at 1.7609 MHz with 8 clocks per machine cycle, which is
+// 3668 machine cycles per 60 Hz frame. The CDP1861 display DMA steals one
+// cycle per byte for 128 scanlines of 8 bytes, and the interrupt routine that
+// counts down the timers takes a few dozen more.
+pub const CYCLES_PER_FRAME: u32 = 3668;
+pub const INTERRUPT_CYCLES: u32 = 128 * 8 + 46;
+pub const INTERPRETER_CYCLES_PER_FRAME: u32 = CYCLES_PER_FRAME - INTERRUPT_CYCLES;
+pub const FRAMES_PER_SECOND: u32 = 60;
+// Instructions per 60 Hz frame with fixed timing for frontends that run a
+// frame at a time, the 300 per second of the window.
+pub const FIXED_TICKS_PER_FRAME: u32 = 5;
+// Bounds a frame of a program that never reaches the display interrupt.
+pub const MAX_TICKS_PER_FRAME: u32 = 100_000;
+
+// The interpreter's fetch and dispatch loop, paid by every instruction.
+const FETCH_CYCLES: u32 = 40;
+
+// Approximate machine cycles per instruction on the VIP interpreter, based
+// on published measurements of its execution times. `skipped` is whether a
+// conditional skip was taken. DXYN is costed separately by `draw_cycles`.
+pub fn vip_cycles(instr: &Instruction, skipped: bool) -> u32 {
+    let skip = if skipped { 4 } else { 0 };
+    let execute = match instr {
+        Instruction::Invalid(_) => 10,
+        Instruction::ClearScreen => 24,
+        Instruction::Return => 10,
+        Instruction::Jump(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::VxNNSkip(..) | Instruction::VxNNNotSkip(..) => 10 + skip,
+        Instruction::VxVySkip(..) | Instruction::VxNotVySkip(..) => 14 + skip,
+        Instruction::SetRegister(..) => 6,
+        Instruction::AddRegister(..) => 10,
+        Instruction::VxSetVy(..)
+        | Instruction::VxBitOrVy(..)
+        | Instruction::VxBitAndVy(..)
+        | Instruction::VxBitXOrVy(..)
+        | Instruction::VxBitAddVy(..)
+        | Instruction::VxSubVy(..)
+        | Instruction::VxBitShiftRVy(..)
+        | Instruction::VxMinusVy(..)
+        | Instruction::VxBitShiftLVy(..) => 44,
+        Instruction::SetIndexRegister(_) => 12,
+        Instruction::V0Jump(_) => 22,
+        Instruction::VxRand(..) => 36,
+        Instruction::Draw(..) => 0,
+        Instruction::KeyVxSkip(_) | Instruction::KeyNotVxSkip(_) => 14 + skip,
+        Instruction::SetTimerVx(_)
+        | Instruction::GetTimerVx(_)
+        | Instruction::SetSoundTimerVx(_) => 10,
+        Instruction::GetKeyVx(_) => 19,
+        Instruction::AddIVx(_) => 16,
+        Instruction::SetISprite(_) | Instruction::SetILargeSprite(_) => 16,
+        Instruction::BCDVX(_) => 204,
+        Instruction::RegDump(x) | Instruction::RegLoad(x) => 14 + 14 * (*x as u32 + 1),
+    };
+    FETCH_CYCLES + execute
+}
+
+// Each sprite row is shifted into place one bit at a time, and rows that are
+// not byte aligned straddle two display bytes.
+pub fn draw_cycles(x: u8, n: u16) -> u32 {
+    let shift = (x % 8) as u32;
+    let per_row = 24 + shift * 8 + if shift != 0 { 12 } else { 0 };
+    46 + n as u32 * per_row
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Instruction;
+    use crate::platform::Quirks;
+    use crate::system::System;
+    use crate::timing::{self, Timing};
+
+    fn vip_system(program: &[u8]) -> System {
+        let mut emu = System::new();
+        emu.load_test(program.to_vec());
+        emu.set_timing(Timing::Vip);
+        emu
+    }
+
+    #[test]
+    fn fixed_timing_counts_down_every_tick() {
+        // 6005 F015 1204: set the delay timer to 5 and spin
+        let mut emu = System::new();
+        emu.load_test(vec![0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        for _ in 0..4 {
+            emu.tick();
+            assert!(emu.end_of_frame());
+        }
+        assert_eq!(emu.test_state().delay_timer, 2);
+    }
+
+    #[test]
+    fn vip_timers_count_down_once_per_frame() {
+        let mut emu = vip_system(&[0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        emu.tick();
+        emu.tick();
+        let mut frames = 0;
+        let mut ticks = 0;
+        while frames < 2 {
+            emu.tick();
+            ticks += 1;
+            if emu.end_of_frame() {
+                frames += 1;
+            }
+        }
+        assert_eq!(emu.test_state().delay_timer, 3);
+
+        // 1NNN costs the same every time, so a frame holds a fixed number of jumps
+        let jump = timing::vip_cycles(&Instruction::Jump(0x204), false);
+        let per_frame = timing::INTERPRETER_CYCLES_PER_FRAME / jump;
+        assert!(ticks >= per_frame as usize && ticks <= 2 * per_frame as usize + 2);
+    }
+
+    #[test]
+    fn vip_draw_waits_for_the_interrupt() {
+        // 6101 F115 D101: one row of the font at (V1, V0) = (1, 0)
+        let mut emu = vip_system(&[0x61, 0x01, 0xF1, 0x15, 0xD1, 0x01]);
+        emu.tick();
+        emu.tick();
+        assert!(!emu.end_of_frame());
+        emu.tick();
+        assert!(emu.end_of_frame());
+        assert_eq!(emu.test_state().delay_timer, 0);
+        assert_eq!(
+            emu.frame_cycles(),
+            timing::vip_cycles(&Instruction::Draw(1, 0, 1), false) + timing::draw_cycles(1, 1)
+        );
+    }
+
+    #[test]
+    fn draw_cost_grows_with_height_and_misalignment() {
+        assert!(timing::draw_cycles(0, 2) > timing::draw_cycles(0, 1));
+        assert!(timing::draw_cycles(3, 5) > timing::draw_cycles(0, 5));
+        assert!(timing::draw_cycles(7, 5) > timing::draw_cycles(3, 5));
+        assert_eq!(timing::draw_cycles(8, 5), timing::draw_cycles(0, 5));
+    }
+
+    #[test]
+    fn vip_skip_costs_more_when_taken() {
+        let instr = Instruction::VxNNSkip(0, 0);
+        assert!(timing::vip_cycles(&instr, true) > timing::vip_cycles(&instr, false));
+    }
+
+    #[test]
+    fn tickrate_runs_a_fixed_number_of_instructions_per_frame() {
+        let mut emu = System::new();
+        emu.load_test(vec![0x60, 0x05, 0xF0, 0x15, 0x12, 0x04]);
+        emu.set_timing(Timing::Tickrate(4));
+        let mut ends = Vec::new();
+        for _ in 0..8 {
+            emu.tick();
+            ends.push(emu.end_of_frame());
+        }
+        assert_eq!(ends, [false, false, false, true, false, false, false, true]);
+        assert_eq!(emu.test_state().delay_timer, 3);
+    }
+
+    #[test]
+    fn tickrate_draw_ends_the_frame_with_vblank_quirk() {
+        // 6101 D101 1202
+        let program = vec![0x61, 0x01, 0xD1, 0x01, 0x12, 0x02];
+        for (vblank, ends) in [(false, false), (true, true)] {
+            let mut emu = System::new();
+            emu.load_test(program.clone());
+            emu.set_quirks(Quirks { vblank, ..Quirks::DEFAULT });
+            emu.set_timing(Timing::Tickrate(10));
+            emu.tick();
+            emu.tick();
+            assert_eq!(emu.end_of_frame(), ends);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use sdl2::pixels::Color;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::keypad;
+use crate::system::System;
+use crate::timing;
+use crate::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// A frontend for the terminal, for machines without a display, e.g. over
+// SSH. The display takes 64x16 character cells, each showing two pixels
+// with the upper half block in the colour of the top pixel on the colour of
+// the bottom one. Registers are shown to the right.
+
+// Most terminals only report presses and autorepeats, so without release
+// events a key counts as held until this long after its last one.
+pub const HOLD: Duration = Duration::from_millis(300);
+// The registers are redrawn at 10 Hz when the display does not change.
+const PANEL_REFRESH_FRAMES: u32 = 6;
+// Stack faults and blocked or self-modifying writes stay in the panel for
+// two seconds.
+const MESSAGE_FRAMES: u32 = 120;
+
+const UPPER_HALF: char = '\u{2580}';
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Buzzer {
+    // Rings the terminal bell each time the sound starts.
+    Bell,
+    // Shows BUZZ in the panel while the sound plays.
+    Visual,
+}
+
+// One line per pair of pixel rows. Colour codes are only written where the
+// colours change.
+pub fn render_screen(pixels: &[u8], off: Color, on: Color) -> Vec<String> {
+    let color = |pixel: u8| if pixel == 0 { off } else { on };
+    (0..SCREEN_HEIGHT / 2)
+        .map(|row| {
+            let mut line = String::new();
+            let mut last = None;
+            for x in 0..SCREEN_WIDTH {
+                let top = color(pixels[row * 2 * SCREEN_WIDTH + x]);
+                let bottom = color(pixels[(row * 2 + 1) * SCREEN_WIDTH + x]);
+                if last != Some((top, bottom)) {
+                    let _ = write!(
+                        line,
+                        "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                        top.r, top.g, top.b, bottom.r, bottom.g, bottom.b
+                    );
+                    last = Some((top, bottom));
+                }
+                line.push(UPPER_HALF);
+            }
+            line.push_str(RESET);
+            line
+        })
+        .collect()
+}
+
+pub fn render_panel(emu: &System, buzzing: bool, message: Option<&str>) -> Vec<String> {
+    let cpu = emu.cpu();
+    let (delay, sound) = emu.timers();
+    let mut lines = vec![
+        format!("PC {:03X}   I {:03X}", cpu.pc(), cpu.index()),
+        format!("SP {:X}     DT {:02X}  ST {:02X}", cpu.sp(), delay, sound),
+        String::new(),
+    ];
+    for i in 0..8 {
+        lines.push(format!("V{:X} {:02X}   V{:X} {:02X}", i, cpu.get_v(i), i + 8, cpu.get_v(i + 8)));
+    }
+    lines.push(String::new());
+    lines.push(if buzzing { format!("\x1b[7m BUZZ {}", RESET) } else { String::new() });
+    lines.push("Esc quits".to_string());
+    lines.push(String::new());
+    lines.push(message.unwrap_or_default().to_string());
+    lines
+}
+
+// Synthetic releases for terminals that do not report them.
+pub struct HeldKeys {
+    until: [Option<Duration>; 16],
+}
+
+impl HeldKeys {
+    pub fn new() -> HeldKeys {
+        HeldKeys { until: [None; 16] }
+    }
+
+    // Returns whether the key was not already held.
+    pub fn press(&mut self, key: u8, time: Duration) -> bool {
+        let slot = &mut self.until[key as usize & 0xF];
+        let new = slot.is_none();
+        *slot = Some(time + HOLD);
+        new
+    }
+    // The keys to release at `time`.
+    pub fn expire(&mut self, time: Duration) -> Vec<u8> {
+        let mut expired = Vec::new();
+        for (key, slot) in self.until.iter_mut().enumerate() {
+            if slot.is_some_and(|until| until <= time) {
+                *slot = None;
+                expired.push(key as u8);
+            }
+        }
+        expired
+    }
+}
+
+// Puts the terminal back however the frontend exits.
+struct RawTerminal {
+    enhanced: bool,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        let enhanced = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(RawTerminal { enhanced })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if self.enhanced {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(out: &mut impl Write, emu: &System, buzzing: bool, message: Option<&str>) -> io::Result<()> {
+    let (off, on) = emu.video().palette();
+    let screen = render_screen(emu.video().pixels(), off, on);
+    let panel = render_panel(emu, buzzing, message);
+    for row in 0..screen.len().max(panel.len()) {
+        queue!(out, MoveTo(0, row as u16))?;
+        match screen.get(row) {
+            Some(line) => write!(out, "{}", line)?,
+            None => write!(out, "{:1$}", "", SCREEN_WIDTH)?,
+        }
+        write!(out, "  {}", panel.get(row).map_or("", |line| line.as_str()))?;
+        queue!(out, Clear(ClearType::UntilNewLine))?;
+    }
+    out.flush()
+}
+
+// Runs the loaded program until Esc or Ctrl-C. Nothing may be printed while
+// the terminal is raw, so the emulator's own messages are shown in the panel.
+pub fn run(emu: &mut System, buzzer: Buzzer) -> Result<(), String> {
+    emu.set_trace(false);
+    emu.set_log(false);
+    let terminal = RawTerminal::enter().map_err(|e| e.to_string())?;
+    let mut out = io::stdout();
+    let frame_duration = Duration::new(0, 1_000_000_000u32 / timing::FRAMES_PER_SECOND);
+    let start = Instant::now();
+    let mut held = HeldKeys::new();
+    let mut was_buzzing = false;
+    let mut redraw = true;
+    let mut frames_since_draw = 0;
+    let mut message: Option<String> = None;
+    let mut message_frames = 0;
+
+    loop {
+        let frame_start = Instant::now();
+        let now = start.elapsed();
+        while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+            match event::read().map_err(|e| e.to_string())? {
+                Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => return Ok(()),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('c'),
+                    modifiers: KeyModifiers::CONTROL,
+                    ..
+                }) => return Ok(()),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char(c),
+                    kind,
+                    ..
+                }) => {
+                    if let Some(key) = keypad::key_for_char(c) {
+                        match kind {
+                            KeyEventKind::Release => emu.keypad_mut().release(key, now),
+                            _ if terminal.enhanced => emu.keypad_mut().press(key, now),
+                            _ => {
+                                if held.press(key, now) {
+                                    emu.keypad_mut().press(key, now);
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::Resize(..) => {
+                    queue!(out, Clear(ClearType::All)).map_err(|e| e.to_string())?;
+                    redraw = true;
+                }
+                _ => {}
+            }
+        }
+        for key in held.expire(now) {
+            emu.keypad_mut().release(key, now);
+        }
+
+        emu.run_frame();
+        let fault = emu.take_stack_fault().map(|fault| fault.to_string());
+        let alert = emu.take_write_alerts().last().and_then(|event| event.alert());
+        if let Some(text) = fault.or(alert) {
+            message = Some(text);
+            message_frames = MESSAGE_FRAMES;
+            redraw = true;
+        } else if message_frames > 0 {
+            message_frames -= 1;
+            if message_frames == 0 {
+                message = None;
+                redraw = true;
+            }
+        }
+
+        let buzzing = emu.should_play_sound();
+        if buzzing && !was_buzzing && buzzer == Buzzer::Bell {
+            write!(out, "\x07").map_err(|e| e.to_string())?;
+        }
+        frames_since_draw += 1;
+        if emu.should_draw() || redraw || buzzing != was_buzzing || frames_since_draw >= PANEL_REFRESH_FRAMES {
+            draw(&mut out, emu, buzzing && buzzer == Buzzer::Visual, message.as_deref()).map_err(|e| e.to_string())?;
+            emu.mark_drawn();
+            redraw = false;
+            frames_since_draw = 0;
+        }
+        was_buzzing = buzzing;
+
+        if let Some(rest) = frame_duration.checked_sub(frame_start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+}
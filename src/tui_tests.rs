@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use crate::system::System;
+    use crate::tui::{render_panel, render_screen, HeldKeys, HOLD};
+    use crate::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use sdl2::pixels::Color;
+    use std::time::Duration;
+
+    #[test]
+    fn two_pixel_rows_share_a_line() {
+        let mut pixels = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        pixels[0] = 1;
+        pixels[SCREEN_WIDTH + 1] = 1;
+        let lines = render_screen(&pixels, Color::BLACK, Color::RGB(0, 255, 0));
+        assert_eq!(lines.len(), SCREEN_HEIGHT / 2);
+
+        let on_off = "\x1b[38;2;0;255;0m\x1b[48;2;0;0;0m\u{2580}";
+        let off_on = "\x1b[38;2;0;0;0m\x1b[48;2;0;255;0m\u{2580}";
+        let off_off = "\x1b[38;2;0;0;0m\x1b[48;2;0;0;0m\u{2580}";
+        assert!(lines[0].starts_with(&format!("{}{}{}", on_off, off_on, off_off)));
+        assert!(lines[0].ends_with("\u{2580}\x1b[0m"));
+        assert_eq!(lines[0].matches('\u{2580}').count(), SCREEN_WIDTH);
+        // the colours are set once for a blank line
+        assert_eq!(lines[1].matches('\x1b').count(), 3);
+    }
+
+    #[test]
+    fn keys_without_release_events_are_held_for_a_while() {
+        let mut held = HeldKeys::new();
+        let ms = Duration::from_millis;
+        assert!(held.press(5, ms(0)));
+        // an autorepeat keeps it held
+        assert!(!held.press(5, ms(100)));
+        assert!(held.expire(ms(100) + HOLD - ms(1)).is_empty());
+        assert_eq!(held.expire(ms(100) + HOLD), vec![5]);
+        assert!(held.press(5, ms(1000)));
+    }
+
+    #[test]
+    fn the_panel_shows_registers_and_the_buzzer() {
+        // V3 = 7, sound timer = V3
+        let mut emu = System::new();
+        emu.set_trace(false);
+        emu.load_rom(vec![0x63, 0x07, 0xF3, 0x18]).unwrap();
+        emu.tick();
+        emu.tick();
+        let panel = render_panel(&emu, true, None);
+        assert_eq!(panel[0], "PC 204   I 000");
+        assert!(panel.iter().any(|line| line == "V3 07   VB 00"));
+        assert!(panel.iter().any(|line| line.contains("BUZZ")));
+        assert!(!render_panel(&emu, false, None).iter().any(|line| line.contains("BUZZ")));
+        let panel = render_panel(&emu, false, Some("Stack underflow at 0x204"));
+        assert_eq!(panel.last().unwrap(), "Stack underflow at 0x204");
+    }
+}
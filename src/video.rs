@@ -2,10 +2,7 @@ extern crate sdl2;
 
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
-
-use crate::mem;
+use sdl2::render::{Canvas, RenderTarget};
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
@@ -13,31 +10,49 @@ pub const PIXEL_SIZE: u32 = 16;
 
 pub struct Video {
     pixels: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // Sprites wrap around the edges, otherwise they are clipped. The start
+    // position always wraps.
+    wrap: bool,
+    // Colours of unlit and lit pixels.
+    palette: (Color, Color),
 }
 
 impl Video {
     pub fn new() -> Video {
         Video {
             pixels: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            wrap: true,
+            palette: (Color::BLACK, Color::WHITE),
         }
     }
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+    pub fn set_palette(&mut self, off: Color, on: Color) {
+        self.palette = (off, on);
+    }
+    pub fn palette(&self) -> (Color, Color) {
+        self.palette
+    }
     pub fn clear(&mut self) {
         for i in 0..self.pixels.len() {
             self.pixels[i] = 0;
         }
     }
 
-    pub fn draw_sprite(&mut self, x: u8, y: u8, n: u16, i: u16, mem: &mem::Memory) -> bool {
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
         let mut flipped = false;
-        let x = x as usize;
-        let y = y as usize;
+        let x = x as usize % SCREEN_WIDTH;
+        let y = y as usize % SCREEN_HEIGHT;
 
-        for yline in 0..n {
-            let pixel = mem.get_byte(i + yline);
+        for (yline, pixel) in sprite.iter().enumerate() {
             for xline in 0..8 {
                 if (pixel & (0x80 >> xline)) != 0 {
+                    if !self.wrap && (x + xline >= SCREEN_WIDTH || y + yline >= SCREEN_HEIGHT) {
+                        continue;
+                    }
                     let x = (x + xline) % SCREEN_WIDTH;
-                    let y = (y + yline as usize) % SCREEN_HEIGHT;
+                    let y = (y + yline) % SCREEN_HEIGHT;
                     if self.pixels[x + y * SCREEN_WIDTH] == 1 {
                         flipped = true
                     }
@@ -48,12 +63,12 @@ impl Video {
 
         flipped
     }
-    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) {
         for x in 0..SCREEN_WIDTH {
             for y in 0..SCREEN_HEIGHT {
                 let color = match self.pixels[y * SCREEN_WIDTH + x] {
-                    0 => Color::BLACK,
-                    _ => Color::WHITE,
+                    0 => self.palette.0,
+                    _ => self.palette.1,
                 };
 
                 canvas.set_draw_color(color);
@@ -68,4 +83,11 @@ impl Video {
             }
         }
     }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+    pub fn set_pixels(&mut self, pixels: &[u8]) {
+        self.pixels.copy_from_slice(pixels);
+    }
 }
@@ -0,0 +1,68 @@
+use std::fs;
+use std::time::{Duration, Instant, SystemTime};
+
+// How often the file is looked at.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Modification time and size, which together tell that a file was rewritten.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &str) -> Stamp {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+// Notices when a ROM file changes on disk, e.g. because an assembler wrote a
+// new build. A change is only reported once the file has looked the same on
+// two polls in a row, so a file still being written is not loaded half done.
+pub struct RomWatcher {
+    path: String,
+    loaded: Stamp,
+    pending: Option<Stamp>,
+    last_poll: Option<Instant>,
+}
+
+impl RomWatcher {
+    // Watches `path` for changes from how it is now.
+    pub fn new(path: &str) -> RomWatcher {
+        RomWatcher {
+            path: path.to_string(),
+            loaded: stamp(path),
+            pending: None,
+            last_poll: None,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Checks the file at most once per POLL_INTERVAL.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last_poll {
+            if now.duration_since(last) < POLL_INTERVAL {
+                return false;
+            }
+        }
+        self.last_poll = Some(now);
+        self.check()
+    }
+
+    // Whether the file changed and has settled since the last change was
+    // reported. A deleted file is a change too, so the reload can fail
+    // visibly, and its reappearance is another.
+    pub fn check(&mut self) -> bool {
+        let current = stamp(&self.path);
+        if current == self.loaded {
+            self.pending = None;
+            return false;
+        }
+        if self.pending == Some(current) {
+            self.loaded = current;
+            self.pending = None;
+            return true;
+        }
+        self.pending = Some(current);
+        false
+    }
+}
@@ -0,0 +1,49 @@
+#[cfg(test)]
+mod tests {
+    use crate::watch::{RomWatcher, POLL_INTERVAL};
+    use std::fs;
+    use std::time::{Duration, Instant};
+
+    fn rom_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("chip8_watch_{}_{}.ch8", name, std::process::id()));
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn reports_a_change_once_it_has_settled() {
+        let path = rom_path("settle");
+        let mut watcher = RomWatcher::new(&path);
+        assert_eq!(watcher.path(), path);
+        assert!(!watcher.check());
+
+        fs::write(&path, [0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert!(!watcher.check());
+        assert!(watcher.check());
+        assert!(!watcher.check());
+
+        // still being written: each poll sees a different file
+        fs::write(&path, [0x60, 0x01]).unwrap();
+        assert!(!watcher.check());
+        fs::write(&path, [0x60, 0x01, 0x12, 0x02, 0x00, 0xE0]).unwrap();
+        assert!(!watcher.check());
+        assert!(watcher.check());
+
+        fs::remove_file(&path).unwrap();
+        assert!(!watcher.check());
+        assert!(watcher.check());
+        assert!(!watcher.check());
+    }
+
+    #[test]
+    fn polls_at_most_once_per_interval() {
+        let path = rom_path("poll");
+        let mut watcher = RomWatcher::new(&path);
+        let start = Instant::now();
+        fs::write(&path, [0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert!(!watcher.poll(start));
+        assert!(!watcher.poll(start + Duration::from_millis(10)));
+        assert!(watcher.poll(start + POLL_INTERVAL));
+        fs::remove_file(&path).unwrap();
+    }
+}